@@ -1,22 +1,52 @@
 use core::hint::black_box;
 use criterion::{Criterion, criterion_group, criterion_main};
-use mcts::{Goal, NimState, Player, play_mcts};
-use rand::{SeedableRng, rngs::StdRng};
+use sliding_tree::{Game, Search};
 
-#[path = "../tests/mcts.rs"]
-mod mcts;
+/// Nim where the player who takes the last object wins.
+#[derive(Clone)]
+struct Nim {
+    piles: [u8; 3],
+    player: u8,
+}
+
+impl Game for Nim {
+    type Move = (usize, u8);
+    type Player = u8;
+
+    fn player_to_move(&self) -> u8 {
+        self.player
+    }
+
+    fn legal_moves(&self, moves: &mut Vec<(usize, u8)>) {
+        for (i, &pile) in self.piles.iter().enumerate() {
+            moves.extend((1..=pile).map(|take| (i, take)));
+        }
+    }
+
+    fn apply(&mut self, &(i, take): &(usize, u8)) {
+        self.piles[i] -= take;
+        self.player = 1 - self.player;
+    }
+
+    fn terminal_value(&self, player: u8) -> Option<f32> {
+        let over = self.piles.iter().all(|&pile| pile == 0);
+        over.then_some(if player == self.player { 0.0 } else { 1.0 })
+    }
+}
 
 fn benchmark_mcts(c: &mut Criterion) {
     c.bench_function("mcts_nim", |b| {
         b.iter(|| {
-            let initial_state = NimState::new([30, 40, 50], Player::Player1);
-            let mut rng = StdRng::seed_from_u64(12345);
-            play_mcts(
-                black_box(initial_state),
-                black_box(1000),
-                black_box(Goal::TakeLast),
-                black_box(&mut rng),
-            );
+            let nim = Nim {
+                piles: [30, 40, 50],
+                player: 0,
+            };
+            let mut search = Search::new(black_box(nim));
+            while !search.is_terminal() {
+                search.run(black_box(1000));
+                search.play_best();
+            }
+            search.state().player
         })
     });
 }
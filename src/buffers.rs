@@ -1,17 +1,21 @@
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::marker::PhantomData;
 use core::{
     cell::{RefCell, RefMut},
-    cmp::{self, Reverse},
-    ops::Range,
+    cmp,
+    mem::MaybeUninit,
+    ptr::{self, NonNull},
     slice,
 };
 
-extern crate alloc;
-use alloc::collections::VecDeque;
-use alloc::vec::Vec;
-use smallvec::SmallVec;
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+use crate::mmap::MmapOptions;
+use crate::storage::{AllocError, Records, Region, Storage};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct Generation(usize);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct Generation(usize);
 
 impl Generation {
     const FIRST: Generation = Generation(0);
@@ -48,54 +52,131 @@ impl From<Generation> for GenerationSpan {
 }
 
 struct Buffer<T> {
-    vec: Vec<T>,
-    ptr_range: Range<*const T>,
+    ptr: NonNull<T>,
+    len: usize,
+    capacity: usize,
     generation: GenerationSpan,
+    marked: bool,
 }
 
+// SAFETY: A buffer uniquely owns its elements, like a `Vec`, so it can be
+// sent to another thread whenever they can.
+unsafe impl<T: Send> Send for Buffer<T> {}
+
 impl<T> Buffer<T> {
-    fn new(capacity: usize, generation: GenerationSpan) -> Buffer<T> {
-        let vec = Vec::<T>::with_capacity(capacity);
-        let start = vec.as_ptr();
-        // SAFETY: The pointer `end` is never dereferenced and the memory
-        // between `start` and `end` is part of the same allocation.
-        let end = unsafe { start.add(vec.capacity()) };
-        Buffer {
-            vec,
-            ptr_range: start..end,
+    fn new(
+        storage: &mut Storage,
+        capacity: usize,
+        generation: GenerationSpan,
+    ) -> Result<Buffer<T>, AllocError> {
+        let capacity = if size_of::<T>() == 0 {
+            usize::MAX
+        } else {
+            capacity
+        };
+        Ok(Buffer {
+            ptr: storage.allocate(capacity)?,
+            len: 0,
+            capacity,
             generation,
-        }
+            marked: false,
+        })
     }
 
     fn clear(&mut self) {
-        self.vec.clear();
+        let len = self.len;
+        self.len = 0;
         self.generation = GenerationSpan::INVALID;
+        self.marked = false;
+        // SAFETY: The first `len` elements were initialised and the length is
+        // reset beforehand so that they cannot be dropped twice.
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.ptr.as_ptr(),
+                len,
+            ));
+        }
+    }
+
+    /// Drops the contents of the buffer and lets `storage` reclaim its memory
+    /// while keeping it available for reuse.
+    fn recycle(&mut self, storage: &mut Storage) {
+        self.clear();
+        // SAFETY: The memory was allocated from `storage` with this capacity
+        // and has just been cleared.
+        unsafe { storage.release(self.ptr, self.capacity) };
+    }
+
+    /// Drops the contents of the buffer and returns its memory to `storage`.
+    fn free(mut self, storage: &mut Storage) {
+        self.clear();
+        // SAFETY: The memory was allocated from `storage` with this capacity.
+        unsafe { storage.deallocate(self.ptr, self.capacity) };
     }
 
     #[inline]
     fn is_full(&self) -> bool {
-        self.vec.len() == self.vec.capacity()
+        self.len == self.capacity
+    }
+
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.capacity - self.len
+    }
+
+    #[inline]
+    fn push(&mut self, value: T) {
+        debug_assert!(!self.is_full());
+        // SAFETY: The slot at `len` is within the buffer and uninitialised.
+        unsafe { self.ptr.as_ptr().add(self.len).write(value) };
+        self.len += 1;
+    }
+
+    /// Moves the elements from `start` onwards in `other` to the end of this
+    /// buffer.
+    fn append_from(&mut self, other: &mut Buffer<T>, start: usize) {
+        let count = other.len - start;
+        debug_assert!(count <= self.remaining());
+        // SAFETY: The source elements are initialised and the destination
+        // slots are uninitialised. Truncating `other` transfers ownership.
+        unsafe {
+            ptr::copy_nonoverlapping(
+                other.ptr.as_ptr().add(start),
+                self.ptr.as_ptr().add(self.len),
+                count,
+            );
+        }
+        other.len = start;
+        self.len += count;
     }
 
     #[inline]
     fn contains(&self, ptr: *const T) -> bool {
-        self.ptr_range.contains(&ptr)
+        let start = self.ptr.as_ptr() as *const T;
+        (start..start.wrapping_add(self.capacity)).contains(&ptr)
     }
 }
 
 struct SlidingBuffersState<T> {
+    storage: Storage,
     capacity: usize,
-    finished: VecDeque<Buffer<T>>,
-    current: SmallVec<[Buffer<T>; 4]>,
-    recycle: Vec<Buffer<T>>,
+    finished: Records<Buffer<T>>,
+    current: Records<Buffer<T>>,
+    recycle: Records<Buffer<T>>,
     current_generation: Generation,
     depth: usize,
     flat_allocation: bool,
 }
 
 impl<T> SlidingBuffersState<T> {
-    fn take_current_buffer(&mut self, required: usize) -> Buffer<T> {
+    fn take_current_buffer(
+        &mut self,
+        required: usize,
+    ) -> Result<Buffer<T>, AllocError> {
         if required > self.capacity {
+            if !self.storage.is_growable() {
+                return Err(AllocError);
+            }
             // Increase the size of future buffers. Older smaller buffers
             // will eventually be freed rather than recycled.
             self.capacity = cmp::max(required, self.capacity * 2);
@@ -111,9 +192,9 @@ impl<T> SlidingBuffersState<T> {
         loop {
             let buf = match self.current.pop() {
                 Some(buf) => {
-                    if buf.vec.capacity() - buf.vec.len() < required {
+                    if buf.remaining() < required {
                         // The buffer is too small.
-                        self.finished.push_back(buf);
+                        self.finished.push(buf);
                         continue;
                     }
                     buf
@@ -132,20 +213,22 @@ impl<T> SlidingBuffersState<T> {
                         }
                         None => {
                             // No free buffers, allocate a new one.
-                            Buffer::new(
+                            match Buffer::new(
+                                &mut self.storage,
                                 self.capacity,
                                 self.current_generation.into(),
-                            )
+                            ) {
+                                Ok(buf) => buf,
+                                Err(err) => {
+                                    self.exit_scope();
+                                    return Err(err);
+                                }
+                            }
                         }
                     }
                 }
             };
-            debug_assert_eq!(buf.vec.as_ptr(), buf.ptr_range.start);
-            debug_assert_eq!(
-                unsafe { buf.vec.as_ptr().add(buf.vec.capacity()) },
-                buf.ptr_range.end
-            );
-            return buf;
+            return Ok(buf);
         }
     }
 
@@ -154,58 +237,189 @@ impl<T> SlidingBuffersState<T> {
         buf: Buffer<T>,
         start_offset: usize,
         remaining_lower_bound: usize,
-    ) -> (Buffer<T>, usize) {
-        let required = buf.vec.len() - start_offset + 1 + remaining_lower_bound;
+    ) -> Result<(Buffer<T>, usize), AllocError> {
+        let required = buf.len - start_offset + 1 + remaining_lower_bound;
 
         // Put the old buffer back first to preserve order.
         self.depth -= 1;
         let old_buf_idx = self.finished.len();
-        self.finished.push_back(buf);
+        self.finished.push(buf);
 
-        // Get a new buffer.
-        let mut new_buf = self.take_current_buffer(required);
-        let new_start_offset = new_buf.vec.len();
+        // Get a new buffer. On failure, the already iterated nodes are left
+        // in the old buffer and dropped when it is recycled.
+        let mut new_buf = self.take_current_buffer(required)?;
+        let new_start_offset = new_buf.len;
 
-        // Index is still valid because only push_back() is called.
-        let old_buf = &mut self.finished[old_buf_idx];
+        // Index is still valid because only push() is called.
+        let old_buf = &mut self.finished.as_mut_slice()[old_buf_idx];
 
         // Copy already iterated nodes to the new buffer.
-        new_buf.vec.extend(old_buf.vec.drain(start_offset..));
-        (new_buf, new_start_offset)
+        new_buf.append_from(old_buf, start_offset);
+        Ok((new_buf, new_start_offset))
     }
 
-    fn put_back(&mut self, mut buf: Buffer<T>) {
-        debug_assert_eq!(buf.vec.as_ptr(), buf.ptr_range.start);
+    #[inline]
+    fn exit_scope(&mut self) {
+        self.depth -= 1;
+        self.flat_allocation |= self.depth == 0;
+    }
 
+    fn put_back(&mut self, mut buf: Buffer<T>) {
         // Extend the range of the buffer to the current generation.
         buf.generation.end = self.current_generation;
 
-        // Exit scope.
-        self.depth -= 1;
-        self.flat_allocation |= self.depth == 0;
+        self.exit_scope();
 
         // Put the buffer back.
         if buf.is_full() {
-            self.finished.push_back(buf);
+            self.finished.push(buf);
         } else {
             self.current.push(buf);
         }
 
         // Sort buffers so that the most filled buffer will be used first.
-        self.current
-            .sort_by_key(|buf| Reverse(buf.vec.capacity() - buf.vec.len()));
+        // This is a stable insertion sort as there are only a few buffers.
+        let current = self.current.as_mut_slice();
+        for i in 1..current.len() {
+            let mut j = i;
+            while j > 0 && current[j - 1].remaining() < current[j].remaining() {
+                current.swap(j - 1, j);
+                j -= 1;
+            }
+        }
     }
 
-    fn find_generation(&self, ptr: *const T) -> GenerationSpan {
+    fn recycle_buffer(&mut self, mut buf: Buffer<T>) {
+        if buf.capacity >= self.capacity {
+            buf.recycle(&mut self.storage);
+            self.recycle.push(buf);
+        } else {
+            buf.free(&mut self.storage);
+        }
+    }
+
+    /// Recycles the finished and current buffers matching `pred`.
+    fn recycle_where<F>(&mut self, mut pred: F)
+    where
+        F: FnMut(&Buffer<T>) -> bool,
+    {
+        for finished in [true, false] {
+            let mut i = 0;
+            loop {
+                let list = if finished {
+                    &mut self.finished
+                } else {
+                    &mut self.current
+                };
+                let Some(buf) = list.as_slice().get(i) else {
+                    break;
+                };
+                if pred(buf) {
+                    let buf = list.remove(i);
+                    self.recycle_buffer(buf);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Recycles the finished buffers at the front of the queue which are
+    /// older than `generation`.
+    fn recycle_older_than(&mut self, generation: GenerationSpan) {
+        while let Some(peek_buf) = self.finished.as_slice().first() {
+            if peek_buf.generation.is_older_than(generation) {
+                let buf = self.finished.pop_front().unwrap();
+                self.recycle_buffer(buf);
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn try_find_generation(&self, ptr: *const T) -> Option<GenerationSpan> {
         self.finished
+            .as_slice()
             .iter()
-            .chain(self.current.iter())
+            .chain(self.current.as_slice())
             .find(|buf| buf.contains(ptr))
             .map(|buf| buf.generation)
+    }
+
+    fn find_generation(&self, ptr: *const T) -> GenerationSpan {
+        self.try_find_generation(ptr)
             .expect("slice not present in this SlidingBuffers")
     }
 }
 
+/// The state of a [`SlidingBuffers`] before [`SlidingBuffers::finish_current`].
+#[cfg(feature = "alloc")]
+pub(crate) struct Checkpoint {
+    generation: Generation,
+    current: usize,
+}
+
+/// The buffers of a [`SlidingBuffers`] sorted by address, with their
+/// generations and a mark for each one.
+#[cfg(feature = "alloc")]
+pub(crate) struct BufferIndex<T> {
+    ranges: Vec<(usize, usize, usize, GenerationSpan)>,
+    marked: Vec<bool>,
+    phantom: PhantomData<*const T>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T> BufferIndex<T> {
+    /// Returns the position and generations of the buffer containing `ptr`.
+    fn find(&self, ptr: *const T) -> Option<(usize, GenerationSpan)> {
+        let addr = ptr as usize;
+        let after = self.ranges.partition_point(|&(start, ..)| start <= addr);
+        match self.ranges[..after].last() {
+            Some(&(_, end, index, generation)) if addr < end => {
+                Some((index, generation))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the generation in which the buffer containing `ptr` was first
+    /// used, as with [`SlidingBuffers::generation_of`].
+    pub fn generation_of(&self, ptr: *const T) -> Option<Generation> {
+        self.find(ptr).map(|(_, generation)| generation.start)
+    }
+
+    /// Returns the latest generation in which anything was allocated in the
+    /// buffer containing `ptr`.
+    pub fn last_generation_of(&self, ptr: *const T) -> Option<Generation> {
+        self.find(ptr).map(|(_, generation)| generation.end)
+    }
+
+    /// Marks the buffer containing `slice`, or returns false if there is no
+    /// such buffer. An empty `slice` is ignored.
+    pub fn mark(&mut self, slice: &[T]) -> bool {
+        if slice.is_empty() {
+            return true;
+        }
+        match self.find(slice.as_ptr()) {
+            Some((index, _)) => {
+                self.marked[index] = true;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T> Drop for SlidingBuffersState<T> {
+    fn drop(&mut self) {
+        for list in [&mut self.finished, &mut self.current, &mut self.recycle] {
+            while let Some(buf) = list.pop() {
+                buf.free(&mut self.storage);
+            }
+        }
+    }
+}
+
 /// A specialised arena allocator which can recycle memory.
 ///
 /// This allocator allocates slices of `T` from a queue of allocation buffers.
@@ -219,22 +433,29 @@ impl<T> SlidingBuffersState<T> {
 ///
 /// This raison d'être of this type is to support the implementation of
 /// [`crate::SlidingTree`]
+///
+/// A `SlidingBuffers` is [`Send`] if `T` is, as it owns the buffers and all
+/// of the values in them. It is never [`Sync`], since allocating from a
+/// shared reference mutates its state.
 pub struct SlidingBuffers<T> {
     state: RefCell<SlidingBuffersState<T>>,
 }
 
 impl<T> SlidingBuffers<T> {
-    /// Creates a new `SlidingBuffers` with the given capacity.
-    ///
-    /// The `capacity` is the maximum number of elements that can be allocated
-    /// in a single buffer.
-    pub fn with_capacity(capacity: usize) -> SlidingBuffers<T> {
+    fn with_storage(
+        storage: Storage,
+        capacity: usize,
+        finished: Records<Buffer<T>>,
+        current: Records<Buffer<T>>,
+        recycle: Records<Buffer<T>>,
+    ) -> SlidingBuffers<T> {
         SlidingBuffers {
             state: RefCell::new(SlidingBuffersState {
+                storage,
                 capacity,
-                finished: VecDeque::new(),
-                current: SmallVec::new(),
-                recycle: Vec::new(),
+                finished,
+                current,
+                recycle,
                 current_generation: Generation::FIRST,
                 depth: 0,
                 flat_allocation: true,
@@ -242,15 +463,91 @@ impl<T> SlidingBuffers<T> {
         }
     }
 
+    /// Creates a new `SlidingBuffers` with the given capacity.
+    ///
+    /// The `capacity` is the maximum number of elements that can be allocated
+    /// in a single buffer.
+    #[cfg(feature = "alloc")]
+    pub fn with_capacity(capacity: usize) -> SlidingBuffers<T> {
+        SlidingBuffers::with_storage(
+            Storage::Heap,
+            capacity,
+            Records::new(),
+            Records::new(),
+            Records::new(),
+        )
+    }
+
+    /// Creates a new `SlidingBuffers` with the given capacity, where each
+    /// buffer is a separate `mmap` mapping configured by `options`.
+    #[cfg(all(feature = "mmap", target_os = "linux"))]
+    pub fn with_mmap(
+        capacity: usize,
+        options: MmapOptions,
+    ) -> SlidingBuffers<T> {
+        SlidingBuffers::with_storage(
+            Storage::Mmap(options),
+            capacity,
+            Records::new(),
+            Records::new(),
+            Records::new(),
+        )
+    }
+
+    /// Creates a new `SlidingBuffers` which allocates only from `region`.
+    ///
+    /// A small part of the region is reserved for book-keeping and the rest is
+    /// divided equally into `buffer_count` buffers. No heap memory is used.
+    /// The capacity of the buffers is fixed, so allocations fail with an
+    /// [`AllocError`] once every buffer is in use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is too small to hold at least one element in each
+    /// buffer.
+    pub fn with_static_storage(
+        region: &'static mut [MaybeUninit<u8>],
+        buffer_count: usize,
+    ) -> SlidingBuffers<T> {
+        let mut region = Region::new(region);
+        let mut records = |slots| {
+            let ptr = region
+                .take::<Buffer<T>>(slots)
+                .expect("region too small for buffer records");
+            Records::fixed(ptr, slots)
+        };
+        // The finished queue is popped from the front.
+        let finished = records(buffer_count * 2);
+        let (current, recycle) = (records(buffer_count), records(buffer_count));
+        let (storage, capacity) = region.into_storage::<T>(buffer_count);
+        assert!(capacity > 0, "region too small for buffers");
+        SlidingBuffers::with_storage(
+            Storage::Static(storage),
+            capacity,
+            finished,
+            current,
+            recycle,
+        )
+    }
+
     /// Preallocates recycled buffers.
+    ///
+    /// With static storage, this stops early once every buffer has been
+    /// allocated.
     pub fn preallocate(&mut self, required: usize) {
-        let mut cell = self.borrow_mut();
+        let cell = self.state.get_mut();
         let capacity = cell.capacity;
         cell.recycle.reserve(required);
         cell.finished.reserve(required);
         for _ in 0..required {
-            cell.recycle
-                .push(Buffer::new(capacity, GenerationSpan::INVALID))
+            let Ok(buf) = Buffer::new(
+                &mut cell.storage,
+                capacity,
+                GenerationSpan::INVALID,
+            ) else {
+                break;
+            };
+            cell.recycle.push(buf);
         }
     }
 
@@ -293,13 +590,51 @@ impl<T> SlidingBuffers<T> {
     ///
     /// Note that it is legal for the iterator to allocate more slices
     /// recursively from the same `SlidingBuffers`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a buffer cannot be allocated. See [`Self::try_alloc_iter`].
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_iter<I>(&self, iter: I) -> &mut [T]
+    where
+        I: Iterator<Item = T>,
+    {
+        match self.try_alloc_iter(iter) {
+            Ok(slice) => slice,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Populates a newly allocated slice with values from the iterator, or
+    /// returns an error if a buffer cannot be allocated.
+    ///
+    /// If an error is returned, the values already taken from the iterator
+    /// are kept in the buffers until they are recycled. Note that it is legal
+    /// for the iterator to allocate more slices recursively from the same
+    /// `SlidingBuffers`.
     #[allow(clippy::mut_from_ref)]
-    pub fn alloc_iter<I>(&self, mut iter: I) -> &mut [T]
+    pub fn try_alloc_iter<I>(&self, iter: I) -> Result<&mut [T], AllocError>
     where
         I: Iterator<Item = T>,
     {
-        let mut buf = self.borrow_mut().take_current_buffer(iter.size_hint().0);
-        let mut start_offset = buf.vec.len();
+        self.try_alloc_iter_with_room(iter, 0)
+    }
+
+    /// Like [`Self::try_alloc_iter`], but places the slice in a buffer with
+    /// room for at least `room` elements, so that it can then be extended
+    /// with [`Self::grow_in_place`] until something else is allocated.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) fn try_alloc_iter_with_room<I>(
+        &self,
+        mut iter: I,
+        room: usize,
+    ) -> Result<&mut [T], AllocError>
+    where
+        I: Iterator<Item = T>,
+    {
+        let required = cmp::max(iter.size_hint().0, room);
+        let mut buf = self.borrow_mut().take_current_buffer(required)?;
+        let mut start_offset = buf.len;
         loop {
             let (next, lower_bound);
             (next, lower_bound, buf) = self.guarded_iter(&mut iter, buf);
@@ -311,12 +646,12 @@ impl<T> SlidingBuffers<T> {
                     buf,
                     start_offset,
                     lower_bound,
-                );
+                )?;
                 debug_assert!(!buf.is_full());
             }
-            buf.vec.push(value);
+            buf.push(value);
         }
-        // SAFETY: The elements of `buf.vec` are only accessed via the slices
+        // SAFETY: The elements of `buf` are only accessed via the slices
         // created here and these slices do not overlap with each other.
         // Although the slice is mutable, it is minted from a shared `&self`
         // borrow (in the manner of a bump allocator), which is sound because
@@ -325,13 +660,13 @@ impl<T> SlidingBuffers<T> {
         // borrow and so cannot outlive the backing buffer.
         let slice = unsafe {
             slice::from_raw_parts_mut(
-                buf.vec.as_mut_ptr().add(start_offset),
-                buf.vec.len() - start_offset,
+                buf.ptr.as_ptr().add(start_offset),
+                buf.len - start_offset,
             )
         };
         debug_assert!(buf.contains(slice.as_ptr()));
         self.borrow_mut().put_back(buf);
-        slice
+        Ok(slice)
     }
 
     /// Recycles all allocation buffers.
@@ -343,12 +678,13 @@ impl<T> SlidingBuffers<T> {
     /// there are outstanding references may lead to undefined behaviour.
     pub unsafe fn recycle_all(&self) {
         let mut cell = self.borrow_mut();
-        while let Some(mut buf) = cell.finished.pop_front() {
-            buf.clear();
+        let cell = &mut *cell;
+        while let Some(mut buf) = cell.finished.pop() {
+            buf.recycle(&mut cell.storage);
             cell.recycle.push(buf);
         }
         while let Some(mut buf) = cell.current.pop() {
-            buf.clear();
+            buf.recycle(&mut cell.storage);
             cell.recycle.push(buf);
         }
     }
@@ -373,17 +709,140 @@ impl<T> SlidingBuffers<T> {
     pub unsafe fn recycle_older_than(&self, slice: &[T]) {
         let mut cell = self.borrow_mut();
         let generation = cell.find_generation(slice.as_ptr());
-        while let Some(peek_buf) = cell.finished.front_mut() {
-            if peek_buf.generation.is_older_than(generation) {
-                let mut buf = cell.finished.pop_front().unwrap();
-                if buf.vec.capacity() >= cell.capacity {
-                    buf.clear();
-                    cell.recycle.push(buf);
-                }
-            } else {
-                break;
+        cell.recycle_older_than(generation);
+    }
+
+    /// Recycles all allocation buffers older than every one of the supplied
+    /// `slices`.
+    ///
+    /// This generalises [`Self::recycle_older_than`] to several independent
+    /// structures sharing these buffers, each with its own oldest live slice.
+    /// Empty slices are ignored, and nothing is recycled if every slice is
+    /// empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any non-empty slice is not a valid reference to an
+    /// allocation from this `SlidingBuffers`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because it assumes that all existing references
+    /// to allocated slices older than all of the supplied `slices` are no
+    /// longer in use. See [`Self::recycle_older_than`].
+    pub unsafe fn recycle_older_than_all<'s, I>(&self, slices: I)
+    where
+        I: IntoIterator<Item = &'s [T]>,
+        T: 's,
+    {
+        let mut cell = self.borrow_mut();
+        let oldest = slices
+            .into_iter()
+            .filter(|slice| !slice.is_empty())
+            .map(|slice| cell.find_generation(slice.as_ptr()))
+            .min_by_key(|generation| generation.start);
+        if let Some(generation) = oldest {
+            cell.recycle_older_than(generation);
+        }
+    }
+
+    /// Marks the buffer containing `slice` as holding live allocations.
+    ///
+    /// Marks are used by [`Self::recycle_unmarked`] to recycle buffers out of
+    /// order, regardless of their age. An empty `slice` is ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a non-empty `slice` is not a valid reference to an allocation
+    /// from this `SlidingBuffers`.
+    pub fn mark(&self, slice: &[T]) {
+        if slice.is_empty() {
+            return;
+        }
+        let mut cell = self.borrow_mut();
+        let cell = &mut *cell;
+        let buf = cell
+            .finished
+            .as_mut_slice()
+            .iter_mut()
+            .chain(cell.current.as_mut_slice())
+            .find(|buf| buf.contains(slice.as_ptr()))
+            .expect("slice not present in this SlidingBuffers");
+        buf.marked = true;
+    }
+
+    /// Returns an index of the buffers in use by address, for finding the
+    /// buffers holding many slices with a binary search each.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn index(&self) -> BufferIndex<T> {
+        let cell = self.state.borrow();
+        let mut ranges: Vec<_> = cell
+            .finished
+            .as_slice()
+            .iter()
+            .chain(cell.current.as_slice())
+            .enumerate()
+            .map(|(index, buf)| {
+                let start = buf.ptr.as_ptr() as usize;
+                let end = start + buf.capacity * size_of::<T>();
+                (start, end, index, buf.generation)
+            })
+            .collect();
+        ranges.sort_unstable();
+        BufferIndex {
+            marked: vec![false; ranges.len()],
+            ranges,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Recycles all allocation buffers which are not marked in `index`, as
+    /// with [`Self::recycle_unmarked`].
+    ///
+    /// # Safety
+    ///
+    /// As with [`Self::recycle_unmarked`], and no buffers may have been
+    /// allocated since `index` was created.
+    #[cfg(feature = "alloc")]
+    pub(crate) unsafe fn recycle_unmarked_in(&self, index: BufferIndex<T>) {
+        {
+            let mut cell = self.borrow_mut();
+            let cell = &mut *cell;
+            for (buf, marked) in cell
+                .finished
+                .as_mut_slice()
+                .iter_mut()
+                .chain(cell.current.as_mut_slice())
+                .zip(index.marked)
+            {
+                buf.marked |= marked;
             }
         }
+        // SAFETY: Forwarded from the caller.
+        unsafe { self.recycle_unmarked() };
+    }
+
+    /// Recycles all allocation buffers which have not been marked with
+    /// [`Self::mark`] since the last call, and then clears the marks.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because it assumes that all existing references
+    /// to allocated slices in unmarked buffers are no longer in use. Calling
+    /// this function while there are outstanding references may lead to
+    /// undefined behaviour.
+    pub unsafe fn recycle_unmarked(&self) {
+        let mut cell = self.borrow_mut();
+        cell.recycle_where(|buf| !buf.marked);
+        let cell = &mut *cell;
+        for buf in cell
+            .finished
+            .as_mut_slice()
+            .iter_mut()
+            .chain(cell.current.as_mut_slice())
+        {
+            buf.marked = false;
+        }
     }
 
     /// Asserts that `src` can safely reference `dst`.
@@ -417,9 +876,225 @@ impl<T> SlidingBuffers<T> {
         }
     }
 
+    /// Returns true if `src` can safely reference `dst`.
+    ///
+    /// This is the non-panicking counterpart of [`Self::assert_can_reference`]
+    /// and has the same buffer-level granularity.
+    pub(crate) fn can_reference(&self, src: &[T], dst: &[T]) -> bool {
+        if dst.is_empty() {
+            return true;
+        }
+        let cell = self.state.borrow();
+        !cell
+            .find_generation(dst.as_ptr())
+            .is_older_than(cell.find_generation(src.as_ptr()))
+    }
+
+    /// Extends `slice` by one element in place, without a new allocation.
+    ///
+    /// This only succeeds if `slice` ends at the tail of a current buffer
+    /// which is not full, so that no other allocation can follow it.
+    /// Otherwise `value` is returned. An empty `slice` is never extended.
+    pub(crate) fn grow_in_place<'s>(
+        &'s self,
+        slice: &mut &'s mut [T],
+        value: T,
+    ) -> Result<(), T> {
+        let len = slice.len();
+        if len == 0 {
+            return Err(value);
+        }
+        let mut cell = self.borrow_mut();
+        let Some(buf) = cell
+            .current
+            .as_mut_slice()
+            .iter_mut()
+            .find(|buf| buf.contains(slice.as_ptr()))
+        else {
+            return Err(value);
+        };
+        // SAFETY: The slice lies within the buffer.
+        let offset =
+            unsafe { slice.as_ptr().offset_from(buf.ptr.as_ptr()) } as usize;
+        if offset + len != buf.len || buf.is_full() {
+            return Err(value);
+        }
+        buf.push(value);
+        // SAFETY: The slice and the slot following it are initialised and are
+        // only accessed through the new slice, which replaces the old one.
+        *slice = unsafe {
+            slice::from_raw_parts_mut(buf.ptr.as_ptr().add(offset), len + 1)
+        };
+        Ok(())
+    }
+
+    /// Extends the generation of the buffer containing `slice` to the current
+    /// one, as though it had just been allocated from, so that `slice` can be
+    /// referenced from any allocation made so far.
+    ///
+    /// The buffer is then only recycled along with the buffers allocated from
+    /// before this call. An empty `slice` is ignored.
+    pub(crate) fn touch(&self, slice: &[T]) {
+        if slice.is_empty() {
+            return;
+        }
+        let mut cell = self.borrow_mut();
+        let cell = &mut *cell;
+        if let Some(buf) = cell
+            .finished
+            .as_mut_slice()
+            .iter_mut()
+            .chain(cell.current.as_mut_slice())
+            .find(|buf| buf.contains(slice.as_ptr()))
+        {
+            buf.generation.end =
+                cmp::max(buf.generation.end, cell.current_generation);
+        }
+    }
+
     /// Frees unused buffers to reduce memory usage.
+    ///
+    /// This has no effect with static storage, where the buffers are kept for
+    /// reuse.
     pub fn trim(&self) {
-        self.borrow_mut().recycle.clear();
+        let mut cell = self.borrow_mut();
+        if cell.storage.is_growable() {
+            let cell = &mut *cell;
+            while let Some(buf) = cell.recycle.pop() {
+                buf.free(&mut cell.storage);
+            }
+        }
+    }
+
+    /// Moves the partially filled buffers to the finished state, so that
+    /// subsequent allocations are made from fresh buffers.
+    ///
+    /// Returns a checkpoint which [`Self::rollback`] can return to.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn finish_current(&self) -> Checkpoint {
+        let mut cell = self.borrow_mut();
+        let current = cell.current.len();
+        while let Some(buf) = cell.current.pop() {
+            cell.finished.push(buf);
+        }
+        Checkpoint {
+            generation: cell.current_generation,
+            current,
+        }
+    }
+
+    /// Recycles all allocation buffers first used after `checkpoint`, and
+    /// returns the buffers finished by [`Self::finish_current`] to the
+    /// current state.
+    ///
+    /// # Safety
+    ///
+    /// As with [`Self::recycle_older_than`], there must be no outstanding
+    /// references to the allocations in the recycled buffers.
+    #[cfg(feature = "alloc")]
+    pub(crate) unsafe fn rollback(&self, checkpoint: Checkpoint) {
+        let mut cell = self.borrow_mut();
+        cell.recycle_where(|buf| buf.generation.start > checkpoint.generation);
+        // Only newer buffers were pushed after these, so they are still at the
+        // end of the queue, in reverse order.
+        for _ in 0..checkpoint.current {
+            let buf = cell.finished.pop().expect("finished buffer missing");
+            cell.current.push(buf);
+        }
+    }
+
+    /// Returns a function which creates empty `SlidingBuffers` with the same
+    /// capacity and kind of storage as these, for allocating on other threads
+    /// and then passing to [`Self::append`], or `None` with static storage.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn forker(
+        &self,
+    ) -> Option<impl Fn() -> SlidingBuffers<T> + Sync + use<T>> {
+        let cell = self.state.borrow();
+        let storage = cell.storage.fork()?;
+        let capacity = cell.capacity;
+        Some(move || {
+            SlidingBuffers::with_storage(
+                storage.fork().expect("forked storage is not static"),
+                capacity,
+                Records::new(),
+                Records::new(),
+                Records::new(),
+            )
+        })
+    }
+
+    /// Moves the buffers of `other`, which must have been created by a
+    /// function from [`Self::forker`], to the end of the finished queue.
+    ///
+    /// The generations of the appended buffers are renumbered to follow every
+    /// buffer here while keeping their order, so that their allocations can
+    /// be referenced from any slice allocated before this call. During a
+    /// recursive allocation, they instead share the current generation with
+    /// the other buffers used by the recursion.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn append(&self, other: SlidingBuffers<T>) {
+        let mut other = other.state.into_inner();
+        let mut cell = self.borrow_mut();
+        let cell = &mut *cell;
+        let base = cell.current_generation;
+        let renumber = |span: GenerationSpan| {
+            if cell.depth > 0 {
+                // A slice allocated after this point in the recursion may be
+                // moved to a new buffer, which must not be newer than these.
+                debug_assert!(!cell.flat_allocation);
+                GenerationSpan::from(base)
+            } else {
+                GenerationSpan {
+                    start: Generation(base.0 + span.start.0),
+                    end: Generation(base.0 + span.end.0),
+                }
+            }
+        };
+        // The partially filled buffers are finished too, so that the order of
+        // the queue follows the generations and they can be recycled in turn.
+        for list in [&mut other.finished, &mut other.current] {
+            while let Some(mut buf) = list.pop_front() {
+                buf.generation = renumber(buf.generation);
+                cell.finished.push(buf);
+            }
+        }
+        if cell.depth == 0 {
+            cell.current_generation =
+                Generation(base.0 + other.current_generation.0);
+        }
+        cell.capacity = cmp::max(cell.capacity, other.capacity);
+        while let Some(buf) = other.recycle.pop() {
+            if buf.capacity >= cell.capacity {
+                cell.recycle.push(buf);
+            } else {
+                buf.free(&mut cell.storage);
+            }
+        }
+    }
+
+    /// Returns the generation in which the buffer containing `ptr` was first
+    /// used, or `None` if no finished or current buffer contains it.
+    ///
+    /// A recycled buffer starts a new generation when it is reused, so this
+    /// distinguishes between successive uses of the same memory.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn generation_of(&self, ptr: *const T) -> Option<Generation> {
+        let cell = self.state.borrow();
+        cell.try_find_generation(ptr).map(|span| span.start)
+    }
+
+    /// Returns the number of elements held in the finished and current
+    /// buffers.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn allocated(&self) -> usize {
+        let cell = self.state.borrow();
+        cell.finished
+            .as_slice()
+            .iter()
+            .chain(cell.current.as_slice())
+            .map(|buf| buf.len)
+            .sum()
     }
 
     /// Returns the current buffer capacity.
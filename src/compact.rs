@@ -0,0 +1,614 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{
+    fmt::{self, Debug, Formatter},
+    iter::FusedIterator,
+    ptr::NonNull,
+    slice,
+};
+
+use crate::{SlidingBuffers, buffers::Generation};
+
+/// The children of a node in a [`CompactTree`], as the index of the first
+/// child and the number of children.
+#[derive(Clone, Copy)]
+struct Span {
+    offset: u32,
+    len: u32,
+}
+
+impl Span {
+    const EMPTY: Span = Span { offset: 0, len: 0 };
+    // Unexpanded nodes are marked by a length which no span can reach.
+    const UNEXPANDED: Span = Span {
+        offset: 0,
+        len: u32::MAX,
+    };
+
+    fn is_expanded(self) -> bool {
+        self.len != u32::MAX
+    }
+
+    fn len(self) -> usize {
+        if self.is_expanded() {
+            self.len as usize
+        } else {
+            0
+        }
+    }
+}
+
+/// The storage for a node in a [`CompactTree`].
+struct Slot<T> {
+    data: T,
+    children: Span,
+}
+
+/// A contiguous run of slots within one buffer of a [`CompactTree`], which
+/// holds the nodes with consecutive indices from `first`.
+struct Chunk<T> {
+    first: u32,
+    ptr: NonNull<Slot<T>>,
+    len: u32,
+    generation: Generation,
+}
+
+/// A tree that grows from the leaves and recedes from the root, with a
+/// compact index-based node layout.
+///
+/// This is a variant of [`crate::SlidingTree`] for small payloads, where the
+/// 16-byte slice reference held by each [`crate::Node`] would dominate the
+/// memory used. Each node instead refers to its children by a pair of `u32`
+/// values, the index of the first child and the number of children, which
+/// halves the overhead per node on 64-bit targets. As the indices do not
+/// depend on where the nodes are located in memory, the layout is also
+/// position-independent.
+///
+/// The nodes are allocated from [`SlidingBuffers`], in which every group of
+/// children is allocated after its parent, and are numbered in the order in
+/// which they are allocated. Advancing the root therefore leaves the
+/// inaccessible nodes in the oldest buffers, which [`Self::recycle`] reuses
+/// without moving the other nodes. Indices wrap around, and at most
+/// `u32::MAX` nodes can be held at once, including those not yet recycled.
+///
+/// Since a mutable reference to a node borrows the whole tree, the children
+/// of a node are modified one at a time through [`CompactNodeMut::at_mut`],
+/// and groups of siblings cannot be edited in place.
+pub struct CompactTree<T> {
+    buffers: SlidingBuffers<Slot<T>>,
+    // The runs of slots holding the nodes, in the order of their indices.
+    chunks: VecDeque<Chunk<T>>,
+    // The index of the next node to be allocated.
+    next: u32,
+    roots: Span,
+}
+
+// SAFETY: The tree owns its buffers, and the chunks only point into them.
+unsafe impl<T: Send> Send for CompactTree<T> {}
+
+impl<T> CompactTree<T> {
+    /// Creates a new empty `CompactTree` with a default capacity based on the
+    /// size of `T`.
+    pub fn new() -> CompactTree<T> {
+        CompactTree::with_capacity(1000000 / size_of::<Slot<T>>().max(1))
+    }
+
+    /// Creates a new empty `CompactTree` with the specified capacity.
+    ///
+    /// The `capacity` is the maximum number of nodes that can be allocated
+    /// in a single buffer. No memory is allocated until the first nodes are
+    /// added.
+    pub fn with_capacity(capacity: usize) -> CompactTree<T> {
+        CompactTree {
+            buffers: SlidingBuffers::with_capacity(capacity),
+            chunks: VecDeque::new(),
+            next: 0,
+            roots: Span::EMPTY,
+        }
+    }
+
+    /// Returns a pointer to the slot of the node at `index`.
+    fn locate(&self, index: u32) -> NonNull<Slot<T>> {
+        let front = self.chunks.front().expect("node has been recycled").first;
+        let position = index.wrapping_sub(front);
+        let chunk = self
+            .chunks
+            .partition_point(|chunk| {
+                chunk.first.wrapping_sub(front) <= position
+            })
+            .checked_sub(1)
+            .map(|i| &self.chunks[i])
+            .filter(|chunk| index.wrapping_sub(chunk.first) < chunk.len)
+            .expect("node has been recycled");
+        // SAFETY: The index lies within the chunk.
+        unsafe { chunk.ptr.add(index.wrapping_sub(chunk.first) as usize) }
+    }
+
+    fn slot(&self, index: u32) -> &Slot<T> {
+        // SAFETY: The chunks only hold slots which have not been recycled.
+        unsafe { self.locate(index).as_ref() }
+    }
+
+    fn slot_mut(&mut self, index: u32) -> &mut Slot<T> {
+        // SAFETY: As for `slot`, and the tree is borrowed mutably.
+        unsafe { self.locate(index).as_mut() }
+    }
+
+    fn children(&self, span: Span) -> slice::Iter<'_, Slot<T>> {
+        if span.len() == 0 {
+            // The offset of an empty span may not lie in any chunk.
+            return [].iter();
+        }
+        // SAFETY: Each group of children is allocated as one slice, so the
+        // span lies within a single chunk.
+        unsafe {
+            slice::from_raw_parts(self.locate(span.offset).as_ptr(), span.len())
+        }
+        .iter()
+    }
+
+    /// Returns the index of the child at `index` in `span`.
+    fn child(span: Span, index: usize) -> u32 {
+        let len = span.len();
+        assert!(index < len, "index (is {index}) should be < len (is {len})");
+        span.offset.wrapping_add(index as u32)
+    }
+
+    /// Allocates a group of leaves after every other node.
+    fn alloc_iter<I>(&mut self, iterable: I) -> Span
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let slots =
+            self.buffers
+                .alloc_iter(iterable.into_iter().map(|data| Slot {
+                    data,
+                    children: Span::UNEXPANDED,
+                }));
+        if slots.is_empty() {
+            return Span::EMPTY;
+        }
+        let len = slots.len();
+        let ptr = NonNull::from(slots).cast::<Slot<T>>();
+        let generation = self
+            .buffers
+            .generation_of(ptr.as_ptr())
+            .expect("slots not present in the buffers");
+        let first = self.next;
+        let held = self
+            .chunks
+            .front()
+            .map_or(0, |chunk| first.wrapping_sub(chunk.first) as usize);
+        assert!(
+            held + len < u32::MAX as usize,
+            "too many nodes in a CompactTree"
+        );
+        match self.chunks.back_mut() {
+            // The slots follow on from the last run in the same buffer.
+            Some(chunk)
+                if chunk.generation == generation
+                    && chunk.ptr.as_ptr().wrapping_add(chunk.len as usize)
+                        == ptr.as_ptr() =>
+            {
+                chunk.len += len as u32;
+            }
+            _ => self.chunks.push_back(Chunk {
+                first,
+                ptr,
+                len: len as u32,
+                generation,
+            }),
+        }
+        self.next = first.wrapping_add(len as u32);
+        Span {
+            offset: first,
+            len: len as u32,
+        }
+    }
+
+    /// Allocates a group of leaves and then builds their subtrees, so that
+    /// every group of children follows its parent.
+    fn alloc_iter_recursive<I, F, U>(
+        &mut self,
+        iterable: I,
+        mut builder: F,
+    ) -> Span
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(CompactNodeMut<'_, T>, U),
+    {
+        let mut recursions = Vec::new();
+        let span = self.alloc_iter(iterable.into_iter().map(|(data, u)| {
+            recursions.push(u);
+            data
+        }));
+        for (index, recursion) in recursions.into_iter().enumerate() {
+            let node = CompactNodeMut {
+                index: Self::child(span, index),
+                tree: self,
+            };
+            builder(node, recursion);
+        }
+        span
+    }
+
+    /// Returns the number of root nodes.
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Returns true if the tree has no root nodes, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.roots.len() == 0
+    }
+
+    /// Returns an iterator over references to the root nodes.
+    pub fn iter(&self) -> CompactIter<'_, T> {
+        CompactIter {
+            iter: self.children(self.roots),
+            tree: self,
+        }
+    }
+
+    /// Returns a reference to the root node at the given index.
+    pub fn at(&self, index: usize) -> CompactNode<'_, T> {
+        CompactNode {
+            slot: self.slot(Self::child(self.roots, index)),
+            tree: self,
+        }
+    }
+
+    /// Returns a mutable reference to the root node at the given index.
+    pub fn at_mut(&mut self, index: usize) -> CompactNodeMut<'_, T> {
+        CompactNodeMut {
+            index: Self::child(self.roots, index),
+            tree: self,
+        }
+    }
+
+    /// Sets the root nodes using the provided iterable.
+    ///
+    /// Any previous nodes become inaccessible.
+    pub fn set_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.roots = self.alloc_iter(iterable);
+    }
+
+    /// Sets the root nodes using the provided iterable, allowing recursive
+    /// construction of a subtree.
+    ///
+    /// The root nodes are allocated first and then the `builder` function is
+    /// called for each of them in turn. Any previous nodes become
+    /// inaccessible.
+    pub fn set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(CompactNodeMut<'_, T>, U),
+    {
+        self.roots = self.alloc_iter_recursive(iterable, builder);
+    }
+
+    /// Adopts the children of the root node at the given index as the roots.
+    ///
+    /// Any previous roots and the other sets of children and their
+    /// descendents become inaccessible.
+    pub fn adopt_grandchildren_at(&mut self, index: usize) {
+        let index = Self::child(self.roots, index);
+        self.roots = self.slot(index).children;
+    }
+
+    /// Clears the tree, removing the roots, all their descendants, and
+    /// recycling all buffers.
+    pub fn clear(&mut self) {
+        self.roots = Span::EMPTY;
+        self.chunks.clear();
+        // SAFETY: Once the roots have been cleared, previously allocated nodes
+        // are inaccessible and can be recycled.
+        unsafe { self.buffers.recycle_all() };
+    }
+
+    /// Recycles buffers containing nodes that are no longer accessible.
+    pub fn recycle(&mut self) {
+        if self.is_empty() {
+            self.clear();
+            return;
+        }
+        let roots = self.children(self.roots).as_slice();
+        // SAFETY: Every group is allocated after its parent, so nodes
+        // allocated before the roots are inaccessible and can be recycled.
+        unsafe { self.buffers.recycle_older_than(roots) };
+        while let Some(chunk) = self.chunks.front() {
+            if self.buffers.generation_of(chunk.ptr.as_ptr())
+                == Some(chunk.generation)
+            {
+                break;
+            }
+            self.chunks.pop_front();
+        }
+    }
+
+    /// Frees unused buffers to reduce memory usage.
+    pub fn trim(&mut self) {
+        self.buffers.trim();
+    }
+
+    /// Returns the number of nodes that each buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffers.capacity()
+    }
+
+    /// Returns the number of nodes held in the buffers, including those
+    /// which are inaccessible but have not been recycled yet.
+    pub fn allocated(&self) -> usize {
+        self.buffers.allocated()
+    }
+
+    /// Returns the number of buffers in the finished, current, and recycled
+    /// states.
+    pub fn buffer_stats(&self) -> (usize, usize, usize) {
+        self.buffers.buffer_stats()
+    }
+}
+
+impl<T> Debug for CompactTree<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompactTree")
+            .field("roots", &self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<T> Default for CompactTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A reference to a node in a [`CompactTree`].
+pub struct CompactNode<'t, T> {
+    slot: &'t Slot<T>,
+    tree: &'t CompactTree<T>,
+}
+
+impl<T> Clone for CompactNode<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for CompactNode<'_, T> {}
+
+impl<'t, T> CompactNode<'t, T> {
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &'t T {
+        &self.slot.data
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none.
+    pub fn is_expanded(&self) -> bool {
+        self.slot.children.is_expanded()
+    }
+
+    /// Returns the number of child nodes.
+    pub fn len(&self) -> usize {
+        self.slot.children.len()
+    }
+
+    /// Returns true if this node has no children, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.slot.children.len() == 0
+    }
+
+    /// Returns an iterator over references to the child nodes.
+    pub fn iter(&self) -> CompactIter<'t, T> {
+        CompactIter {
+            iter: self.tree.children(self.slot.children),
+            tree: self.tree,
+        }
+    }
+
+    /// Returns a reference to the child node at the given index.
+    pub fn at(&self, index: usize) -> CompactNode<'t, T> {
+        CompactNode {
+            slot: self
+                .tree
+                .slot(CompactTree::<T>::child(self.slot.children, index)),
+            tree: self.tree,
+        }
+    }
+}
+
+impl<T> Debug for CompactNode<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompactNode")
+            .field("data", self.get())
+            .field("children", &self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// A mutable reference to a node in a [`CompactTree`].
+///
+/// This borrows the whole tree, as new children are allocated from it.
+pub struct CompactNodeMut<'t, T> {
+    index: u32,
+    tree: &'t mut CompactTree<T>,
+}
+
+impl<T> CompactNodeMut<'_, T> {
+    fn children(&self) -> Span {
+        self.tree.slot(self.index).children
+    }
+
+    fn set_children_span(&mut self, span: Span) {
+        self.tree.slot_mut(self.index).children = span;
+    }
+
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &T {
+        &self.tree.slot(self.index).data
+    }
+
+    /// Returns a mutable reference to the user data stored in this node.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.tree.slot_mut(self.index).data
+    }
+
+    /// Returns a reference to this node.
+    pub fn as_node(&self) -> CompactNode<'_, T> {
+        CompactNode {
+            slot: self.tree.slot(self.index),
+            tree: self.tree,
+        }
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none.
+    pub fn is_expanded(&self) -> bool {
+        self.children().is_expanded()
+    }
+
+    /// Returns the number of child nodes.
+    pub fn len(&self) -> usize {
+        self.children().len()
+    }
+
+    /// Returns true if this node has no children, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.children().len() == 0
+    }
+
+    /// Returns an iterator over references to the child nodes.
+    pub fn iter(&self) -> CompactIter<'_, T> {
+        CompactIter {
+            iter: self.tree.children(self.children()),
+            tree: self.tree,
+        }
+    }
+
+    /// Returns a reference to the child node at the given index.
+    pub fn at(&self, index: usize) -> CompactNode<'_, T> {
+        CompactNode {
+            slot: self
+                .tree
+                .slot(CompactTree::<T>::child(self.children(), index)),
+            tree: self.tree,
+        }
+    }
+
+    /// Returns a mutable reference to the child node at the given index.
+    pub fn at_mut(&mut self, index: usize) -> CompactNodeMut<'_, T> {
+        CompactNodeMut {
+            index: CompactTree::<T>::child(self.children(), index),
+            tree: self.tree,
+        }
+    }
+
+    /// Sets the child nodes using the provided iterable.
+    ///
+    /// Any previous child nodes become inaccessible.
+    pub fn set_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let span = self.tree.alloc_iter(iterable);
+        self.set_children_span(span);
+    }
+
+    /// Sets the child nodes using the provided iterable, allowing recursive
+    /// construction of a subtree.
+    ///
+    /// See [`CompactTree::set_children_subtree`].
+    pub fn set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(CompactNodeMut<'_, T>, U),
+    {
+        let span = self.tree.alloc_iter_recursive(iterable, builder);
+        self.set_children_span(span);
+    }
+
+    /// Sets the child nodes using the iterable returned by `f`, unless they
+    /// have already been set.
+    ///
+    /// Returns true if the node was expanded by this call. See
+    /// [`crate::NodeMut::expand_with`].
+    pub fn expand_with<F, I>(&mut self, f: F) -> bool
+    where
+        F: FnOnce(&mut T) -> I,
+        I: IntoIterator<Item = T>,
+    {
+        if self.is_expanded() {
+            return false;
+        }
+        let iterable = f(self.get_mut());
+        self.set_children(iterable);
+        true
+    }
+
+    /// Adopts the children of the child node at the given index as the
+    /// children here.
+    ///
+    /// Any previous child nodes and the other sets of grandchildren and their
+    /// descendents become inaccessible.
+    pub fn adopt_grandchildren_at(&mut self, index: usize) {
+        let child = CompactTree::<T>::child(self.children(), index);
+        let span = self.tree.slot(child).children;
+        self.set_children_span(span);
+    }
+
+    /// Moves the child nodes from here to become the roots of the tree.
+    ///
+    /// This replaces the current children of the node with an empty slice.
+    pub fn move_children_to_root(&mut self) {
+        self.tree.roots = self.children();
+        self.set_children_span(Span::EMPTY);
+    }
+}
+
+impl<T> Debug for CompactNodeMut<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_node().fmt(f)
+    }
+}
+
+/// An iterator over references to the nodes of a [`CompactTree`].
+pub struct CompactIter<'t, T> {
+    iter: slice::Iter<'t, Slot<T>>,
+    tree: &'t CompactTree<T>,
+}
+
+impl<'t, T> Iterator for CompactIter<'t, T> {
+    type Item = CompactNode<'t, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        self.iter.next().map(|slot| CompactNode { slot, tree })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for CompactIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        self.iter.next_back().map(|slot| CompactNode { slot, tree })
+    }
+}
+
+impl<T> ExactSizeIterator for CompactIter<'_, T> {}
+
+impl<T> FusedIterator for CompactIter<'_, T> {}
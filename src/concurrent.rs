@@ -0,0 +1,464 @@
+use alloc::{vec, vec::Vec};
+use core::{
+    fmt::{self, Debug, Formatter},
+    iter::FusedIterator,
+    mem,
+    ptr::{self, NonNull},
+    slice,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::SlidingBuffers;
+
+/// The storage for a node in a [`ConcurrentTree`].
+struct AtomicNode<T> {
+    data: T,
+    // This is null until the children are claimed for expansion, and then
+    // set to the claimed marker until they are published.
+    children: AtomicPtr<AtomicNode<T>>,
+    len: AtomicUsize,
+}
+
+impl<T> AtomicNode<T> {
+    // Claimed nodes are marked by an address at the top of the address space,
+    // where no buffer is ever located.
+    const CLAIMED_ADDR: usize = 0usize.wrapping_sub(mem::align_of::<Self>());
+
+    fn leaf(data: T) -> AtomicNode<T> {
+        AtomicNode {
+            data,
+            children: AtomicPtr::new(ptr::null_mut()),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_published(ptr: *mut AtomicNode<T>) -> bool {
+        !ptr.is_null() && ptr.addr() != Self::CLAIMED_ADDR
+    }
+
+    fn children(&self) -> &[AtomicNode<T>] {
+        let ptr = self.children.load(Ordering::Acquire);
+        if !Self::is_published(ptr) {
+            return &[];
+        }
+        // SAFETY: The length was stored before the pointer was published
+        // with release ordering, and the slice stays allocated while the
+        // tree is borrowed.
+        unsafe { slice::from_raw_parts(ptr, self.len.load(Ordering::Relaxed)) }
+    }
+}
+
+struct Shard<T> {
+    buffers: SlidingBuffers<AtomicNode<T>>,
+    claimed: AtomicBool,
+}
+
+/// A tree which can be grown by several threads at once.
+///
+/// This is a concurrent variant of [`crate::SlidingTree`] intended for
+/// tree-parallel search. Threads share the tree by reference and each obtains
+/// a [`ConcurrentWorker`] with [`Self::worker`], which allocates from its own
+/// shard of the buffers. They can then descend through the tree at the same
+/// time and expand its leaves, where the children of each node are published
+/// atomically exactly once. The payloads can only be accessed by shared
+/// reference, so any statistics they hold should use atomics or other
+/// synchronisation.
+///
+/// Changing the roots and recycling buffers require exclusive access, so
+/// they are stop-the-world operations performed while no workers exist.
+/// Because the children of a node may be allocated by any of the shards,
+/// [`Self::recycle`] walks the tree to find the buffers which are still in
+/// use.
+///
+/// A `ConcurrentTree` is [`Send`] if `T` is, and [`Sync`] if `T` is both
+/// `Send` and `Sync`, as payloads created on one thread may be dropped on
+/// another when their buffer is recycled.
+pub struct ConcurrentTree<T> {
+    roots: NonNull<[AtomicNode<T>]>,
+    shards: Vec<Shard<T>>,
+}
+
+// SAFETY: The tree owns its shards, and the roots only point into them.
+unsafe impl<T: Send> Send for ConcurrentTree<T> {}
+
+// SAFETY: Each shard is only accessed through a worker which has claimed it
+// exclusively, or with exclusive access to the tree, and the children of the
+// nodes are only read once they have been published.
+unsafe impl<T: Send + Sync> Sync for ConcurrentTree<T> {}
+
+impl<T> ConcurrentTree<T> {
+    /// Creates a new empty `ConcurrentTree` with a default capacity based on
+    /// the size of `T`, and the given number of shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new(shards: usize) -> ConcurrentTree<T> {
+        ConcurrentTree::with_capacity(
+            1000000 / size_of::<AtomicNode<T>>(),
+            shards,
+        )
+    }
+
+    /// Creates a new empty `ConcurrentTree` with the specified capacity and
+    /// number of shards.
+    ///
+    /// The `capacity` is the maximum number of nodes that can be allocated
+    /// in a single buffer. At most `shards` workers can exist at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_capacity(capacity: usize, shards: usize) -> ConcurrentTree<T> {
+        assert!(shards > 0, "a ConcurrentTree needs at least one shard");
+        ConcurrentTree {
+            roots: NonNull::slice_from_raw_parts(NonNull::dangling(), 0),
+            shards: (0..shards)
+                .map(|_| Shard {
+                    buffers: SlidingBuffers::with_capacity(capacity),
+                    claimed: AtomicBool::new(false),
+                })
+                .collect(),
+        }
+    }
+
+    /// Claims an unused shard and returns a worker which allocates from it,
+    /// or `None` if every shard is in use.
+    ///
+    /// The shard is released when the worker is dropped.
+    pub fn worker(&self) -> Option<ConcurrentWorker<'_, T>> {
+        self.shards
+            .iter()
+            .find(|shard| {
+                shard
+                    .claimed
+                    .compare_exchange(
+                        false,
+                        true,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            })
+            .map(|shard| ConcurrentWorker { tree: self, shard })
+    }
+
+    fn roots(&self) -> &[AtomicNode<T>] {
+        // SAFETY: The roots were allocated from one of the shards, which
+        // cannot be recycled while the tree is borrowed.
+        unsafe { self.roots.as_ref() }
+    }
+
+    /// Returns the number of root nodes.
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Returns true if the tree has no root nodes, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Returns an iterator over references to the root nodes.
+    pub fn iter(&self) -> ConcurrentIter<'_, T> {
+        ConcurrentIter {
+            iter: self.roots().iter(),
+            tree: self,
+        }
+    }
+
+    /// Returns a reference to the root node at the given index.
+    pub fn at(&self, index: usize) -> ConcurrentNode<'_, T> {
+        ConcurrentNode {
+            node: &self.roots()[index],
+            tree: self,
+        }
+    }
+
+    /// Sets the root nodes using the provided iterable.
+    ///
+    /// Any previous nodes become inaccessible.
+    pub fn set_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let roots = self.shards[0]
+            .buffers
+            .alloc_iter(iterable.into_iter().map(AtomicNode::leaf));
+        self.roots = NonNull::from(roots);
+    }
+
+    /// Adopts the children of the root node at the given index as the roots.
+    ///
+    /// Any previous roots and the other sets of children and their
+    /// descendents become inaccessible. Call [`Self::recycle`] afterwards to
+    /// reuse their memory.
+    pub fn adopt_grandchildren_at(&mut self, index: usize) {
+        let children = self.roots()[index].children();
+        self.roots = NonNull::from(children);
+    }
+
+    /// Clears the tree, removing the roots, all their descendants, and
+    /// recycling all buffers.
+    pub fn clear(&mut self) {
+        self.roots = NonNull::slice_from_raw_parts(NonNull::dangling(), 0);
+        for shard in &self.shards {
+            // SAFETY: Once the roots have been cleared, previously allocated
+            // nodes are inaccessible and can be recycled.
+            unsafe { shard.buffers.recycle_all() };
+        }
+    }
+
+    /// Recycles every buffer which does not contain any nodes reachable from
+    /// the roots.
+    ///
+    /// This walks the whole tree to find the buffers which are still in use,
+    /// so it takes time proportional to the number of reachable nodes.
+    pub fn recycle(&mut self) {
+        if self.is_empty() {
+            self.clear();
+            return;
+        }
+        let mut marks: Vec<_> = self
+            .shards
+            .iter()
+            .map(|shard| shard.buffers.index())
+            .collect();
+        // The slices are walked from an explicit stack so that deep trees
+        // cannot overflow the call stack.
+        let mut stack = vec![self.roots()];
+        while let Some(nodes) = stack.pop() {
+            assert!(
+                marks.iter_mut().any(|marks| marks.mark(nodes)),
+                "slice not present in this ConcurrentTree"
+            );
+            stack.extend(
+                nodes
+                    .iter()
+                    .map(AtomicNode::children)
+                    .filter(|children| !children.is_empty()),
+            );
+        }
+        for (shard, marks) in self.shards.iter().zip(marks) {
+            // SAFETY: Every buffer holding a reachable node has been marked,
+            // so the nodes in the other buffers are inaccessible.
+            unsafe { shard.buffers.recycle_unmarked_in(marks) };
+        }
+    }
+
+    /// Frees unused buffers to reduce memory usage.
+    pub fn trim(&mut self) {
+        for shard in &self.shards {
+            shard.buffers.trim();
+        }
+    }
+
+    /// Returns the number of buffers in the finished, current, and recycled
+    /// states, summed over all of the shards.
+    pub fn buffer_stats(&mut self) -> (usize, usize, usize) {
+        self.shards.iter().fold((0, 0, 0), |(f, c, r), shard| {
+            let (sf, sc, sr) = shard.buffers.buffer_stats();
+            (f + sf, c + sc, r + sr)
+        })
+    }
+}
+
+impl<T> Debug for ConcurrentTree<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrentTree")
+            .field("roots", &self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// A worker which expands the nodes of a [`ConcurrentTree`] from its own
+/// shard of the buffers.
+///
+/// This is created by [`ConcurrentTree::worker`].
+pub struct ConcurrentWorker<'t, T> {
+    tree: &'t ConcurrentTree<T>,
+    shard: &'t Shard<T>,
+}
+
+// SAFETY: The worker has claimed its shard exclusively, so the shard is only
+// used by the thread which owns the worker.
+unsafe impl<T: Send + Sync> Send for ConcurrentWorker<'_, T> {}
+
+impl<'t, T> ConcurrentWorker<'t, T> {
+    /// Returns the tree which this worker expands.
+    pub fn tree(&self) -> &'t ConcurrentTree<T> {
+        self.tree
+    }
+
+    /// Sets the children of a leaf node, generating them from its payload
+    /// with `f`, unless it has already been claimed by another expansion.
+    ///
+    /// Returns true if the children were published by this call. If the node
+    /// is already expanded, or is being expanded by another thread, then `f`
+    /// is not called and false is returned. If `f` panics, the node is left
+    /// unexpanded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` belongs to a different tree.
+    pub fn expand_with<F, I>(
+        &mut self,
+        node: ConcurrentNode<'t, T>,
+        f: F,
+    ) -> bool
+    where
+        F: FnOnce(&T) -> I,
+        I: IntoIterator<Item = T>,
+    {
+        struct Unclaim<'n, T>(&'n AtomicNode<T>);
+        impl<T> Drop for Unclaim<'_, T> {
+            fn drop(&mut self) {
+                // Only reached while unwinding.
+                self.0.children.store(ptr::null_mut(), Ordering::Release);
+            }
+        }
+
+        assert!(
+            ptr::eq(node.tree, self.tree),
+            "node belongs to a different ConcurrentTree"
+        );
+        let node = node.node;
+        let claimed =
+            ptr::without_provenance_mut(AtomicNode::<T>::CLAIMED_ADDR);
+        if node
+            .children
+            .compare_exchange(
+                ptr::null_mut(),
+                claimed,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return false;
+        }
+        let guard = Unclaim(node);
+        let children = self
+            .shard
+            .buffers
+            .alloc_iter(f(&node.data).into_iter().map(AtomicNode::leaf));
+        mem::forget(guard);
+        node.len.store(children.len(), Ordering::Relaxed);
+        node.children
+            .store(children.as_mut_ptr(), Ordering::Release);
+        true
+    }
+}
+
+impl<T> Drop for ConcurrentWorker<'_, T> {
+    fn drop(&mut self) {
+        self.shard.claimed.store(false, Ordering::Release);
+    }
+}
+
+/// A reference to a node in a [`ConcurrentTree`].
+///
+/// This can be copied and shared between the threads using the tree.
+pub struct ConcurrentNode<'t, T> {
+    node: &'t AtomicNode<T>,
+    tree: &'t ConcurrentTree<T>,
+}
+
+impl<T> Clone for ConcurrentNode<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ConcurrentNode<'_, T> {}
+
+impl<'t, T> ConcurrentNode<'t, T> {
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &'t T {
+        &self.node.data
+    }
+
+    /// Returns true if the children of this node have been published, even
+    /// if there are none.
+    pub fn is_expanded(&self) -> bool {
+        AtomicNode::is_published(self.node.children.load(Ordering::Acquire))
+    }
+
+    /// Returns the number of child nodes, which is zero until they have been
+    /// published.
+    pub fn len(&self) -> usize {
+        self.node.children().len()
+    }
+
+    /// Returns true if this node has no published children, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.node.children().is_empty()
+    }
+
+    /// Returns an iterator over references to the published child nodes.
+    pub fn iter(&self) -> ConcurrentIter<'t, T> {
+        ConcurrentIter {
+            iter: self.node.children().iter(),
+            tree: self.tree,
+        }
+    }
+
+    /// Returns a reference to the child node at the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds of the published children.
+    pub fn at(&self, index: usize) -> ConcurrentNode<'t, T> {
+        ConcurrentNode {
+            node: &self.node.children()[index],
+            tree: self.tree,
+        }
+    }
+}
+
+impl<T> Debug for ConcurrentNode<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrentNode")
+            .field("data", self.get())
+            .field("children", &self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// An iterator over references to the nodes of a [`ConcurrentTree`].
+pub struct ConcurrentIter<'t, T> {
+    iter: slice::Iter<'t, AtomicNode<T>>,
+    tree: &'t ConcurrentTree<T>,
+}
+
+impl<'t, T> Iterator for ConcurrentIter<'t, T> {
+    type Item = ConcurrentNode<'t, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        self.iter.next().map(|node| ConcurrentNode { node, tree })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for ConcurrentIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        self.iter
+            .next_back()
+            .map(|node| ConcurrentNode { node, tree })
+    }
+}
+
+impl<T> ExactSizeIterator for ConcurrentIter<'_, T> {}
+
+impl<T> FusedIterator for ConcurrentIter<'_, T> {}
@@ -0,0 +1,358 @@
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+use core::{
+    cell::Cell,
+    fmt::{self, Debug, Formatter},
+    iter::FusedIterator,
+    ptr, slice,
+};
+
+use crate::SlidingBuffers;
+
+/// The storage for a node in a [`DagTree`].
+struct Vertex<'a, T> {
+    data: T,
+    children: Cell<Option<&'a [Vertex<'a, T>]>>,
+}
+
+/// A tree that grows from the leaves and recedes from the root, in which
+/// several parents can share one slice of children.
+///
+/// This is a variant of [`crate::SlidingTree`] for directed acyclic graphs,
+/// such as the positions of a game which are reachable by different move
+/// orders. Nodes are only ever referred to through shared references, so
+/// their children are replaced with [`DagNode::set_children`] and
+/// [`DagNode::share_children_of`] through a shared reference to the tree, and
+/// the payloads can be updated through interior mutability, for example with
+/// [`Cell`] statistics.
+///
+/// A shared slice may have been allocated before some of its parents, so
+/// [`Self::recycle`] marks every buffer holding a reachable node and recycles
+/// the rest, rather than dropping the buffers older than the roots.
+///
+/// A node which shares the children of one of its own ancestors creates a
+/// cycle. This is not unsafe, but recursive traversals such as the [`Debug`]
+/// implementation will then not terminate.
+pub struct DagTree<'a, T> {
+    roots: Cell<&'a [Vertex<'a, T>]>,
+    buffers: SlidingBuffers<Vertex<'a, T>>,
+}
+
+// SAFETY: The tree owns its buffers, and the nodes only refer to each other.
+unsafe impl<T: Send> Send for DagTree<'_, T> {}
+
+impl<'a, T> DagTree<'a, T> {
+    /// Creates a new empty `DagTree` with a default capacity based on the
+    /// size of `T`.
+    pub fn new() -> DagTree<'a, T> {
+        DagTree::with_capacity(1000000 / size_of::<Vertex<'a, T>>())
+    }
+
+    /// Creates a new empty `DagTree` with the specified capacity.
+    ///
+    /// The `capacity` is the maximum number of nodes that can be allocated
+    /// in a single buffer.
+    pub fn with_capacity(capacity: usize) -> DagTree<'a, T> {
+        DagTree {
+            roots: Cell::new(&[]),
+            buffers: SlidingBuffers::with_capacity(capacity),
+        }
+    }
+
+    fn alloc_iter<I>(&self, iterable: I) -> &'a [Vertex<'a, T>]
+    where
+        I: IntoIterator<Item = T>,
+    {
+        // SAFETY: As in `SlidingTreeState::borrow_buffers`, `'a` is never
+        // leaked outside the implementation, and the buffers are only
+        // recycled through a mutable reference to the tree.
+        let buffers = unsafe {
+            &*(&self.buffers as *const SlidingBuffers<Vertex<'a, T>>)
+        };
+        buffers.alloc_iter(iterable.into_iter().map(|data| Vertex {
+            data,
+            children: Cell::new(None),
+        }))
+    }
+
+    /// Returns the number of root nodes.
+    pub fn len(&self) -> usize {
+        self.roots.get().len()
+    }
+
+    /// Returns true if the tree has no root nodes, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.roots.get().is_empty()
+    }
+
+    /// Returns an iterator over references to the root nodes.
+    pub fn iter(&self) -> DagIter<'_, 'a, T> {
+        DagIter {
+            iter: self.roots.get().iter(),
+            tree: self,
+        }
+    }
+
+    /// Returns a reference to the root node at the given index.
+    pub fn at(&self, index: usize) -> DagNode<'_, 'a, T> {
+        DagNode {
+            vertex: &self.roots.get()[index],
+            tree: self,
+        }
+    }
+
+    /// Sets the root nodes using the provided iterable.
+    ///
+    /// Any previous nodes which are not shared become inaccessible.
+    pub fn set_children<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.roots.set(self.alloc_iter(iterable));
+    }
+
+    /// Adopts the children of `node` as the roots.
+    ///
+    /// Unlike [`crate::HasChildrenMut::move_children_to_root`], the node keeps
+    /// its children, as they may be shared with other parents.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` belongs to a different tree.
+    pub fn adopt_children_of(&self, node: DagNode<'_, 'a, T>) {
+        assert!(
+            ptr::eq(self, node.tree),
+            "node belongs to a different DagTree"
+        );
+        self.roots.set(node.vertex.children.get().unwrap_or(&[]));
+    }
+
+    /// Adopts the children of the root node at the given index as the roots.
+    pub fn adopt_grandchildren_at(&self, index: usize) {
+        self.adopt_children_of(self.at(index));
+    }
+
+    /// Clears the tree, removing the roots, all their descendants, and
+    /// recycling all buffers.
+    pub fn clear(&mut self) {
+        self.roots.set(&[]);
+        // SAFETY: Once the roots have been cleared, previously allocated nodes
+        // are inaccessible and can be recycled.
+        unsafe { self.buffers.recycle_all() };
+    }
+
+    /// Recycles buffers which hold no nodes reachable from the roots.
+    ///
+    /// This takes time proportional to the number of reachable slices of
+    /// children, visiting each shared slice once.
+    pub fn recycle(&mut self) {
+        let roots = self.roots.get();
+        if roots.is_empty() {
+            self.clear();
+            return;
+        }
+        let mut marks = self.buffers.index();
+        let mut visited = BTreeSet::new();
+        // The slices are walked from an explicit stack so that deep graphs
+        // cannot overflow the call stack.
+        let mut stack = vec![roots];
+        while let Some(nodes) = stack.pop() {
+            assert!(
+                marks.mark(nodes),
+                "slice not present in this SlidingBuffers"
+            );
+            for node in nodes {
+                let children = node.children.get().unwrap_or(&[]);
+                if !children.is_empty() && visited.insert(children.as_ptr()) {
+                    stack.push(children);
+                }
+            }
+        }
+        // SAFETY: Every buffer holding a reachable node has been marked, so
+        // the nodes in the other buffers are inaccessible.
+        unsafe { self.buffers.recycle_unmarked_in(marks) };
+    }
+
+    /// Frees unused buffers to reduce memory usage.
+    pub fn trim(&mut self) {
+        self.buffers.trim();
+    }
+
+    /// Returns the number of buffers in the finished, current, and recycled
+    /// states.
+    pub fn buffer_stats(&self) -> (usize, usize, usize) {
+        self.buffers.buffer_stats()
+    }
+}
+
+impl<T> Debug for DagTree<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DagTree")
+            .field("roots", &self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<T> Default for DagTree<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A reference to a node in a [`DagTree`].
+///
+/// This also refers to the tree, from which new children are allocated.
+pub struct DagNode<'t, 'a, T> {
+    vertex: &'t Vertex<'a, T>,
+    tree: &'t DagTree<'a, T>,
+}
+
+impl<T> Clone for DagNode<'_, '_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for DagNode<'_, '_, T> {}
+
+impl<'t, 'a, T> DagNode<'t, 'a, T> {
+    fn children(&self) -> &'t [Vertex<'a, T>] {
+        self.vertex.children.get().unwrap_or(&[])
+    }
+
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &'t T {
+        &self.vertex.data
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none.
+    pub fn is_expanded(&self) -> bool {
+        self.vertex.children.get().is_some()
+    }
+
+    /// Returns the number of child nodes.
+    pub fn len(&self) -> usize {
+        self.children().len()
+    }
+
+    /// Returns true if this node has no children, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.children().is_empty()
+    }
+
+    /// Returns an iterator over references to the child nodes.
+    pub fn iter(&self) -> DagIter<'t, 'a, T> {
+        DagIter {
+            iter: self.children().iter(),
+            tree: self.tree,
+        }
+    }
+
+    /// Returns a reference to the child node at the given index.
+    pub fn at(&self, index: usize) -> DagNode<'t, 'a, T> {
+        DagNode {
+            vertex: &self.children()[index],
+            tree: self.tree,
+        }
+    }
+
+    /// Sets the child nodes using the provided iterable.
+    ///
+    /// Any previous child nodes which are not shared become inaccessible.
+    pub fn set_children<I>(&self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.vertex
+            .children
+            .set(Some(self.tree.alloc_iter(iterable)));
+    }
+
+    /// Sets the child nodes using the iterable returned by `f`, unless they
+    /// have already been set.
+    ///
+    /// Returns true if the node was expanded by this call.
+    pub fn expand_with<F, I>(&self, f: F) -> bool
+    where
+        F: FnOnce(&T) -> I,
+        I: IntoIterator<Item = T>,
+    {
+        if self.is_expanded() {
+            return false;
+        }
+        self.set_children(f(self.get()));
+        true
+    }
+
+    /// Shares the children of `other` as the children here, so that both
+    /// nodes are parents of the same slice.
+    ///
+    /// If `other` is unexpanded then this node becomes unexpanded too.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `other` belongs to a different tree.
+    pub fn share_children_of(&self, other: DagNode<'_, 'a, T>) {
+        assert!(
+            ptr::eq(self.tree, other.tree),
+            "node belongs to a different DagTree"
+        );
+        self.vertex.children.set(other.vertex.children.get());
+    }
+
+    /// Returns true if this node and `other` share the same non-empty slice
+    /// of children.
+    pub fn shares_children_with(&self, other: DagNode<'_, 'a, T>) -> bool {
+        let children = self.children();
+        !children.is_empty() && ptr::eq(children, other.children())
+    }
+}
+
+impl<T> Debug for DagNode<'_, '_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DagNode")
+            .field("data", self.get())
+            .field("children", &self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// An iterator over references to the nodes of a [`DagTree`].
+pub struct DagIter<'t, 'a, T> {
+    iter: slice::Iter<'t, Vertex<'a, T>>,
+    tree: &'t DagTree<'a, T>,
+}
+
+impl<'t, 'a, T> Iterator for DagIter<'t, 'a, T> {
+    type Item = DagNode<'t, 'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(DagNode {
+            vertex: self.iter.next()?,
+            tree: self.tree,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for DagIter<'_, '_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        Some(DagNode {
+            vertex: self.iter.next_back()?,
+            tree: self.tree,
+        })
+    }
+}
+
+impl<T> ExactSizeIterator for DagIter<'_, '_, T> {}
+
+impl<T> FusedIterator for DagIter<'_, '_, T> {}
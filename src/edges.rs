@@ -0,0 +1,546 @@
+use core::{
+    fmt::{self, Debug, Formatter},
+    iter::FusedIterator,
+    mem::MaybeUninit,
+    slice,
+};
+
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+use crate::MmapOptions;
+use crate::{
+    AllocError, HasChildren, HasChildrenMut, Node, NodeChildrenMut,
+    NodeIterMut, NodeMut, SlidingTree,
+};
+
+fn wrap<'s, 'a, N, E>(
+    nodes: &'s [Node<'a, (E, N)>],
+) -> &'s [EdgeNode<'a, N, E>] {
+    // SAFETY: `EdgeNode` is a transparent wrapper around `Node`.
+    unsafe { &*(nodes as *const [Node<'a, (E, N)>] as *const _) }
+}
+
+fn wrap_one<'s, 'a, N, E>(
+    node: &'s Node<'a, (E, N)>,
+) -> &'s EdgeNode<'a, N, E> {
+    // SAFETY: `EdgeNode` is a transparent wrapper around `Node`.
+    unsafe { &*(node as *const Node<'a, (E, N)> as *const _) }
+}
+
+/// A trait for types that have child nodes with labelled edges.
+///
+/// This is the counterpart of [`HasChildren`] for an [`EdgeTree`]. Every
+/// method is provided in terms of [`Self::children`].
+pub trait HasEdges<'a, N: 'a, E: 'a> {
+    /// Returns a reference to the child nodes.
+    fn children(&self) -> &[EdgeNode<'a, N, E>];
+
+    /// Returns true if this node has no children, false otherwise.
+    fn is_empty(&self) -> bool {
+        self.children().is_empty()
+    }
+
+    /// Returns the number of child nodes.
+    fn len(&self) -> usize {
+        self.children().len()
+    }
+
+    /// Returns an iterator over references to the child nodes.
+    fn iter(&self) -> slice::Iter<'_, EdgeNode<'a, N, E>> {
+        self.children().iter()
+    }
+
+    /// Returns a reference to the child node at the given index.
+    fn at(&self, index: usize) -> &EdgeNode<'a, N, E> {
+        &self.children()[index]
+    }
+
+    /// Returns an iterator over the labels of the edges to the child nodes.
+    fn edges(&self) -> Edges<'a, '_, N, E> {
+        Edges {
+            iter: self.children().iter(),
+        }
+    }
+}
+
+/// A trait for types that have mutable child nodes with labelled edges.
+///
+/// This is the counterpart of [`HasChildrenMut`] for an [`EdgeTree`]. Every
+/// method is provided in terms of [`Self::children_mut`].
+pub trait HasEdgesMut<'a, N: 'a, E: 'a>: HasEdges<'a, N, E> {
+    /// Returns a mutable reference to the child nodes.
+    fn children_mut(&mut self) -> EdgeChildrenMut<'a, '_, N, E>;
+
+    /// Sets the child nodes using the provided iterable of edge labels and
+    /// node data.
+    ///
+    /// See [`HasChildrenMut::set_children`].
+    fn set_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = (E, N)>,
+    {
+        self.children_mut().inner.set_children(iterable);
+    }
+
+    /// Sets the child nodes using the provided iterable, allowing
+    /// recursive construction of a subtree.
+    ///
+    /// See [`HasChildrenMut::set_children_subtree`].
+    fn set_children_subtree<I, F, U>(&mut self, iterable: I, mut builder: F)
+    where
+        I: IntoIterator<Item = (E, N, U)>,
+        F: FnMut(EdgeNodeMut<'a, '_, N, E>, U),
+    {
+        self.children_mut().inner.set_children_subtree(
+            iterable
+                .into_iter()
+                .map(|(edge, data, u)| ((edge, data), u)),
+            |node, u| builder(EdgeNodeMut { inner: node }, u),
+        );
+    }
+
+    /// Sets the child nodes using the provided iterable, or returns an error
+    /// if they cannot be allocated.
+    ///
+    /// See [`HasChildrenMut::try_set_children`].
+    fn try_set_children<I>(&mut self, iterable: I) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = (E, N)>,
+    {
+        self.children_mut().inner.try_set_children(iterable)
+    }
+
+    /// Sets the child nodes using the provided iterable, allowing
+    /// recursive construction of a subtree, or returns an error if they
+    /// cannot be allocated.
+    ///
+    /// See [`HasChildrenMut::try_set_children_subtree`].
+    fn try_set_children_subtree<I, F, U>(
+        &mut self,
+        iterable: I,
+        mut builder: F,
+    ) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = (E, N, U)>,
+        F: FnMut(EdgeNodeMut<'a, '_, N, E>, U) -> Result<(), AllocError>,
+    {
+        self.children_mut().inner.try_set_children_subtree(
+            iterable
+                .into_iter()
+                .map(|(edge, data, u)| ((edge, data), u)),
+            |node, u| builder(EdgeNodeMut { inner: node }, u),
+        )
+    }
+
+    /// Adopts the children of the child node at the given index as the
+    /// children here.
+    ///
+    /// See [`HasChildrenMut::adopt_grandchildren_at`].
+    fn adopt_grandchildren_at(&mut self, index: usize) {
+        self.children_mut().inner.adopt_grandchildren_at(index);
+    }
+
+    /// Moves the child nodes from here to become the roots of the tree.
+    ///
+    /// The edges to the new roots keep their labels. See
+    /// [`HasChildrenMut::move_children_to_root`].
+    fn move_children_to_root(&mut self) {
+        self.children_mut().inner.move_children_to_root();
+    }
+
+    /// Returns an iterator over mutable references to the child nodes.
+    fn iter_mut(&mut self) -> EdgeIterMut<'a, '_, N, E> {
+        let NodeChildrenMut { children, state } = self.children_mut().inner;
+        EdgeIterMut {
+            inner: NodeIterMut {
+                iter: children.iter_mut(),
+                state,
+            },
+        }
+    }
+
+    /// Returns a mutable reference to the child node at the given index.
+    fn at_mut(&mut self, index: usize) -> EdgeNodeMut<'a, '_, N, E> {
+        let NodeChildrenMut { children, state } = self.children_mut().inner;
+        EdgeNodeMut {
+            inner: NodeMut {
+                node: &mut children[index],
+                state,
+            },
+        }
+    }
+
+    /// Retains only the child nodes for which `f` returns true, preserving
+    /// their order.
+    ///
+    /// See [`HasChildrenMut::retain_children`].
+    fn retain_children<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&EdgeNode<'a, N, E>) -> bool,
+    {
+        self.children_mut()
+            .inner
+            .retain_children(|node| f(wrap_one(node)));
+    }
+
+    /// Removes the child node at the given index, shifting the following
+    /// child nodes down.
+    ///
+    /// See [`HasChildrenMut::remove_child`].
+    fn remove_child(&mut self, index: usize) {
+        self.children_mut().inner.remove_child(index);
+    }
+
+    /// Appends a new child node after the existing ones.
+    ///
+    /// See [`HasChildrenMut::push_child`].
+    fn push_child(&mut self, edge: E, data: N) {
+        self.children_mut().inner.push_child((edge, data));
+    }
+
+    /// Appends a new child node for each item in the iterable, after the
+    /// existing ones.
+    ///
+    /// See [`HasChildrenMut::extend_children`].
+    fn extend_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = (E, N)>,
+    {
+        self.children_mut().inner.extend_children(iterable);
+    }
+}
+
+/// A node in an [`EdgeTree`], containing user data, the label of the edge
+/// from its parent, and child nodes.
+///
+/// The label is stored alongside the node in its parent's slice of children,
+/// so iterating over the edges does not follow any further references.
+#[repr(transparent)]
+pub struct EdgeNode<'a, N, E> {
+    inner: Node<'a, (E, N)>,
+}
+
+impl<'a, N, E> EdgeNode<'a, N, E> {
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &N {
+        &self.inner.get().1
+    }
+
+    /// Returns a reference to the label of the edge to this node.
+    pub fn edge(&self) -> &E {
+        &self.inner.get().0
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none. See [`Node::is_expanded`].
+    pub fn is_expanded(&self) -> bool {
+        self.inner.is_expanded()
+    }
+}
+
+impl<'a, N, E> HasEdges<'a, N, E> for EdgeNode<'a, N, E> {
+    fn children(&self) -> &[EdgeNode<'a, N, E>] {
+        wrap(self.inner.children())
+    }
+}
+
+impl<'a, N, E> Debug for EdgeNode<'a, N, E>
+where
+    N: Debug,
+    E: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EdgeNode")
+            .field("edge", self.edge())
+            .field("data", self.get())
+            .field("children", &self.children())
+            .finish()
+    }
+}
+
+/// A mutable reference to a node in an [`EdgeTree`].
+pub struct EdgeNodeMut<'a, 'b, N, E> {
+    inner: NodeMut<'a, 'b, (E, N)>,
+}
+
+impl<'a, 'b, N, E> EdgeNodeMut<'a, 'b, N, E> {
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &N {
+        &self.inner.get().1
+    }
+
+    /// Returns a mutable reference to the user data stored in this node.
+    pub fn get_mut(&mut self) -> &mut N {
+        &mut self.inner.get_mut().1
+    }
+
+    /// Returns a reference to the label of the edge to this node.
+    pub fn edge(&self) -> &E {
+        &self.inner.get().0
+    }
+
+    /// Returns a mutable reference to the label of the edge to this node.
+    pub fn edge_mut(&mut self) -> &mut E {
+        &mut self.inner.get_mut().0
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none. See [`Node::is_expanded`].
+    pub fn is_expanded(&self) -> bool {
+        self.inner.is_expanded()
+    }
+
+    /// Sets the child nodes using the edge labels and node data returned by
+    /// `f`, unless they have already been set.
+    ///
+    /// See [`NodeMut::expand_with`].
+    pub fn expand_with<F, I>(&mut self, f: F) -> bool
+    where
+        F: FnOnce(&mut N) -> I,
+        I: IntoIterator<Item = (E, N)>,
+    {
+        self.inner.expand_with(|(_, data)| f(data))
+    }
+}
+
+impl<'a, N, E> HasEdges<'a, N, E> for EdgeNodeMut<'a, '_, N, E> {
+    fn children(&self) -> &[EdgeNode<'a, N, E>] {
+        wrap(self.inner.children())
+    }
+}
+
+impl<'a, N, E> HasEdgesMut<'a, N, E> for EdgeNodeMut<'a, '_, N, E> {
+    fn children_mut(&mut self) -> EdgeChildrenMut<'a, '_, N, E> {
+        EdgeChildrenMut {
+            inner: self.inner.children_mut(),
+        }
+    }
+}
+
+impl<'a, N, E> AsRef<EdgeNode<'a, N, E>> for EdgeNodeMut<'a, '_, N, E> {
+    fn as_ref(&self) -> &EdgeNode<'a, N, E> {
+        wrap_one(self.inner.as_ref())
+    }
+}
+
+impl<'a, N, E> Debug for EdgeNodeMut<'a, '_, N, E>
+where
+    N: Debug,
+    E: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+/// A mutable reference to the children of a node in an [`EdgeTree`].
+pub struct EdgeChildrenMut<'a, 'b, N, E> {
+    inner: NodeChildrenMut<'a, 'b, (E, N)>,
+}
+
+impl<'a, N, E> HasEdges<'a, N, E> for EdgeChildrenMut<'a, '_, N, E> {
+    fn children(&self) -> &[EdgeNode<'a, N, E>] {
+        wrap(self.inner.children())
+    }
+}
+
+impl<'a, N, E> HasEdgesMut<'a, N, E> for EdgeChildrenMut<'a, '_, N, E> {
+    fn children_mut(&mut self) -> EdgeChildrenMut<'a, '_, N, E> {
+        EdgeChildrenMut {
+            inner: self.inner.children_mut(),
+        }
+    }
+}
+
+/// An iterator over the labels of the edges to a slice of nodes.
+pub struct Edges<'a, 'b, N, E> {
+    iter: slice::Iter<'b, EdgeNode<'a, N, E>>,
+}
+
+impl<'a, 'b, N, E> Iterator for Edges<'a, 'b, N, E> {
+    type Item = &'b E;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(EdgeNode::edge)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<N, E> DoubleEndedIterator for Edges<'_, '_, N, E> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(EdgeNode::edge)
+    }
+}
+
+impl<N, E> ExactSizeIterator for Edges<'_, '_, N, E> {}
+
+impl<N, E> FusedIterator for Edges<'_, '_, N, E> {}
+
+/// An iterator over a slice of mutable node references in an [`EdgeTree`].
+pub struct EdgeIterMut<'a, 'b, N, E> {
+    inner: NodeIterMut<'a, 'b, (E, N)>,
+}
+
+impl<'a, 'b, N, E> Iterator for EdgeIterMut<'a, 'b, N, E> {
+    type Item = EdgeNodeMut<'a, 'b, N, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|inner| EdgeNodeMut { inner })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<N, E> DoubleEndedIterator for EdgeIterMut<'_, '_, N, E> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|inner| EdgeNodeMut { inner })
+    }
+}
+
+impl<N, E> ExactSizeIterator for EdgeIterMut<'_, '_, N, E> {}
+
+/// A [`SlidingTree`] whose edges are labelled.
+///
+/// Each child is stored as a pair of its edge label of type `E` and its node
+/// data of type `N`, such as a move in a game and the statistics of the
+/// resulting position. The roots have labels too, which are kept when a node's
+/// children are moved to the root.
+pub struct EdgeTree<'a, N, E> {
+    tree: SlidingTree<'a, (E, N)>,
+}
+
+impl<'a, N, E> EdgeTree<'a, N, E> {
+    /// Creates a new empty `EdgeTree` with a default capacity based on the
+    /// size of its nodes.
+    #[cfg(feature = "alloc")]
+    pub fn new() -> EdgeTree<'a, N, E> {
+        EdgeTree {
+            tree: SlidingTree::new(),
+        }
+    }
+
+    /// Creates a new empty `EdgeTree` with the specified capacity.
+    ///
+    /// See [`SlidingTree::with_capacity`].
+    #[cfg(feature = "alloc")]
+    pub fn with_capacity(capacity: usize) -> EdgeTree<'a, N, E> {
+        EdgeTree {
+            tree: SlidingTree::with_capacity(capacity),
+        }
+    }
+
+    /// Creates a new empty `EdgeTree` with the specified capacity, where each
+    /// buffer is a separate `mmap` mapping configured by `options`.
+    #[cfg(all(feature = "mmap", target_os = "linux"))]
+    pub fn with_mmap(
+        capacity: usize,
+        options: MmapOptions,
+    ) -> EdgeTree<'a, N, E> {
+        EdgeTree {
+            tree: SlidingTree::with_mmap(capacity, options),
+        }
+    }
+
+    /// Creates a new empty `EdgeTree` which allocates nodes only from
+    /// `region`, divided into `buffer_count` buffers.
+    ///
+    /// See [`SlidingTree::with_static_storage`].
+    pub fn with_static_storage(
+        region: &'static mut [MaybeUninit<u8>],
+        buffer_count: usize,
+    ) -> EdgeTree<'a, N, E> {
+        EdgeTree {
+            tree: SlidingTree::with_static_storage(region, buffer_count),
+        }
+    }
+
+    /// Preallocates recycled buffers.
+    pub fn preallocate(&mut self, required: usize) {
+        self.tree.preallocate(required);
+    }
+
+    /// Clears the tree, removing the roots, all their descendants, and
+    /// recycling all buffers.
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Recycles buffers containing nodes that are no longer accessible.
+    pub fn recycle(&mut self) {
+        self.tree.recycle();
+    }
+
+    /// Recycles every buffer which does not contain any nodes reachable from
+    /// the roots. See [`SlidingTree::recycle_unreachable`].
+    #[cfg(feature = "alloc")]
+    pub fn recycle_unreachable(&mut self) {
+        self.tree.recycle_unreachable();
+    }
+
+    /// Returns the fraction of the nodes held in the buffers which are no
+    /// longer reachable from the roots.
+    #[cfg(feature = "alloc")]
+    pub fn fragmentation(&self) -> f32 {
+        self.tree.fragmentation()
+    }
+
+    /// Relocates the reachable nodes into fresh buffers and recycles all of
+    /// the old buffers. See [`SlidingTree::compact`].
+    #[cfg(feature = "alloc")]
+    pub fn compact(&mut self) -> Result<(), AllocError> {
+        self.tree.compact()
+    }
+
+    /// Frees unused buffers to reduce memory usage.
+    pub fn trim(&mut self) {
+        self.tree.trim();
+    }
+
+    /// Returns the current buffer capacity.
+    pub fn capacity(&self) -> usize {
+        self.tree.capacity()
+    }
+
+    /// Returns the number of buffers in the finished, current, and recycled
+    /// states.
+    pub fn buffer_stats(&self) -> (usize, usize, usize) {
+        self.tree.buffer_stats()
+    }
+}
+
+impl<'a, N, E> Debug for EdgeTree<'a, N, E>
+where
+    N: Debug,
+    E: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EdgeTree")
+            .field("roots", &self.children())
+            .finish()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, N, E> Default for EdgeTree<'a, N, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, N, E> HasEdges<'a, N, E> for EdgeTree<'a, N, E> {
+    fn children(&self) -> &[EdgeNode<'a, N, E>] {
+        wrap(self.tree.children())
+    }
+}
+
+impl<'a, N, E> HasEdgesMut<'a, N, E> for EdgeTree<'a, N, E> {
+    fn children_mut(&mut self) -> EdgeChildrenMut<'a, '_, N, E> {
+        EdgeChildrenMut {
+            inner: self.tree.children_mut(),
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Debug, Formatter},
+    mem::MaybeUninit,
+};
+
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+use crate::MmapOptions;
+use crate::{
+    FrozenTree, Node, SlidingBuffers, SlidingTreeState, cell::RefSliceCell,
+    roots::impl_roots,
+};
+
+/// Identifies a tree in a [`SlidingForest`].
+///
+/// The identifier of a closed tree may be reused by a tree opened later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TreeId(usize);
+
+/// A collection of independent trees which share one set of buffers.
+///
+/// Each tree behaves like a [`SlidingTree`](crate::SlidingTree), but their
+/// nodes are allocated from the same [`SlidingBuffers`], so that many small
+/// trees do not each hold a mostly empty buffer. Trees are opened with
+/// [`Self::open_tree`] and accessed by their [`TreeId`].
+///
+/// As the buffers are shared, [`Self::recycle`] can only reuse the buffers
+/// which are older than the roots of every tree. Closing a tree with
+/// [`Self::close_tree`] releases its roots, so that a tree which has stopped
+/// sliding does not hold back the others.
+pub struct SlidingForest<'a, T> {
+    trees: Vec<Option<RefSliceCell<'a, Node<'a, T>>>>,
+    free: Vec<usize>,
+    active: usize,
+    state: SlidingTreeState<'a, T>,
+}
+
+impl<'a, T> SlidingForest<'a, T> {
+    #[inline]
+    fn process_pending_roots(&self) {
+        // Pending roots can only have been set by the last tree borrowed with
+        // `tree_mut`, which is still open.
+        if let Some(pending_roots) = self.state.pending_roots.take() {
+            self.roots(self.active).set(pending_roots);
+        }
+    }
+
+    fn roots(&self, index: usize) -> &RefSliceCell<'a, Node<'a, T>> {
+        self.trees
+            .get(index)
+            .and_then(Option::as_ref)
+            .expect("tree has been closed")
+    }
+
+    fn with_buffers(
+        buffers: SlidingBuffers<Node<'a, T>>,
+    ) -> SlidingForest<'a, T> {
+        SlidingForest {
+            trees: Vec::new(),
+            free: Vec::new(),
+            active: 0,
+            state: SlidingTreeState::with_buffers(buffers),
+        }
+    }
+
+    /// Creates a new empty `SlidingForest` with a default capacity based on
+    /// the size of `T`.
+    pub fn new() -> SlidingForest<'a, T> {
+        SlidingForest::with_capacity(1000000 / size_of::<T>().max(1))
+    }
+
+    /// Creates a new empty `SlidingForest` with the specified capacity.
+    ///
+    /// The `capacity` is the maximum number of nodes that can be allocated
+    /// in a single buffer.
+    pub fn with_capacity(capacity: usize) -> SlidingForest<'a, T> {
+        SlidingForest::with_buffers(SlidingBuffers::with_capacity(capacity))
+    }
+
+    /// Creates a new empty `SlidingForest` with the specified capacity, where
+    /// each buffer is a separate `mmap` mapping configured by `options`.
+    #[cfg(all(feature = "mmap", target_os = "linux"))]
+    pub fn with_mmap(
+        capacity: usize,
+        options: MmapOptions,
+    ) -> SlidingForest<'a, T> {
+        SlidingForest::with_buffers(SlidingBuffers::with_mmap(
+            capacity, options,
+        ))
+    }
+
+    /// Creates a new empty `SlidingForest` which allocates nodes only from
+    /// `region`, divided into `buffer_count` buffers.
+    ///
+    /// See [`SlidingBuffers::with_static_storage`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is too small to hold at least one node in each
+    /// buffer.
+    pub fn with_static_storage(
+        region: &'static mut [MaybeUninit<u8>],
+        buffer_count: usize,
+    ) -> SlidingForest<'a, T> {
+        SlidingForest::with_buffers(SlidingBuffers::with_static_storage(
+            region,
+            buffer_count,
+        ))
+    }
+
+    /// Opens a new empty tree and returns its identifier.
+    pub fn open_tree(&mut self) -> TreeId {
+        let roots = Some(RefSliceCell::new(&mut []));
+        match self.free.pop() {
+            Some(index) => {
+                self.trees[index] = roots;
+                TreeId(index)
+            }
+            None => {
+                self.trees.push(roots);
+                TreeId(self.trees.len() - 1)
+            }
+        }
+    }
+
+    /// Closes a tree, releasing its roots.
+    ///
+    /// The nodes of the tree become inaccessible and are dropped once their
+    /// buffers are recycled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has already been closed.
+    pub fn close_tree(&mut self, id: TreeId) {
+        self.process_pending_roots();
+        self.roots(id.0);
+        self.trees[id.0] = None;
+        self.free.push(id.0);
+    }
+
+    /// Returns true if the tree has been opened and not yet closed.
+    pub fn contains_tree(&self, id: TreeId) -> bool {
+        matches!(self.trees.get(id.0), Some(Some(_)))
+    }
+
+    /// Returns an iterator over the identifiers of the open trees.
+    pub fn tree_ids(&self) -> impl Iterator<Item = TreeId> + '_ {
+        self.trees
+            .iter()
+            .enumerate()
+            .filter(|(_, roots)| roots.is_some())
+            .map(|(index, _)| TreeId(index))
+    }
+
+    /// Returns the number of open trees.
+    pub fn len(&self) -> usize {
+        self.trees.len() - self.free.len()
+    }
+
+    /// Returns true if there are no open trees.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a read-only view of the roots of a tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has been closed.
+    pub fn tree(&self, id: TreeId) -> FrozenTree<'a, '_, T> {
+        self.process_pending_roots();
+        FrozenTree {
+            roots: self.roots(id.0).get(),
+        }
+    }
+
+    /// Returns a mutable reference to a tree, through which it can be grown
+    /// and its root advanced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tree has been closed.
+    pub fn tree_mut(&mut self, id: TreeId) -> ForestTree<'a, '_, T> {
+        self.process_pending_roots();
+        self.roots(id.0);
+        self.active = id.0;
+        ForestTree {
+            roots: self.trees[id.0].as_mut().unwrap(),
+            state: &self.state,
+        }
+    }
+
+    /// Closes every tree and recycles all buffers.
+    pub fn clear(&mut self) {
+        self.trees.clear();
+        self.free.clear();
+        self.state.pending_roots.set(None);
+        // SAFETY: Once the trees have been closed, previously allocated nodes
+        // are inaccessible and can be recycled.
+        unsafe {
+            self.state.buffers.recycle_all();
+        }
+    }
+
+    /// Recycles buffers containing nodes that are no longer accessible from
+    /// any tree.
+    ///
+    /// Only the buffers allocated before the oldest roots of all of the open
+    /// trees can be reused.
+    pub fn recycle(&mut self) {
+        self.process_pending_roots();
+        let roots = || self.trees.iter().flatten().map(RefSliceCell::get);
+        #[cfg(debug_assertions)]
+        roots().for_each(|roots| self.state.sanity_check(roots));
+        if roots().all(<[_]>::is_empty) {
+            // SAFETY: No tree has any nodes, so none are accessible.
+            unsafe {
+                self.state.buffers.recycle_all();
+            }
+        } else {
+            // SAFETY: Nodes allocated before the roots of every tree are
+            // inaccessible and can be recycled.
+            unsafe {
+                self.state.buffers.recycle_older_than_all(roots());
+            }
+        }
+    }
+
+    /// Preallocates recycled buffers.
+    pub fn preallocate(&mut self, required: usize) {
+        self.state.buffers.preallocate(required);
+    }
+
+    /// Frees unused buffers to reduce memory usage.
+    pub fn trim(&mut self) {
+        self.state.buffers.trim();
+    }
+
+    /// Returns the current buffer capacity.
+    pub fn capacity(&self) -> usize {
+        self.state.buffers.capacity()
+    }
+
+    /// Returns the number of buffers in the finished, current, and recycled states.
+    pub fn buffer_stats(&self) -> (usize, usize, usize) {
+        self.state.buffers.buffer_stats()
+    }
+}
+
+impl<'a, T> Debug for SlidingForest<'a, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.process_pending_roots();
+        f.debug_map()
+            .entries(self.trees.iter().enumerate().filter_map(
+                |(index, roots)| Some((TreeId(index), roots.as_ref()?.get())),
+            ))
+            .finish()
+    }
+}
+
+impl<'a, T> Default for SlidingForest<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A mutable reference to a tree in a [`SlidingForest`].
+///
+/// This provides the same operations on the roots of the tree as a
+/// [`SlidingTree`](crate::SlidingTree) does, allocating from the buffers
+/// shared by the forest.
+pub struct ForestTree<'a, 'b, T> {
+    roots: &'b mut RefSliceCell<'a, Node<'a, T>>,
+    state: &'b SlidingTreeState<'a, T>,
+}
+
+impl<T> ForestTree<'_, '_, T> {
+    #[inline]
+    fn process_pending_roots(&self) {
+        if let Some(pending_roots) = self.state.pending_roots.take() {
+            self.roots.set(pending_roots);
+        }
+    }
+}
+
+impl<'a, T> Debug for ForestTree<'a, '_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.process_pending_roots();
+        f.debug_struct("ForestTree")
+            .field("roots", &self.roots.get())
+            .finish()
+    }
+}
+
+impl_roots!(ForestTree<'a, '_, T>);
@@ -0,0 +1,86 @@
+use alloc::vec;
+use core::ptr;
+
+use crate::{Node, SlidingTree, buffers::Generation};
+
+/// A weak reference to a node in a [`SlidingTree`], which can be kept across
+/// operations that might make the node unreachable or recycle its buffer.
+///
+/// A handle records the position of the node together with the generation of
+/// the buffer holding it. It is created by [`SlidingTree::handle`] and can be
+/// turned back into a reference with [`SlidingTree::resolve`], which checks
+/// that the node is still alive. Handles do not borrow the tree.
+///
+/// A handle refers to the slot occupied by the node. Operations which move
+/// nodes between slots in place, such as sorting or removing siblings, leave
+/// the handle referring to whichever node takes the slot, if it is reachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeHandle {
+    generation: Generation,
+    addr: usize,
+}
+
+impl<'a, T> SlidingTree<'a, T> {
+    /// Returns a handle to a node in this tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` does not belong to this tree.
+    pub fn handle(&self, node: &Node<'a, T>) -> NodeHandle {
+        let ptr = node as *const Node<'a, T>;
+        let generation = self
+            .state
+            .buffers
+            .generation_of(ptr)
+            .expect("node not present in this tree");
+        NodeHandle {
+            generation,
+            addr: ptr.addr(),
+        }
+    }
+
+    /// Returns a reference to the node identified by `handle`, or `None` if
+    /// its buffer has since been recycled or it is no longer reachable from
+    /// the roots.
+    ///
+    /// Checking reachability walks the tree from the roots, but every node is
+    /// allocated after its ancestors, so only the slices in buffers first used
+    /// no later than the last allocation in the node's buffer are visited.
+    /// Resolving a handle to a node near the roots is therefore cheap even in
+    /// a large tree.
+    pub fn resolve(&self, handle: NodeHandle) -> Option<&Node<'a, T>> {
+        self.process_pending_roots();
+        let index = self.state.buffers.index();
+        let ptr = ptr::without_provenance::<Node<'a, T>>(handle.addr);
+        if index.generation_of(ptr) != Some(handle.generation) {
+            return None;
+        }
+        // An ancestor may be in a newer buffer than the node when the node was
+        // allocated in an older buffer which is still current, but never in
+        // a buffer first used after the node was allocated.
+        let latest = index.last_generation_of(ptr)?;
+        let is_candidate = |nodes: &[Node<'a, T>]| {
+            index
+                .generation_of(nodes.as_ptr())
+                .is_some_and(|generation| generation <= latest)
+        };
+        // The slices are walked from an explicit stack so that deep trees
+        // cannot overflow the call stack.
+        let mut stack = vec![self.roots.get()];
+        while let Some(nodes) = stack.pop() {
+            let start = nodes.as_ptr().addr();
+            let end = start + size_of_val(nodes);
+            if (start..end).contains(&handle.addr) {
+                let index = (handle.addr - start) / size_of::<Node<'a, T>>();
+                return nodes.get(index);
+            }
+            stack.extend(
+                nodes
+                    .iter()
+                    .map(|node| &*node.children)
+                    .filter(|children| is_candidate(children)),
+            );
+        }
+        None
+    }
+}
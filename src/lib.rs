@@ -1,16 +1,82 @@
 #![no_std]
-#![doc = include_str!("../README.md")]
+// The examples in the README allocate from the heap.
+#![cfg_attr(feature = "alloc", doc = include_str!("../README.md"))]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
 
 pub use buffers::SlidingBuffers;
 use cell::RefSliceCell;
+#[cfg(feature = "alloc")]
+pub use compact::{CompactIter, CompactNode, CompactNodeMut, CompactTree};
+#[cfg(feature = "alloc")]
+pub use concurrent::{
+    ConcurrentIter, ConcurrentNode, ConcurrentTree, ConcurrentWorker,
+};
 use core::{
     cell::Cell,
+    cmp::Ordering,
     fmt::{self, Debug, Formatter},
-    mem, slice,
+    mem::{self, MaybeUninit},
+    ptr, slice,
+};
+#[cfg(feature = "alloc")]
+pub use dag::{DagIter, DagNode, DagTree};
+pub use edges::{
+    EdgeChildrenMut, EdgeIterMut, EdgeNode, EdgeNodeMut, EdgeTree, Edges,
+    HasEdges, HasEdgesMut,
 };
+#[cfg(feature = "alloc")]
+pub use forest::{ForestTree, SlidingForest, TreeId};
+#[cfg(feature = "alloc")]
+pub use handle::NodeHandle;
+#[cfg(feature = "mcts")]
+pub use mcts::{
+    Evaluator, Game, Leaf, RaveSchedule, Rollout, Search, SearchNode,
+    SearchOptions, Selection,
+};
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+pub use mmap::MmapOptions;
+#[cfg(feature = "rayon")]
+pub use par::{ParNodeIterMut, ParNodeMut};
+#[cfg(feature = "rayon")]
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator};
+use roots::impl_roots;
+#[cfg(feature = "alloc")]
+pub use soa::{SoaIter, SoaNode, SoaNodeMut, SoaTree};
+pub use storage::AllocError;
+#[cfg(feature = "alloc")]
+pub use transposition::TranspositionTable;
 
 mod buffers;
 mod cell;
+#[cfg(feature = "alloc")]
+mod compact;
+#[cfg(feature = "alloc")]
+mod concurrent;
+#[cfg(feature = "alloc")]
+mod dag;
+mod edges;
+#[cfg(feature = "alloc")]
+mod forest;
+#[cfg(feature = "alloc")]
+mod handle;
+#[cfg(feature = "mcts")]
+mod mcts;
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+mod mmap;
+#[cfg(feature = "rayon")]
+mod par;
+mod roots;
+mod siblings;
+#[cfg(feature = "alloc")]
+mod soa;
+mod storage;
+#[cfg(feature = "alloc")]
+mod transposition;
 
 /// A trait for types that have child nodes.
 ///
@@ -31,6 +97,12 @@ pub trait HasChildren<'a, T> {
 
     /// Returns a reference to the child node at the given index.
     fn at(&self, index: usize) -> &Node<'a, T>;
+
+    /// Returns a parallel iterator over references to the child nodes.
+    #[cfg(feature = "rayon")]
+    fn par_iter(&self) -> rayon::slice::Iter<'_, Node<'a, T>>
+    where
+        T: Sync;
 }
 
 /// A trait for types that have mutable child nodes.
@@ -63,6 +135,69 @@ pub trait HasChildrenMut<'a, T>: HasChildren<'a, T> {
         I: IntoIterator<Item = (T, U)>,
         F: FnMut(NodeMut<'a, '_, T>, U);
 
+    /// Sets the child nodes using the provided parallel iterable, building
+    /// their subtrees on the threads of the current rayon thread pool.
+    ///
+    /// This is the parallel counterpart of [`Self::set_children_subtree`].
+    /// Each rayon worker thread allocates the subtrees it builds from its own
+    /// buffers, which are added to the tree's buffers afterwards, in order.
+    /// With static storage, the `builder` is called on the current thread
+    /// instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `builder` calls [`Self::move_children_to_root`], as the
+    /// roots cannot be replaced while subtrees are built in parallel.
+    #[cfg(feature = "rayon")]
+    fn par_set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
+    where
+        T: Send,
+        I: IntoParallelIterator<Item = (T, U)>,
+        F: Fn(NodeMut<'a, '_, T>, U) + Sync,
+        U: Send;
+
+    /// Sets the child nodes using the provided iterable, or returns an error
+    /// if they cannot be allocated.
+    ///
+    /// This is the fallible counterpart of [`Self::set_children`]. If an
+    /// error is returned, the current children are left unchanged.
+    fn try_set_children<I>(&mut self, iterable: I) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = T>;
+
+    /// Sets the child nodes using the provided iterable, allowing
+    /// recursive construction of a subtree, or returns an error if they
+    /// cannot be allocated.
+    ///
+    /// This is the fallible counterpart of [`Self::set_children_subtree`].
+    /// Construction stops at the first error, whether it comes from this
+    /// allocation or is returned by the `builder`. If an error is returned,
+    /// the current children are left unchanged.
+    fn try_set_children_subtree<I, F, U>(
+        &mut self,
+        iterable: I,
+        builder: F,
+    ) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(NodeMut<'a, '_, T>, U) -> Result<(), AllocError>;
+
+    /// Sets the child nodes to a chain of nodes with one child each, taking
+    /// their values from the iterable in order.
+    ///
+    /// The chain is allocated as one contiguous run, in which the children
+    /// of each node are the next node in the run rather than a slice of
+    /// their own, which suits long lines of forced moves. Its last node is
+    /// left unexpanded, and an empty iterable leaves no children. The nodes
+    /// are read and edited like any others. Adding a second child to one of
+    /// them, such as with [`Self::push_child`], cuts the run there: only its
+    /// child is moved to a new slice, while the rest of the run stays in
+    /// place and its buffer is kept for as long as the new slice. Any
+    /// previous child nodes become inaccessible.
+    fn set_children_chain<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>;
+
     /// Adopts the children of the child node at the given index as the
     /// children here.
     ///
@@ -80,14 +215,187 @@ pub trait HasChildrenMut<'a, T>: HasChildren<'a, T> {
     /// Returns an iterator over mutable references to the child nodes.
     fn iter_mut(&mut self) -> NodeIterMut<'a, '_, T>;
 
+    /// Returns a parallel iterator over mutable references to the child
+    /// nodes.
+    ///
+    /// The data of the nodes and their descendants can be modified in
+    /// parallel, but they cannot be given new children. See [`ParNodeMut`].
+    #[cfg(feature = "rayon")]
+    fn par_iter_mut(&mut self) -> ParNodeIterMut<'a, '_, T>
+    where
+        T: Send;
+
     /// Returns a mutable reference to the child node at the given index.
     fn at_mut(&mut self, index: usize) -> NodeMut<'a, '_, T>;
+
+    /// Returns mutable references to the child nodes at several indices at
+    /// once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is out of bounds or appears more than once.
+    fn get_many_mut<const K: usize>(
+        &mut self,
+        indices: [usize; K],
+    ) -> [NodeMut<'a, '_, T>; K];
+
+    /// Divides the child nodes into two mutable slices at the given index.
+    ///
+    /// The first slice contains the nodes before `mid` and the second the
+    /// rest. The nodes in either slice can still be given new children.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid` is greater than the number of child nodes.
+    fn split_at_mut(
+        &mut self,
+        mid: usize,
+    ) -> (NodeSliceMut<'a, '_, T>, NodeSliceMut<'a, '_, T>);
+
+    /// Returns a mutable reference to the first child node and a mutable
+    /// slice of the rest, or `None` if there are no child nodes.
+    fn split_first_mut(
+        &mut self,
+    ) -> Option<(NodeMut<'a, '_, T>, NodeSliceMut<'a, '_, T>)>;
+
+    /// Returns a mutable reference to the last child node and a mutable
+    /// slice of the rest, or `None` if there are no child nodes.
+    fn split_last_mut(
+        &mut self,
+    ) -> Option<(NodeMut<'a, '_, T>, NodeSliceMut<'a, '_, T>)>;
+
+    /// Calls `f` for each node along a path of child indices from here,
+    /// passing a mutable slice of the node and its siblings together with
+    /// the index of the node in it.
+    ///
+    /// The path is followed from the top down, after `f` returns for each
+    /// node, so `f` can give the node new children but should not reorder
+    /// its siblings. This allows the siblings of every node on a selected
+    /// path to be updated, as in RAVE, without holding a mutable reference to
+    /// each level at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is out of bounds, before `f` is called with it.
+    fn for_each_on_path_mut<F>(&mut self, path: &[usize], f: F)
+    where
+        F: FnMut(NodeSliceMut<'a, '_, T>, usize);
+
+    /// Retains only the child nodes for which `f` returns true, preserving
+    /// their order.
+    ///
+    /// This is performed in place and the retained nodes keep their subtrees.
+    /// The data of the other child nodes is dropped and their descendants
+    /// become inaccessible.
+    fn retain_children<F>(&mut self, f: F)
+    where
+        F: FnMut(&Node<'a, T>) -> bool;
+
+    /// Removes the child node at the given index, shifting the following
+    /// child nodes down.
+    ///
+    /// This is performed in place. The data of the removed node is dropped
+    /// and its descendants become inaccessible.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    fn remove_child(&mut self, index: usize);
+
+    /// Inserts a new child node at the given index, shifting the following
+    /// child nodes up.
+    ///
+    /// This appends the node as with [`Self::push_child`] and then rotates it
+    /// into place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of child nodes.
+    fn insert_child(&mut self, index: usize, data: T);
+
+    /// Appends a new child node after the existing ones.
+    ///
+    /// The child nodes are extended in place if they are the last allocation
+    /// in a buffer with free space, which takes constant time. Otherwise they
+    /// are moved to the end of a buffer with room for as many nodes again,
+    /// together with any of their descendants in buffers older than the new
+    /// slice. This takes time in proportion to the number of nodes moved, and
+    /// leaves their old slots as garbage until their buffers are recycled.
+    ///
+    /// Repeated appends to the same children, with nothing else allocated in
+    /// between, therefore take amortised constant time. Appends interleaved
+    /// with other allocations move the children each time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a buffer cannot be allocated. See [`Self::try_push_child`].
+    fn push_child(&mut self, data: T);
+
+    /// Appends a new child node after the existing ones, or returns an error
+    /// if it cannot be allocated.
+    ///
+    /// This is the fallible counterpart of [`Self::push_child`]. If an error
+    /// is returned, the child nodes are left unchanged, although some of
+    /// their descendants may have been moved.
+    fn try_push_child(&mut self, data: T) -> Result<(), AllocError>;
+
+    /// Appends a new child node for each item in the iterable, after the
+    /// existing ones.
+    ///
+    /// This behaves like calling [`Self::push_child`] for each item, except
+    /// that the lower bound of the iterator's size hint is used to leave
+    /// enough room for the remaining items when the child nodes are moved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a buffer cannot be allocated. See
+    /// [`Self::try_extend_children`].
+    fn extend_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>;
+
+    /// Appends a new child node for each item in the iterable, or returns an
+    /// error if they cannot be allocated.
+    ///
+    /// This is the fallible counterpart of [`Self::extend_children`].
+    /// Appending stops at the first error, keeping the child nodes appended
+    /// before it, and the rest of the iterable is not consumed.
+    fn try_extend_children<I>(&mut self, iterable: I) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = T>;
+
+    /// Swaps the child nodes at the given indices, together with their
+    /// subtrees.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is out of bounds.
+    fn swap_children(&mut self, a: usize, b: usize);
+
+    /// Sorts the child nodes with a comparator function, preserving the order
+    /// of equal nodes.
+    ///
+    /// This is performed in place and each node keeps its subtree.
+    #[cfg(feature = "alloc")]
+    fn sort_children_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&Node<'a, T>, &Node<'a, T>) -> Ordering;
+
+    /// Sorts the child nodes with a comparator function, without preserving
+    /// the order of equal nodes.
+    ///
+    /// This is performed in place and each node keeps its subtree. Unlike
+    /// `sort_children_by`, it does not allocate any temporary memory, so it is
+    /// also available without the `alloc` feature.
+    fn sort_children_unstable_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&Node<'a, T>, &Node<'a, T>) -> Ordering;
 }
 
 /// A node in the tree, containing user data and child nodes.
-#[derive(Debug)]
 pub struct Node<'a, T> {
-    data: T,
+    // This is only uninitialised in tombstones, which are never reachable.
+    data: MaybeUninit<T>,
     children: &'a mut [Node<'a, T>],
 }
 
@@ -95,7 +403,84 @@ pub struct Node<'a, T> {
 impl<'a, T> Node<'a, T> {
     /// Returns a reference to the user data stored in this node.
     pub fn get(&self) -> &T {
-        &self.data
+        // SAFETY: Reachable nodes are never tombstones.
+        unsafe { self.data.assume_init_ref() }
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none.
+    ///
+    /// New nodes start out unexpanded. This distinguishes a leaf which has
+    /// not been expanded yet from one which has no children, such as a
+    /// terminal state in a game. See [`NodeMut::expand_with`].
+    pub fn is_expanded(&self) -> bool {
+        self.children.as_ptr().addr() != Self::UNEXPANDED_ADDR
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        // SAFETY: Reachable nodes are never tombstones.
+        unsafe { self.data.assume_init_mut() }
+    }
+
+    fn leaf(data: T) -> Node<'a, T> {
+        Node {
+            data: MaybeUninit::new(data),
+            children: Self::sentinel(Self::UNEXPANDED_ADDR),
+        }
+    }
+
+    /// Creates a placeholder without any data, which fills a slot in a buffer
+    /// whose node has been moved or removed.
+    fn tombstone() -> Node<'a, T> {
+        Node {
+            data: MaybeUninit::uninit(),
+            children: Self::sentinel(Self::TOMBSTONE_ADDR),
+        }
+    }
+
+    // Unexpanded nodes and tombstones are marked by empty slices at the top
+    // of the address space, where no buffer is ever located.
+    const TOMBSTONE_ADDR: usize =
+        0usize.wrapping_sub(mem::align_of::<Node<'a, T>>());
+    const UNEXPANDED_ADDR: usize =
+        0usize.wrapping_sub(2 * mem::align_of::<Node<'a, T>>());
+
+    fn sentinel(addr: usize) -> &'a mut [Node<'a, T>] {
+        // SAFETY: The pointer is non-null and aligned, which is all that is
+        // required for an empty slice.
+        unsafe {
+            slice::from_raw_parts_mut(ptr::without_provenance_mut(addr), 0)
+        }
+    }
+
+    fn is_tombstone(&self) -> bool {
+        self.children.as_ptr().addr() == Self::TOMBSTONE_ADDR
+    }
+
+    /// Moves the node out, leaving a tombstone in its place.
+    fn take(&mut self) -> Node<'a, T> {
+        mem::replace(self, Node::tombstone())
+    }
+}
+
+impl<T> Drop for Node<'_, T> {
+    fn drop(&mut self) {
+        if !self.is_tombstone() {
+            // SAFETY: The data of nodes other than tombstones is initialised.
+            unsafe { self.data.assume_init_drop() };
+        }
+    }
+}
+
+impl<'a, T> Debug for Node<'a, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("data", self.get())
+            .field("children", &self.children)
+            .finish()
     }
 }
 
@@ -119,6 +504,14 @@ impl<'a, T> HasChildren<'a, T> for Node<'a, T> {
     fn at(&self, index: usize) -> &Node<'a, T> {
         &self.children[index]
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_iter(&self) -> rayon::slice::Iter<'_, Node<'a, T>>
+    where
+        T: Sync,
+    {
+        self.children.par_iter()
+    }
 }
 
 /// A mutable reference to a node in the tree.
@@ -130,12 +523,38 @@ pub struct NodeMut<'a, 'b, T> {
 impl<'a, 'b, T> NodeMut<'a, 'b, T> {
     /// Returns a reference to the user data stored in this node.
     pub fn get(&self) -> &T {
-        &self.node.data
+        self.node.get()
     }
 
     /// Returns a mutable reference to the user data stored in this node.
     pub fn get_mut(&mut self) -> &mut T {
-        &mut self.node.data
+        self.node.get_mut()
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none. See [`Node::is_expanded`].
+    pub fn is_expanded(&self) -> bool {
+        self.node.is_expanded()
+    }
+
+    /// Sets the child nodes using the iterable returned by `f`, unless they
+    /// have already been set.
+    ///
+    /// The user data of this node is passed to `f`, so that it can serve as
+    /// the seed for generating the children, such as a game position from
+    /// which the legal moves are generated. Returns true if the node was
+    /// expanded by this call. If `f` or the iterator panics, the node is
+    /// left unexpanded.
+    pub fn expand_with<F, I>(&mut self, f: F) -> bool
+    where
+        F: FnOnce(&mut T) -> I,
+        I: IntoIterator<Item = T>,
+    {
+        if self.node.is_expanded() {
+            return false;
+        }
+        self.node.children = self.state.alloc_iter(f(self.node.get_mut()));
+        true
     }
 }
 
@@ -159,6 +578,14 @@ impl<'a, T> HasChildren<'a, T> for NodeMut<'a, '_, T> {
     fn at(&self, index: usize) -> &Node<'a, T> {
         &self.node.children[index]
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_iter(&self) -> rayon::slice::Iter<'_, Node<'a, T>>
+    where
+        T: Sync,
+    {
+        self.node.children.par_iter()
+    }
 }
 
 impl<'a, T> HasChildrenMut<'a, T> for NodeMut<'a, '_, T> {
@@ -184,6 +611,47 @@ impl<'a, T> HasChildrenMut<'a, T> for NodeMut<'a, '_, T> {
         self.node.children = self.state.alloc_iter_recursive(iterable, builder);
     }
 
+    #[cfg(feature = "rayon")]
+    fn par_set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
+    where
+        T: Send,
+        I: IntoParallelIterator<Item = (T, U)>,
+        F: Fn(NodeMut<'a, '_, T>, U) + Sync,
+        U: Send,
+    {
+        self.node.children =
+            self.state.par_alloc_iter_recursive(iterable, builder);
+    }
+
+    fn try_set_children<I>(&mut self, iterable: I) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.node.children = self.state.try_alloc_iter(iterable)?;
+        Ok(())
+    }
+
+    fn try_set_children_subtree<I, F, U>(
+        &mut self,
+        iterable: I,
+        builder: F,
+    ) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(NodeMut<'a, '_, T>, U) -> Result<(), AllocError>,
+    {
+        self.node.children =
+            self.state.try_alloc_iter_recursive(iterable, builder)?;
+        Ok(())
+    }
+
+    fn set_children_chain<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.node.children = self.state.alloc_chain(iterable);
+    }
+
     fn adopt_grandchildren_at(&mut self, index: usize) {
         let node = &mut self.node.children[index];
         self.node.children = mem::take(&mut node.children);
@@ -201,12 +669,110 @@ impl<'a, T> HasChildrenMut<'a, T> for NodeMut<'a, '_, T> {
         }
     }
 
+    #[cfg(feature = "rayon")]
+    fn par_iter_mut(&mut self) -> ParNodeIterMut<'a, '_, T>
+    where
+        T: Send,
+    {
+        ParNodeIterMut::new(self.node.children)
+    }
+
     fn at_mut(&mut self, index: usize) -> NodeMut<'a, '_, T> {
         NodeMut {
             node: &mut self.node.children[index],
             state: self.state,
         }
     }
+
+    fn get_many_mut<const K: usize>(
+        &mut self,
+        indices: [usize; K],
+    ) -> [NodeMut<'a, '_, T>; K] {
+        NodeSliceMut::new(self.node.children, self.state).into_many_mut(indices)
+    }
+
+    fn split_at_mut(
+        &mut self,
+        mid: usize,
+    ) -> (NodeSliceMut<'a, '_, T>, NodeSliceMut<'a, '_, T>) {
+        NodeSliceMut::new(self.node.children, self.state).into_split_at(mid)
+    }
+
+    fn split_first_mut(
+        &mut self,
+    ) -> Option<(NodeMut<'a, '_, T>, NodeSliceMut<'a, '_, T>)> {
+        NodeSliceMut::new(self.node.children, self.state).into_split_first()
+    }
+
+    fn split_last_mut(
+        &mut self,
+    ) -> Option<(NodeMut<'a, '_, T>, NodeSliceMut<'a, '_, T>)> {
+        NodeSliceMut::new(self.node.children, self.state).into_split_last()
+    }
+
+    fn for_each_on_path_mut<F>(&mut self, path: &[usize], f: F)
+    where
+        F: FnMut(NodeSliceMut<'a, '_, T>, usize),
+    {
+        NodeSliceMut::new(self.node.children, self.state)
+            .into_for_each_on_path(path, f);
+    }
+
+    fn retain_children<F>(&mut self, f: F)
+    where
+        F: FnMut(&Node<'a, T>) -> bool,
+    {
+        self.children_mut().retain_children(f);
+    }
+
+    fn remove_child(&mut self, index: usize) {
+        self.children_mut().remove_child(index);
+    }
+
+    fn insert_child(&mut self, index: usize, data: T) {
+        self.children_mut().insert_child(index, data);
+    }
+
+    fn push_child(&mut self, data: T) {
+        self.children_mut().push_child(data);
+    }
+
+    fn try_push_child(&mut self, data: T) -> Result<(), AllocError> {
+        self.children_mut().try_push_child(data)
+    }
+
+    fn extend_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.children_mut().extend_children(iterable);
+    }
+
+    fn try_extend_children<I>(&mut self, iterable: I) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.children_mut().try_extend_children(iterable)
+    }
+
+    fn swap_children(&mut self, a: usize, b: usize) {
+        self.children_mut().swap_children(a, b);
+    }
+
+    #[cfg(feature = "alloc")]
+    fn sort_children_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&Node<'a, T>, &Node<'a, T>) -> Ordering,
+    {
+        self.children_mut().sort_children_by(compare);
+    }
+
+    fn sort_children_unstable_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&Node<'a, T>, &Node<'a, T>) -> Ordering,
+    {
+        self.children_mut().sort_children_unstable_by(compare);
+    }
 }
 
 impl<'a, T> AsRef<Node<'a, T>> for NodeMut<'a, '_, T> {
@@ -253,6 +819,46 @@ impl<'a, T> HasChildrenMut<'a, T> for NodeChildrenMut<'a, '_, T> {
         *self.children = self.state.alloc_iter_recursive(iterable, builder);
     }
 
+    #[cfg(feature = "rayon")]
+    fn par_set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
+    where
+        T: Send,
+        I: IntoParallelIterator<Item = (T, U)>,
+        F: Fn(NodeMut<'a, '_, T>, U) + Sync,
+        U: Send,
+    {
+        *self.children = self.state.par_alloc_iter_recursive(iterable, builder);
+    }
+
+    fn try_set_children<I>(&mut self, iterable: I) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        *self.children = self.state.try_alloc_iter(iterable)?;
+        Ok(())
+    }
+
+    fn try_set_children_subtree<I, F, U>(
+        &mut self,
+        iterable: I,
+        builder: F,
+    ) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(NodeMut<'a, '_, T>, U) -> Result<(), AllocError>,
+    {
+        *self.children =
+            self.state.try_alloc_iter_recursive(iterable, builder)?;
+        Ok(())
+    }
+
+    fn set_children_chain<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        *self.children = self.state.alloc_chain(iterable);
+    }
+
     fn adopt_grandchildren_at(&mut self, index: usize) {
         let node = &mut self.children[index];
         *self.children = mem::take(&mut node.children);
@@ -270,12 +876,110 @@ impl<'a, T> HasChildrenMut<'a, T> for NodeChildrenMut<'a, '_, T> {
         }
     }
 
+    #[cfg(feature = "rayon")]
+    fn par_iter_mut(&mut self) -> ParNodeIterMut<'a, '_, T>
+    where
+        T: Send,
+    {
+        ParNodeIterMut::new(self.children)
+    }
+
     fn at_mut(&mut self, index: usize) -> NodeMut<'a, '_, T> {
         NodeMut {
             node: &mut self.children[index],
             state: self.state,
         }
     }
+
+    fn get_many_mut<const K: usize>(
+        &mut self,
+        indices: [usize; K],
+    ) -> [NodeMut<'a, '_, T>; K] {
+        NodeSliceMut::new(self.children, self.state).into_many_mut(indices)
+    }
+
+    fn split_at_mut(
+        &mut self,
+        mid: usize,
+    ) -> (NodeSliceMut<'a, '_, T>, NodeSliceMut<'a, '_, T>) {
+        NodeSliceMut::new(self.children, self.state).into_split_at(mid)
+    }
+
+    fn split_first_mut(
+        &mut self,
+    ) -> Option<(NodeMut<'a, '_, T>, NodeSliceMut<'a, '_, T>)> {
+        NodeSliceMut::new(self.children, self.state).into_split_first()
+    }
+
+    fn split_last_mut(
+        &mut self,
+    ) -> Option<(NodeMut<'a, '_, T>, NodeSliceMut<'a, '_, T>)> {
+        NodeSliceMut::new(self.children, self.state).into_split_last()
+    }
+
+    fn for_each_on_path_mut<F>(&mut self, path: &[usize], f: F)
+    where
+        F: FnMut(NodeSliceMut<'a, '_, T>, usize),
+    {
+        NodeSliceMut::new(self.children, self.state)
+            .into_for_each_on_path(path, f);
+    }
+
+    fn retain_children<F>(&mut self, f: F)
+    where
+        F: FnMut(&Node<'a, T>) -> bool,
+    {
+        siblings::retain(self.children, f);
+    }
+
+    fn remove_child(&mut self, index: usize) {
+        siblings::remove(self.children, index);
+    }
+
+    fn insert_child(&mut self, index: usize, data: T) {
+        siblings::insert(self.state, self.children, index, data);
+    }
+
+    fn push_child(&mut self, data: T) {
+        siblings::push(self.state, self.children, data);
+    }
+
+    fn try_push_child(&mut self, data: T) -> Result<(), AllocError> {
+        siblings::try_push(self.state, self.children, data, 0)
+    }
+
+    fn extend_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        siblings::extend(self.state, self.children, iterable);
+    }
+
+    fn try_extend_children<I>(&mut self, iterable: I) -> Result<(), AllocError>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        siblings::try_extend(self.state, self.children, iterable)
+    }
+
+    fn swap_children(&mut self, a: usize, b: usize) {
+        self.children.swap(a, b);
+    }
+
+    #[cfg(feature = "alloc")]
+    fn sort_children_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&Node<'a, T>, &Node<'a, T>) -> Ordering,
+    {
+        self.children.sort_by(compare);
+    }
+
+    fn sort_children_unstable_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&Node<'a, T>, &Node<'a, T>) -> Ordering,
+    {
+        self.children.sort_unstable_by(compare);
+    }
 }
 
 impl<'a, T> HasChildren<'a, T> for NodeChildrenMut<'a, '_, T> {
@@ -298,6 +1002,180 @@ impl<'a, T> HasChildren<'a, T> for NodeChildrenMut<'a, '_, T> {
     fn at(&self, index: usize) -> &Node<'a, T> {
         &self.children[index]
     }
+
+    #[cfg(feature = "rayon")]
+    fn par_iter(&self) -> rayon::slice::Iter<'_, Node<'a, T>>
+    where
+        T: Sync,
+    {
+        self.children.par_iter()
+    }
+}
+
+/// A mutable reference to a contiguous part of the children of a node.
+///
+/// This is returned when the children are split, so that several nodes can
+/// be modified at once. The nodes themselves can still be given new children
+/// through [`Self::at_mut`] and [`Self::iter_mut`], but the slice cannot be
+/// resized.
+pub struct NodeSliceMut<'a, 'b, T> {
+    nodes: &'b mut [Node<'a, T>],
+    state: &'b SlidingTreeState<'a, T>,
+}
+
+impl<'a, 'b, T> NodeSliceMut<'a, 'b, T> {
+    fn new(
+        nodes: &'b mut [Node<'a, T>],
+        state: &'b SlidingTreeState<'a, T>,
+    ) -> NodeSliceMut<'a, 'b, T> {
+        NodeSliceMut { nodes, state }
+    }
+
+    fn reborrow(&mut self) -> NodeSliceMut<'a, '_, T> {
+        NodeSliceMut {
+            nodes: self.nodes,
+            state: self.state,
+        }
+    }
+
+    fn into_many_mut<const K: usize>(
+        self,
+        indices: [usize; K],
+    ) -> [NodeMut<'a, 'b, T>; K] {
+        let state = self.state;
+        siblings::get_many_mut(self.nodes, indices)
+            .map(|node| NodeMut { node, state })
+    }
+
+    fn into_split_at(
+        self,
+        mid: usize,
+    ) -> (NodeSliceMut<'a, 'b, T>, NodeSliceMut<'a, 'b, T>) {
+        let (left, right) = self.nodes.split_at_mut(mid);
+        (
+            NodeSliceMut::new(left, self.state),
+            NodeSliceMut::new(right, self.state),
+        )
+    }
+
+    fn into_split_first(
+        self,
+    ) -> Option<(NodeMut<'a, 'b, T>, NodeSliceMut<'a, 'b, T>)> {
+        let state = self.state;
+        let (first, rest) = self.nodes.split_first_mut()?;
+        Some((
+            NodeMut { node: first, state },
+            NodeSliceMut::new(rest, state),
+        ))
+    }
+
+    fn into_split_last(
+        self,
+    ) -> Option<(NodeMut<'a, 'b, T>, NodeSliceMut<'a, 'b, T>)> {
+        let state = self.state;
+        let (last, rest) = self.nodes.split_last_mut()?;
+        Some((
+            NodeMut { node: last, state },
+            NodeSliceMut::new(rest, state),
+        ))
+    }
+
+    fn into_for_each_on_path<F>(self, path: &[usize], mut f: F)
+    where
+        F: FnMut(NodeSliceMut<'a, '_, T>, usize),
+    {
+        let state = self.state;
+        let mut nodes = self.nodes;
+        for &index in path {
+            let len = nodes.len();
+            assert!(
+                index < len,
+                "index (is {index}) should be < len (is {len})"
+            );
+            f(NodeSliceMut::new(nodes, state), index);
+            nodes = &mut nodes[index].children;
+        }
+    }
+
+    /// Returns an iterator over mutable references to the nodes.
+    pub fn iter_mut(&mut self) -> NodeIterMut<'a, '_, T> {
+        NodeIterMut {
+            iter: self.nodes.iter_mut(),
+            state: self.state,
+        }
+    }
+
+    /// Returns a mutable reference to the node at the given index.
+    pub fn at_mut(&mut self, index: usize) -> NodeMut<'a, '_, T> {
+        NodeMut {
+            node: &mut self.nodes[index],
+            state: self.state,
+        }
+    }
+
+    /// Returns mutable references to the nodes at several indices at once.
+    /// See [`HasChildrenMut::get_many_mut`].
+    pub fn get_many_mut<const K: usize>(
+        &mut self,
+        indices: [usize; K],
+    ) -> [NodeMut<'a, '_, T>; K] {
+        self.reborrow().into_many_mut(indices)
+    }
+
+    /// Divides the nodes into two mutable slices at the given index.
+    /// See [`HasChildrenMut::split_at_mut`].
+    pub fn split_at_mut(
+        &mut self,
+        mid: usize,
+    ) -> (NodeSliceMut<'a, '_, T>, NodeSliceMut<'a, '_, T>) {
+        self.reborrow().into_split_at(mid)
+    }
+
+    /// Returns a mutable reference to the first node and a mutable slice of
+    /// the rest, or `None` if the slice is empty.
+    pub fn split_first_mut(
+        &mut self,
+    ) -> Option<(NodeMut<'a, '_, T>, NodeSliceMut<'a, '_, T>)> {
+        self.reborrow().into_split_first()
+    }
+
+    /// Returns a mutable reference to the last node and a mutable slice of
+    /// the rest, or `None` if the slice is empty.
+    pub fn split_last_mut(
+        &mut self,
+    ) -> Option<(NodeMut<'a, '_, T>, NodeSliceMut<'a, '_, T>)> {
+        self.reborrow().into_split_last()
+    }
+}
+
+impl<'a, T> HasChildren<'a, T> for NodeSliceMut<'a, '_, T> {
+    fn children(&self) -> &[Node<'a, T>] {
+        self.nodes
+    }
+
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn iter(&self) -> slice::Iter<'_, Node<'a, T>> {
+        self.nodes.iter()
+    }
+
+    fn at(&self, index: usize) -> &Node<'a, T> {
+        &self.nodes[index]
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_iter(&self) -> rayon::slice::Iter<'_, Node<'a, T>>
+    where
+        T: Sync,
+    {
+        self.nodes.par_iter()
+    }
 }
 
 /// An iterator over a slice of mutable node references.
@@ -338,10 +1216,24 @@ struct SlidingTreeState<'a, T> {
 }
 
 impl<'a, T> SlidingTreeState<'a, T> {
-    fn with_capacity(capacity: usize) -> SlidingTreeState<'a, T> {
+    fn with_buffers(
+        buffers: SlidingBuffers<Node<'a, T>>,
+    ) -> SlidingTreeState<'a, T> {
         SlidingTreeState {
             pending_roots: Cell::new(None),
-            buffers: SlidingBuffers::with_capacity(capacity),
+            buffers,
+        }
+    }
+
+    /// Asserts that every node below `src` can reference its children.
+    #[cfg(all(debug_assertions, feature = "alloc"))]
+    fn sanity_check(&self, src: &[Node<'a, T>]) {
+        let mut stack = vec![src];
+        while let Some(src) = stack.pop() {
+            for node in src.iter() {
+                self.buffers.assert_can_reference(src, node.children);
+                stack.push(node.children);
+            }
         }
     }
 
@@ -365,10 +1257,34 @@ impl<'a, T> SlidingTreeState<'a, T> {
         I: IntoIterator<Item = T>,
     {
         self.borrow_buffers()
-            .alloc_iter(iter.into_iter().map(|data| Node {
-                data,
-                children: &mut [],
-            }))
+            .alloc_iter(iter.into_iter().map(Node::leaf))
+    }
+
+    fn try_alloc_iter<I>(
+        &self,
+        iter: I,
+    ) -> Result<&'a mut [Node<'a, T>], AllocError>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.borrow_buffers()
+            .try_alloc_iter(iter.into_iter().map(Node::leaf))
+    }
+
+    fn alloc_chain<I>(&self, iter: I) -> &'a mut [Node<'a, T>]
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut run = self.alloc_iter(iter);
+        // Each node takes the next one as its children, working back from the
+        // end of the run so that the slices stay disjoint.
+        while run.len() > 1 {
+            let len = run.len();
+            let (init, last) = mem::take(&mut run).split_at_mut(len - 1);
+            init[len - 2].children = last;
+            run = init;
+        }
+        run
     }
 
     fn alloc_iter_recursive<I, F, U>(
@@ -382,10 +1298,7 @@ impl<'a, T> SlidingTreeState<'a, T> {
     {
         self.borrow_buffers().alloc_iter(iter.into_iter().map(
             |(data, recursion)| {
-                let mut node = Node {
-                    data,
-                    children: &mut [],
-                };
+                let mut node = Node::leaf(data);
                 let node_mut = NodeMut {
                     node: &mut node,
                     state: self,
@@ -395,6 +1308,159 @@ impl<'a, T> SlidingTreeState<'a, T> {
             },
         ))
     }
+
+    fn try_alloc_iter_recursive<I, F, U>(
+        &self,
+        iter: I,
+        mut builder: F,
+    ) -> Result<&'a mut [Node<'a, T>], AllocError>
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(NodeMut<'a, '_, T>, U) -> Result<(), AllocError>,
+    {
+        let mut result = Ok(());
+        let slice = self.borrow_buffers().try_alloc_iter(UntilErr {
+            iter: iter.into_iter(),
+            result: &mut result,
+            f: |(data, recursion)| {
+                let mut node = Node::leaf(data);
+                let node_mut = NodeMut {
+                    node: &mut node,
+                    state: self,
+                };
+                builder(node_mut, recursion).map(|()| node)
+            },
+        })?;
+        result.map(|()| slice)
+    }
+}
+
+/// A slice of nodes moved during compaction, with the node whose children
+/// it is, which is null for the roots.
+#[cfg(feature = "alloc")]
+struct Moved<'a, T> {
+    old: *mut Node<'a, T>,
+    new: *mut Node<'a, T>,
+    len: usize,
+    parent: *mut Node<'a, T>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, T> SlidingTreeState<'a, T> {
+    /// Moves the nodes of `roots` and all of their descendants into newly
+    /// allocated slices, in breadth-first order, and returns the new roots.
+    ///
+    /// The record of the moved slices doubles as the queue of slices whose
+    /// children are still to be moved. If an allocation fails, every node is
+    /// moved back to its old slot before the error is returned.
+    fn try_move_tree(
+        &self,
+        roots: &mut [Node<'a, T>],
+    ) -> Result<&'a mut [Node<'a, T>], AllocError> {
+        let mut moved = Vec::new();
+        let mut result =
+            self.try_move_slice(roots, ptr::null_mut(), &mut moved);
+        let mut next = 0;
+        while result.is_ok() && next < moved.len() {
+            let Moved { new, len, .. } = moved[next];
+            next += 1;
+            for index in 0..len {
+                // SAFETY: The new slice was allocated by `try_move_slice` and
+                // is only accessed through this pointer.
+                let node = unsafe { &mut *new.add(index) };
+                if node.children.is_empty() {
+                    continue;
+                }
+                let children: *mut [Node<'a, T>] = node.children;
+                // SAFETY: Each old slice of children is reachable only
+                // through its parent, which has just been moved.
+                let children = unsafe { &mut *children };
+                result = self.try_move_slice(children, node, &mut moved);
+                if result.is_err() {
+                    break;
+                }
+            }
+        }
+        if let Err(err) = result {
+            for moved in moved.iter().rev() {
+                // SAFETY: The slices recorded in `moved` are disjoint and
+                // still allocated, and each parent is in a newer slice than
+                // its children, so it has not been moved back yet.
+                unsafe {
+                    let old = slice::from_raw_parts_mut(moved.old, moved.len);
+                    let new = slice::from_raw_parts_mut(moved.new, moved.len);
+                    for (old, new) in old.iter_mut().zip(new) {
+                        *old = new.take();
+                    }
+                    if let Some(parent) = moved.parent.as_mut() {
+                        parent.children = old;
+                    }
+                }
+            }
+            return Err(err);
+        }
+        // SAFETY: The roots were the first slice to be moved.
+        Ok(unsafe { slice::from_raw_parts_mut(moved[0].new, moved[0].len) })
+    }
+
+    /// Moves the nodes of `old` into a new slice, leaving tombstones behind,
+    /// and makes the new slice the children of `parent` if it is not null.
+    fn try_move_slice(
+        &self,
+        old: &mut [Node<'a, T>],
+        parent: *mut Node<'a, T>,
+        moved: &mut Vec<Moved<'a, T>>,
+    ) -> Result<(), AllocError> {
+        // An exact size hint means that nothing is taken if this fails.
+        let new = self
+            .borrow_buffers()
+            .try_alloc_iter(old.iter_mut().map(Node::take))?;
+        moved.push(Moved {
+            old: old.as_mut_ptr(),
+            new: new.as_mut_ptr(),
+            len: new.len(),
+            parent,
+        });
+        // SAFETY: The parent is a node in a slice moved earlier.
+        if let Some(parent) = unsafe { parent.as_mut() } {
+            parent.children = new;
+        }
+        Ok(())
+    }
+}
+
+/// An iterator adapter which maps items with a fallible function, stopping
+/// and recording the error at the first failure.
+struct UntilErr<'r, I, F> {
+    iter: I,
+    result: &'r mut Result<(), AllocError>,
+    f: F,
+}
+
+impl<I, F, R> Iterator for UntilErr<'_, I, F>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> Result<R, AllocError>,
+{
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        self.result.as_ref().ok()?;
+        match (self.f)(self.iter.next()?) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                *self.result = Err(err);
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.result {
+            Ok(()) => self.iter.size_hint(),
+            Err(_) => (0, Some(0)),
+        }
+    }
 }
 
 /// A tree that grows from the leaves and recedes from the root.
@@ -404,9 +1470,27 @@ impl<'a, T> SlidingTreeState<'a, T> {
 /// allocated efficiently using a [`crate::SlidingBuffers`]. Hence, the root
 /// of the tree can be advanced through the tree making ancestor nodes
 /// inaccessible and allowing their memory to be reused.
+///
+/// A `SlidingTree` is [`Send`] if `T` is, so it can be handed to another
+/// thread between moves. This is sound because the tree owns its buffers and
+/// the references between nodes only point into them, while no reference to
+/// a node can outlive a borrow of the tree itself. Moving the tree does not
+/// move the buffers, so these references stay valid. The interior mutability
+/// used while traversing the tree makes it `!Sync`.
 pub struct SlidingTree<'a, T> {
     roots: RefSliceCell<'a, Node<'a, T>>,
     state: SlidingTreeState<'a, T>,
+    #[cfg(feature = "alloc")]
+    auto_compact: Option<AutoCompact>,
+}
+
+/// The settings of automatic compaction, with the number of allocated nodes
+/// at which the threshold is next checked.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Copy)]
+struct AutoCompact {
+    threshold: f32,
+    next_check: usize,
 }
 
 impl<'a, T> SlidingTree<'a, T> {
@@ -419,8 +1503,20 @@ impl<'a, T> SlidingTree<'a, T> {
         }
     }
 
+    fn with_buffers(
+        buffers: SlidingBuffers<Node<'a, T>>,
+    ) -> SlidingTree<'a, T> {
+        SlidingTree {
+            roots: RefSliceCell::new(&mut []),
+            state: SlidingTreeState::with_buffers(buffers),
+            #[cfg(feature = "alloc")]
+            auto_compact: None,
+        }
+    }
+
     /// Creates a new empty `SlidingTree` with a default capacity based on
     /// the size of `T`.
+    #[cfg(feature = "alloc")]
     pub fn new() -> SlidingTree<'a, T> {
         SlidingTree::with_capacity(1000000 / size_of::<T>().max(1))
     }
@@ -429,11 +1525,41 @@ impl<'a, T> SlidingTree<'a, T> {
     ///
     /// The `capacity` is the maximum number of nodes that can be allocated
     /// in a single buffer.
+    #[cfg(feature = "alloc")]
     pub fn with_capacity(capacity: usize) -> SlidingTree<'a, T> {
-        SlidingTree {
-            roots: RefSliceCell::new(&mut []),
-            state: SlidingTreeState::with_capacity(capacity),
-        }
+        SlidingTree::with_buffers(SlidingBuffers::with_capacity(capacity))
+    }
+
+    /// Creates a new empty `SlidingTree` with the specified capacity, where
+    /// each buffer is a separate `mmap` mapping configured by `options`.
+    #[cfg(all(feature = "mmap", target_os = "linux"))]
+    pub fn with_mmap(
+        capacity: usize,
+        options: MmapOptions,
+    ) -> SlidingTree<'a, T> {
+        SlidingTree::with_buffers(SlidingBuffers::with_mmap(capacity, options))
+    }
+
+    /// Creates a new empty `SlidingTree` which allocates nodes only from
+    /// `region`, divided into `buffer_count` buffers.
+    ///
+    /// No heap memory is used. Once every buffer holds reachable nodes,
+    /// growing the tree fails and the `try_` methods of [`HasChildrenMut`]
+    /// return an [`AllocError`]. See
+    /// [`SlidingBuffers::with_static_storage`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is too small to hold at least one node in each
+    /// buffer.
+    pub fn with_static_storage(
+        region: &'static mut [MaybeUninit<u8>],
+        buffer_count: usize,
+    ) -> SlidingTree<'a, T> {
+        SlidingTree::with_buffers(SlidingBuffers::with_static_storage(
+            region,
+            buffer_count,
+        ))
     }
 
     /// Preallocates recycled buffers.
@@ -455,19 +1581,8 @@ impl<'a, T> SlidingTree<'a, T> {
     /// Recycles buffers containing nodes that are no longer accessible.
     pub fn recycle(&mut self) {
         self.process_pending_roots();
-        #[cfg(debug_assertions)]
-        {
-            fn sanity_check<'a, T>(
-                src: &[Node<'a, T>],
-                state: &SlidingTreeState<'a, T>,
-            ) {
-                for node in src.iter() {
-                    state.buffers.assert_can_reference(src, node.children);
-                    sanity_check(node.children, state);
-                }
-            }
-            sanity_check(self.roots.get(), &self.state);
-        }
+        #[cfg(all(debug_assertions, feature = "alloc"))]
+        self.state.sanity_check(self.roots.get());
         if self.roots.get().is_empty() {
             self.clear();
         } else {
@@ -476,7 +1591,159 @@ impl<'a, T> SlidingTree<'a, T> {
             unsafe {
                 self.state.buffers.recycle_older_than(self.roots.get());
             }
+            #[cfg(feature = "alloc")]
+            self.auto_compact();
+        }
+    }
+
+    /// Recycles every buffer which does not contain any nodes reachable from
+    /// the roots, without moving any nodes.
+    ///
+    /// Unlike [`Self::recycle`], which can only reuse buffers allocated
+    /// entirely before the roots, this also reclaims newer buffers, such as
+    /// those left holding only the subtrees pruned with `set_children`. It
+    /// walks the whole tree to find the buffers which are still in use, so it
+    /// takes time proportional to the number of reachable nodes.
+    #[cfg(feature = "alloc")]
+    pub fn recycle_unreachable(&mut self) {
+        self.process_pending_roots();
+        if self.roots.get().is_empty() {
+            self.clear();
+            return;
+        }
+        let mut marks = self.state.buffers.index();
+        // The slices are walked from an explicit stack so that deep trees
+        // cannot overflow the call stack.
+        let mut stack = vec![self.roots.get()];
+        while let Some(nodes) = stack.pop() {
+            assert!(
+                marks.mark(nodes),
+                "slice not present in this SlidingBuffers"
+            );
+            stack.extend(
+                nodes
+                    .iter()
+                    .map(|node| &*node.children)
+                    .filter(|children| !children.is_empty()),
+            );
+        }
+        // SAFETY: Every buffer holding a reachable node has been marked, so
+        // the nodes in the other buffers are inaccessible.
+        unsafe { self.state.buffers.recycle_unmarked_in(marks) };
+    }
+
+    /// Returns the number of nodes reachable from the roots.
+    #[cfg(feature = "alloc")]
+    fn count_reachable(&self) -> usize {
+        self.process_pending_roots();
+        // The slices are walked from an explicit stack so that deep trees
+        // cannot overflow the call stack.
+        let mut stack = vec![self.roots.get()];
+        let mut count = 0;
+        while let Some(nodes) = stack.pop() {
+            count += nodes.len();
+            stack.extend(
+                nodes
+                    .iter()
+                    .map(|node| &*node.children)
+                    .filter(|children| !children.is_empty()),
+            );
+        }
+        count
+    }
+
+    /// Returns the fraction of the nodes held in the buffers which are no
+    /// longer reachable from the roots.
+    ///
+    /// This is 0.0 for an empty tree. It takes time proportional to the number
+    /// of reachable nodes.
+    #[cfg(feature = "alloc")]
+    pub fn fragmentation(&self) -> f32 {
+        let reachable = self.count_reachable();
+        let allocated = self.state.buffers.allocated();
+        if allocated == 0 {
+            return 0.0;
+        }
+        1.0 - reachable as f32 / allocated as f32
+    }
+
+    /// Relocates the reachable nodes into fresh buffers and recycles all of
+    /// the old buffers.
+    ///
+    /// Unlike [`Self::recycle`], which can only reuse buffers allocated
+    /// entirely before the roots, this also reclaims unreachable nodes
+    /// allocated after the roots, such as the former siblings of nodes which
+    /// were moved to the root. The nodes are moved rather than copied, in
+    /// breadth-first order, so that the nodes near the roots end up close
+    /// together.
+    ///
+    /// If the new buffers cannot be allocated, an error is returned and the
+    /// tree is left unchanged.
+    #[cfg(feature = "alloc")]
+    pub fn compact(&mut self) -> Result<(), AllocError> {
+        self.process_pending_roots();
+        if self.roots.get().is_empty() {
+            self.clear();
+            return Ok(());
+        }
+        let checkpoint = self.state.buffers.finish_current();
+        match self.state.try_move_tree(self.roots.get_mut()) {
+            Ok(new_roots) => {
+                self.roots = RefSliceCell::new(new_roots);
+                // SAFETY: The new roots were allocated after all of the old
+                // nodes, none of which are reachable any more.
+                unsafe {
+                    self.state.buffers.recycle_older_than(self.roots.get());
+                }
+                Ok(())
+            }
+            Err(err) => {
+                // SAFETY: Every node has been moved back to its old slot, so
+                // the new buffers only hold tombstones.
+                unsafe { self.state.buffers.rollback(checkpoint) };
+                Err(err)
+            }
+        }
+    }
+
+    /// Sets a fragmentation threshold above which [`Self::recycle`]
+    /// automatically calls [`Self::compact`], or disables automatic
+    /// compaction if `None`.
+    ///
+    /// Counting the reachable nodes takes time proportional to their number,
+    /// so the threshold is only checked once as many nodes have been
+    /// allocated since the last check as were reachable then, which keeps the
+    /// cost in proportion to the allocations. Automatic compaction is skipped
+    /// if it fails to allocate.
+    #[cfg(feature = "alloc")]
+    pub fn set_compaction_threshold(&mut self, threshold: Option<f32>) {
+        self.auto_compact = threshold.map(|threshold| AutoCompact {
+            threshold,
+            next_check: 0,
+        });
+    }
+
+    /// Compacts the tree if automatic compaction is enabled, the threshold is
+    /// due to be checked, and the fragmentation is above it.
+    #[cfg(feature = "alloc")]
+    fn auto_compact(&mut self) {
+        let Some(auto) = self.auto_compact else {
+            return;
+        };
+        let mut allocated = self.state.buffers.allocated();
+        if allocated < auto.next_check {
+            return;
+        }
+        let reachable = self.count_reachable();
+        let fragmentation = 1.0 - reachable as f32 / allocated as f32;
+        // On failure the tree is left as it was.
+        if fragmentation > auto.threshold && self.compact().is_ok() {
+            allocated = reachable;
         }
+        self.auto_compact = Some(AutoCompact {
+            threshold: auto.threshold,
+            next_check: allocated + reachable.max(1),
+        });
     }
 
     /// Frees unused buffers to reduce memory usage.
@@ -493,6 +1760,19 @@ impl<'a, T> SlidingTree<'a, T> {
     pub fn buffer_stats(&self) -> (usize, usize, usize) {
         self.state.buffers.buffer_stats()
     }
+
+    /// Returns a read-only view of the tree which can be shared between
+    /// threads.
+    ///
+    /// The tree cannot be modified while the view exists, so the view does
+    /// not need the interior mutability of the tree itself and is [`Sync`]
+    /// if `T` is.
+    pub fn freeze(&mut self) -> FrozenTree<'a, '_, T> {
+        self.process_pending_roots();
+        FrozenTree {
+            roots: self.roots.get_mut(),
+        }
+    }
 }
 
 impl<'a, T> Debug for SlidingTree<'a, T>
@@ -507,92 +1787,69 @@ where
     }
 }
 
+#[cfg(feature = "alloc")]
 impl<'a, T> Default for SlidingTree<'a, T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<'a, T> HasChildren<'a, T> for SlidingTree<'a, T> {
-    fn children(&self) -> &[Node<'a, T>] {
-        self.process_pending_roots();
-        self.roots.get()
-    }
+impl_roots!(SlidingTree<'a, T>);
 
-    fn is_empty(&self) -> bool {
-        self.process_pending_roots();
-        self.roots.get().is_empty()
-    }
+/// A read-only view of a [`SlidingTree`] which can be shared between threads.
+///
+/// This is created by [`SlidingTree::freeze`], or by `SlidingForest::tree`
+/// for a tree in a forest. It provides the same access to the roots as
+/// [`HasChildren`] does for the tree, and is [`Sync`] if `T` is.
+pub struct FrozenTree<'a, 'b, T> {
+    roots: &'b [Node<'a, T>],
+}
 
-    fn len(&self) -> usize {
-        self.process_pending_roots();
-        self.roots.get().len()
+impl<T> Clone for FrozenTree<'_, '_, T> {
+    fn clone(&self) -> Self {
+        *self
     }
+}
 
-    fn iter(&self) -> slice::Iter<'_, Node<'a, T>> {
-        self.process_pending_roots();
-        self.roots.get().iter()
-    }
+impl<T> Copy for FrozenTree<'_, '_, T> {}
 
-    fn at(&self, index: usize) -> &Node<'a, T> {
-        self.process_pending_roots();
-        &self.roots.get()[index]
+impl<'a, T> Debug for FrozenTree<'a, '_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrozenTree")
+            .field("roots", &self.roots)
+            .finish()
     }
 }
 
-impl<'a, T> HasChildrenMut<'a, T> for SlidingTree<'a, T> {
-    fn children_mut(&mut self) -> NodeChildrenMut<'a, '_, T> {
-        self.process_pending_roots();
-        NodeChildrenMut {
-            children: self.roots.get_mut(),
-            state: &self.state,
-        }
-    }
-
-    fn set_children<I>(&mut self, iterable: I)
-    where
-        I: IntoIterator<Item = T>,
-    {
-        self.roots = RefSliceCell::new(self.state.alloc_iter(iterable));
-        self.state.pending_roots.set(None);
+impl<'a, T> HasChildren<'a, T> for FrozenTree<'a, '_, T> {
+    fn children(&self) -> &[Node<'a, T>] {
+        self.roots
     }
 
-    fn set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
-    where
-        I: IntoIterator<Item = (T, U)>,
-        F: FnMut(NodeMut<'a, '_, T>, U),
-    {
-        self.roots = RefSliceCell::new(
-            self.state.alloc_iter_recursive(iterable, builder),
-        );
-        self.state.pending_roots.set(None);
+    fn is_empty(&self) -> bool {
+        self.roots.is_empty()
     }
 
-    fn adopt_grandchildren_at(&mut self, index: usize) {
-        self.process_pending_roots();
-        let node = &mut self.roots.get_mut()[index];
-        self.roots = RefSliceCell::new(mem::take(&mut node.children));
-        self.state.pending_roots.set(None);
+    fn len(&self) -> usize {
+        self.roots.len()
     }
 
-    fn move_children_to_root(&mut self) {
-        self.process_pending_roots();
-        // This is already the root.
+    fn iter(&self) -> slice::Iter<'_, Node<'a, T>> {
+        self.roots.iter()
     }
 
-    fn iter_mut(&mut self) -> NodeIterMut<'a, '_, T> {
-        self.process_pending_roots();
-        NodeIterMut {
-            iter: self.roots.get_mut().iter_mut(),
-            state: &self.state,
-        }
+    fn at(&self, index: usize) -> &Node<'a, T> {
+        &self.roots[index]
     }
 
-    fn at_mut(&mut self, index: usize) -> NodeMut<'a, '_, T> {
-        self.process_pending_roots();
-        NodeMut {
-            node: &mut self.roots.get_mut()[index],
-            state: &self.state,
-        }
+    #[cfg(feature = "rayon")]
+    fn par_iter(&self) -> rayon::slice::Iter<'_, Node<'a, T>>
+    where
+        T: Sync,
+    {
+        self.roots.par_iter()
     }
 }
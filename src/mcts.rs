@@ -0,0 +1,1105 @@
+use alloc::vec::Vec;
+use core::{
+    f32,
+    fmt::{self, Debug, Formatter},
+    mem, slice,
+};
+
+use crate::{HasChildren, HasChildrenMut, Node, NodeMut, SlidingTree};
+
+/// A game which can be played by a [`Search`].
+///
+/// Values are given from the point of view of a particular player, from 0.0
+/// for a loss to 1.0 for a win. The game is assumed to be zero-sum between
+/// two players, so that the value for one player is one minus the value for
+/// the other.
+pub trait Game: Clone {
+    /// The type of a move.
+    type Move: Clone + PartialEq;
+    /// The type identifying a player.
+    type Player: Copy + PartialEq;
+
+    /// Returns the player to move.
+    fn player_to_move(&self) -> Self::Player;
+
+    /// Appends the legal moves to `moves`, which is empty.
+    ///
+    /// A state which is not terminal must have at least one legal move.
+    fn legal_moves(&self, moves: &mut Vec<Self::Move>);
+
+    /// Applies a legal move.
+    fn apply(&mut self, mv: &Self::Move);
+
+    /// Returns the value of the state for `player` if it is terminal, or
+    /// `None` otherwise.
+    fn terminal_value(&self, player: Self::Player) -> Option<f32>;
+}
+
+/// An evaluator of the states of a [`Game`] which are reached by a
+/// [`Search`], such as a neural network with value and policy heads.
+pub trait Evaluator<G: Game> {
+    /// Evaluates a state which is not terminal, returning its value for the
+    /// player to move.
+    ///
+    /// The prior probability of each of the legal `moves` must be pushed to
+    /// `priors`, which is empty, in the same order.
+    fn evaluate(
+        &mut self,
+        state: &G,
+        moves: &[G::Move],
+        priors: &mut Vec<f32>,
+    ) -> f32;
+
+    /// Returns the moves played by the last call to [`Self::evaluate`], with
+    /// the player who made each, such as those of a rollout.
+    ///
+    /// These are included in the AMAF statistics when RAVE is enabled. The
+    /// default implementation returns no moves.
+    fn playout(&self) -> &[(G::Player, G::Move)] {
+        &[]
+    }
+}
+
+/// The default [`Evaluator`], which plays random moves until the end of the
+/// game and gives every move the same prior probability.
+pub struct Rollout<G: Game> {
+    rng: Rng,
+    moves: Vec<G::Move>,
+    playout: Vec<(G::Player, G::Move)>,
+}
+
+impl<G: Game> Rollout<G> {
+    /// Creates a new `Rollout` with the given random seed.
+    pub fn new(seed: u64) -> Rollout<G> {
+        Rollout {
+            rng: Rng(seed),
+            moves: Vec::new(),
+            playout: Vec::new(),
+        }
+    }
+}
+
+impl<G: Game> Default for Rollout<G> {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<G: Game> Debug for Rollout<G> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rollout").finish_non_exhaustive()
+    }
+}
+
+impl<G: Game> Evaluator<G> for Rollout<G> {
+    fn evaluate(
+        &mut self,
+        state: &G,
+        moves: &[G::Move],
+        priors: &mut Vec<f32>,
+    ) -> f32 {
+        priors.resize(moves.len(), 1.0 / moves.len() as f32);
+        self.playout.clear();
+        let player = state.player_to_move();
+        let mut state = state.clone();
+        loop {
+            if let Some(value) = state.terminal_value(player) {
+                return value;
+            }
+            self.moves.clear();
+            state.legal_moves(&mut self.moves);
+            assert!(
+                !self.moves.is_empty(),
+                "non-terminal state has no legal moves"
+            );
+            let index = self.rng.below(self.moves.len());
+            let mv = self.moves.swap_remove(index);
+            let mover = state.player_to_move();
+            state.apply(&mv);
+            self.playout.push((mover, mv));
+        }
+    }
+
+    fn playout(&self) -> &[(G::Player, G::Move)] {
+        &self.playout
+    }
+}
+
+/// The policy used by a [`Search`] to select which child to descend into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Selection {
+    /// UCB1, which maximises `q + c * sqrt(ln(N) / n)` for a child with mean
+    /// value `q` and `n` visits out of `N` visits to its parent, ignoring the
+    /// priors.
+    #[default]
+    Ucb1,
+    /// PUCT, which maximises `q + c * p * sqrt(N) / (1 + n)` for a child with
+    /// prior probability `p`.
+    Puct,
+}
+
+/// The schedule by which a [`Search`] with RAVE weights the AMAF value of a
+/// child against its mean value.
+///
+/// The weight given to the AMAF value falls from 1.0 for a child which has
+/// not been visited towards zero as its visits increase.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RaveSchedule {
+    /// Weights the AMAF value by `sqrt(k / (3 * n + k))` for a child with `n`
+    /// visits, so that both values count equally when `n` is `k`.
+    Equivalence(f32),
+    /// Weights the AMAF value by `m / (n + m + 4 * b * b * n * m)` for a
+    /// child with `n` visits and `m` AMAF visits, where `b` is the expected
+    /// bias of the AMAF value.
+    MinimumError(f32),
+}
+
+impl RaveSchedule {
+    fn weight(self, visits: u32, amaf_visits: u32) -> f32 {
+        let n = visits as f32;
+        let m = amaf_visits as f32;
+        match self {
+            RaveSchedule::Equivalence(k) => libm::sqrtf(k / (3.0 * n + k)),
+            RaveSchedule::MinimumError(b) => m / (n + m + 4.0 * b * b * n * m),
+        }
+    }
+}
+
+/// Options for a [`Search`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchOptions {
+    selection: Selection,
+    exploration: f32,
+    first_play_urgency: Option<f32>,
+    noise_alpha: f32,
+    noise_fraction: f32,
+    rave: Option<RaveSchedule>,
+    virtual_loss: u32,
+    solver: bool,
+    seed: u64,
+}
+
+impl SearchOptions {
+    /// Creates a new set of options using UCB1 with an exploration constant
+    /// of √2, without root noise, RAVE or the solver.
+    pub fn new() -> SearchOptions {
+        SearchOptions {
+            selection: Selection::Ucb1,
+            exploration: f32::consts::SQRT_2,
+            first_play_urgency: None,
+            noise_alpha: 0.3,
+            noise_fraction: 0.0,
+            rave: None,
+            virtual_loss: 1,
+            solver: false,
+            seed: 0,
+        }
+    }
+
+    /// Sets the selection policy.
+    pub fn selection(mut self, selection: Selection) -> SearchOptions {
+        self.selection = selection;
+        self
+    }
+
+    /// Sets the exploration constant `c` of the selection policy.
+    pub fn exploration(mut self, c: f32) -> SearchOptions {
+        self.exploration = c;
+        self
+    }
+
+    /// Sets the first-play urgency, the mean value assumed for a child which
+    /// has not been visited.
+    ///
+    /// By default, unvisited children are selected before any other under
+    /// UCB1, and are assumed to be losses under PUCT.
+    pub fn first_play_urgency(mut self, value: f32) -> SearchOptions {
+        self.first_play_urgency = Some(value);
+        self
+    }
+
+    /// Mixes Dirichlet noise with concentration `alpha` into the priors of
+    /// the moves from the root state, replacing the given `fraction` of each
+    /// prior.
+    ///
+    /// New noise is drawn for each call to [`Search::run`]. This only has an
+    /// effect under PUCT.
+    pub fn root_noise(mut self, alpha: f32, fraction: f32) -> SearchOptions {
+        self.noise_alpha = alpha;
+        self.noise_fraction = fraction;
+        self
+    }
+
+    /// Enables Rapid Action Value Estimation, blending the mean value of each
+    /// child with its all-moves-as-first (AMAF) value on the given schedule.
+    ///
+    /// After each iteration, the AMAF statistics are updated for every
+    /// sibling of each node on the selected path whose move was made later
+    /// in the iteration by the same player, including moves played by the
+    /// [`Evaluator`]. Under UCB1, children which have not been visited are
+    /// still scored by the first-play urgency.
+    pub fn rave(mut self, schedule: RaveSchedule) -> SearchOptions {
+        self.rave = Some(schedule);
+        self
+    }
+
+    /// Sets the number of lost visits added to each node on the path to a
+    /// leaf which is waiting to be evaluated by [`Search::run_batched`], so
+    /// that later descents in the same batch are steered elsewhere.
+    ///
+    /// The default is one visit.
+    pub fn virtual_loss(mut self, visits: u32) -> SearchOptions {
+        self.virtual_loss = visits;
+        self
+    }
+
+    /// Enables or disables the MCTS-Solver, which proves the values of moves
+    /// whose outcome is certain.
+    ///
+    /// A move reaching a terminal state is proven to have its terminal value.
+    /// A move is also proven once the best reply to it is proven to win, or
+    /// every reply is proven, and its value is then that of the best reply
+    /// for the opponent. Proven moves are never selected again, and a search
+    /// stops early once the value of the root state is proven.
+    pub fn solver(mut self, enabled: bool) -> SearchOptions {
+        self.solver = enabled;
+        self
+    }
+
+    /// Sets the seed of the random number generators used for rollouts and
+    /// root noise.
+    pub fn seed(mut self, seed: u64) -> SearchOptions {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The statistics of a move in a [`Search`], which are the payloads of its
+/// tree.
+pub struct SearchNode<G: Game> {
+    mv: G::Move,
+    player: G::Player,
+    prior: f32,
+    visits: u32,
+    total: f32,
+    amaf_visits: u32,
+    amaf_total: f32,
+    in_flight: u32,
+    proven: Option<f32>,
+}
+
+impl<G: Game> SearchNode<G> {
+    fn new(mv: G::Move, player: G::Player, prior: f32) -> Self {
+        SearchNode {
+            mv,
+            player,
+            prior,
+            visits: 0,
+            total: 0.0,
+            amaf_visits: 0,
+            amaf_total: 0.0,
+            in_flight: 0,
+            proven: None,
+        }
+    }
+
+    /// Returns the move leading to this node.
+    pub fn mv(&self) -> &G::Move {
+        &self.mv
+    }
+
+    /// Returns the player who made the move.
+    pub fn player(&self) -> G::Player {
+        self.player
+    }
+
+    /// Returns the prior probability of the move given by the [`Evaluator`].
+    pub fn prior(&self) -> f32 {
+        self.prior
+    }
+
+    /// Returns the number of times this node has been visited.
+    pub fn visits(&self) -> u32 {
+        self.visits
+    }
+
+    /// Returns the number of iterations passing through this node which are
+    /// waiting for a leaf to be evaluated by [`Search::run_batched`].
+    pub fn in_flight(&self) -> u32 {
+        self.in_flight
+    }
+
+    /// Returns the mean value of the move for the player who made it, or 0.0
+    /// if it has not been visited.
+    pub fn value(&self) -> f32 {
+        if self.visits > 0 {
+            self.total / self.visits as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the value of the move for the player who made it if it has
+    /// been proven by the solver, or `None` otherwise.
+    ///
+    /// This is 1.0 for a win and 0.0 for a loss, or the value of a terminal
+    /// state in between, such as a draw.
+    pub fn proven(&self) -> Option<f32> {
+        self.proven
+    }
+
+    fn update(&mut self, result: (G::Player, f32)) {
+        self.visits += 1;
+        self.total += value_for(self.player, result);
+    }
+
+    /// Returns the number of AMAF updates this node has received.
+    pub fn amaf_visits(&self) -> u32 {
+        self.amaf_visits
+    }
+
+    /// Returns the mean AMAF value of the move for the player who made it,
+    /// or 0.0 if it has not received any AMAF updates.
+    pub fn amaf_value(&self) -> f32 {
+        if self.amaf_visits > 0 {
+            self.amaf_total / self.amaf_visits as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+impl<G: Game> Debug for SearchNode<G>
+where
+    G::Move: Debug,
+    G::Player: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SearchNode")
+            .field("mv", &self.mv)
+            .field("player", &self.player)
+            .field("prior", &self.prior)
+            .field("visits", &self.visits)
+            .field("value", &self.value())
+            .field("amaf_visits", &self.amaf_visits)
+            .field("amaf_value", &self.amaf_value())
+            .field("in_flight", &self.in_flight)
+            .field("proven", &self.proven)
+            .finish()
+    }
+}
+
+/// Returns the value for `player` of a result given as a player and its value
+/// for them.
+fn value_for<P: PartialEq>(player: P, (to, value): (P, f32)) -> f32 {
+    if to == player { value } else { 1.0 - value }
+}
+
+/// Returns the player to move and the best value of a state for them if it
+/// has been proven by its children, which are the moves from the state.
+///
+/// This is the case if a child is proven to win, or every child is proven.
+fn proven_value<G: Game>(
+    children: slice::Iter<'_, Node<'_, SearchNode<G>>>,
+) -> Option<(G::Player, f32)> {
+    let mut best: Option<(G::Player, f32)> = None;
+    for stats in children.map(Node::get) {
+        let value = stats.proven?;
+        if value >= 1.0 {
+            return Some((stats.player, value));
+        }
+        if best.is_none_or(|(_, best)| value > best) {
+            best = Some((stats.player, value));
+        }
+    }
+    best
+}
+
+/// A small SplitMix64 generator, so that the search does not depend on a
+/// random number crate.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a uniformly distributed index below `n`.
+    fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+
+    /// Returns a uniformly distributed number in (0, 1].
+    fn open_unit(&mut self) -> f32 {
+        ((self.next_u64() >> 40) + 1) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Returns a standard normal sample, using the Box-Muller transform.
+    fn normal(&mut self) -> f32 {
+        let radius = libm::sqrtf(-2.0 * libm::logf(self.open_unit()));
+        radius * libm::cosf(2.0 * f32::consts::PI * self.open_unit())
+    }
+
+    /// Returns a sample from the gamma distribution with shape `alpha` and
+    /// unit scale, using the method of Marsaglia and Tsang.
+    fn gamma(&mut self, alpha: f32) -> f32 {
+        if alpha < 1.0 {
+            let boost = libm::powf(self.open_unit(), 1.0 / alpha);
+            return self.gamma(alpha + 1.0) * boost;
+        }
+        let d = alpha - 1.0 / 3.0;
+        let c = 1.0 / libm::sqrtf(9.0 * d);
+        loop {
+            let x = self.normal();
+            let v = 1.0 + c * x;
+            if v <= 0.0 {
+                continue;
+            }
+            let v = v * v * v;
+            let u = self.open_unit();
+            if libm::logf(u) < 0.5 * x * x + d - d * v + d * libm::logf(v) {
+                return d * v;
+            }
+        }
+    }
+
+    /// Fills `samples` with a sample from the symmetric Dirichlet
+    /// distribution with concentration `alpha`.
+    fn dirichlet(&mut self, alpha: f32, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            *sample = self.gamma(alpha);
+        }
+        let sum: f32 = samples.iter().sum();
+        if sum > 0.0 {
+            for sample in samples {
+                *sample /= sum;
+            }
+        }
+    }
+}
+
+/// The outcome of a descent through the tree.
+#[derive(Clone, Copy)]
+enum Descent<P> {
+    /// The iteration is complete, with a player and its value for them.
+    Value(P, f32),
+    /// An unexpanded leaf was left for a batch evaluation.
+    Leaf,
+    /// An unexpanded leaf was reached which is already in the batch.
+    Collision,
+}
+
+/// A state reached by [`Search::run_batched`] which is waiting to be
+/// evaluated.
+pub struct Leaf<G: Game> {
+    path: Vec<usize>,
+    state: G,
+    moves: Vec<G::Move>,
+    priors: Vec<f32>,
+    value: f32,
+}
+
+impl<G: Game> Leaf<G> {
+    fn new(path: Vec<usize>, state: G) -> Leaf<G> {
+        let mut moves = Vec::new();
+        state.legal_moves(&mut moves);
+        assert!(!moves.is_empty(), "non-terminal state has no legal moves");
+        Leaf {
+            path,
+            state,
+            moves,
+            priors: Vec::new(),
+            value: 0.0,
+        }
+    }
+
+    /// Returns the indices of the nodes leading to the leaf from the roots
+    /// of the tree, which is empty if the leaf is the root state.
+    pub fn path(&self) -> &[usize] {
+        &self.path
+    }
+
+    /// Returns the state to be evaluated.
+    pub fn state(&self) -> &G {
+        &self.state
+    }
+
+    /// Returns the legal moves from the state.
+    pub fn moves(&self) -> &[G::Move] {
+        &self.moves
+    }
+
+    /// Sets the value of the state for the player to move, and the prior
+    /// probability of each of the legal moves in the same order, as with
+    /// [`Evaluator::evaluate`].
+    pub fn set_evaluation(&mut self, value: f32, priors: &[f32]) {
+        self.value = value;
+        self.priors.clear();
+        self.priors.extend_from_slice(priors);
+    }
+}
+
+impl<G: Game + Debug> Debug for Leaf<G>
+where
+    G::Move: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Leaf")
+            .field("path", &self.path)
+            .field("state", &self.state)
+            .field("moves", &self.moves)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The parts of a [`Search`] used while descending the tree.
+struct Context<G: Game, E> {
+    options: SearchOptions,
+    evaluator: E,
+    rng: Rng,
+    moves: Vec<G::Move>,
+    priors: Vec<f32>,
+    noise: Vec<f32>,
+    path: Vec<usize>,
+    playout: Vec<(G::Player, G::Move)>,
+}
+
+impl<G: Game, E: Evaluator<G>> Context<G, E> {
+    fn is_terminal(state: &G) -> bool {
+        state.terminal_value(state.player_to_move()).is_some()
+    }
+
+    /// Sets the children of `node` to the moves from `state`, with the
+    /// priors given by the evaluator, and returns the player to move and
+    /// the value of `state` for them.
+    fn expand<'a>(
+        &mut self,
+        node: &mut impl HasChildrenMut<'a, SearchNode<G>>,
+        state: &G,
+    ) -> (G::Player, f32) {
+        self.moves.clear();
+        self.priors.clear();
+        state.legal_moves(&mut self.moves);
+        assert!(
+            !self.moves.is_empty(),
+            "non-terminal state has no legal moves"
+        );
+        let value =
+            self.evaluator
+                .evaluate(state, &self.moves, &mut self.priors);
+        assert_eq!(
+            self.priors.len(),
+            self.moves.len(),
+            "evaluator should give one prior per move"
+        );
+        if self.options.rave.is_some() {
+            self.playout.extend_from_slice(self.evaluator.playout());
+        }
+        let player = state.player_to_move();
+        node.set_children(
+            self.moves
+                .drain(..)
+                .zip(&self.priors)
+                .map(|(mv, &prior)| SearchNode::new(mv, player, prior)),
+        );
+        (player, value)
+    }
+
+    /// Returns the number of visits to a node, counting the virtual losses of
+    /// the iterations in flight through it.
+    fn visits(&self, stats: &SearchNode<G>) -> u32 {
+        stats.visits + stats.in_flight * self.options.virtual_loss
+    }
+
+    /// Returns the mean value of a child, blended with its AMAF value if RAVE
+    /// is enabled, or `None` if there are no statistics to go on.
+    fn mean(&self, stats: &SearchNode<G>) -> Option<f32> {
+        let visits = self.visits(stats);
+        let mean = stats.total / visits as f32;
+        match self.options.rave {
+            Some(schedule) if stats.amaf_visits > 0 => {
+                let beta = schedule.weight(visits, stats.amaf_visits);
+                let mean = if visits > 0 { mean } else { 0.0 };
+                Some((1.0 - beta) * mean + beta * stats.amaf_value())
+            }
+            _ => (visits > 0).then_some(mean),
+        }
+    }
+
+    /// Returns the index of the child with the highest score under the
+    /// selection policy, mixing `noise` into the priors if it is not empty.
+    ///
+    /// Children which have been proven by the solver are skipped.
+    fn select(
+        &self,
+        children: slice::Iter<'_, Node<'_, SearchNode<G>>>,
+        visits: u32,
+        noise: &[f32],
+    ) -> usize {
+        let options = &self.options;
+        let score = |index: usize, stats: &SearchNode<G>| {
+            let n = self.visits(stats) as f32;
+            match options.selection {
+                Selection::Ucb1 => match self.mean(stats) {
+                    Some(q) if n > 0.0 => {
+                        let ln_visits = libm::logf(visits as f32);
+                        q + options.exploration * libm::sqrtf(ln_visits / n)
+                    }
+                    _ => options.first_play_urgency.unwrap_or(f32::INFINITY),
+                },
+                Selection::Puct => {
+                    let q = self.mean(stats).unwrap_or_else(|| {
+                        options.first_play_urgency.unwrap_or(0.0)
+                    });
+                    let p = match noise.get(index) {
+                        Some(eta) => {
+                            let fraction = options.noise_fraction;
+                            (1.0 - fraction) * stats.prior + fraction * eta
+                        }
+                        None => stats.prior,
+                    };
+                    let sqrt_visits = libm::sqrtf(visits.max(1) as f32);
+                    q + options.exploration * p * sqrt_visits / (1.0 + n)
+                }
+            }
+        };
+        children
+            .map(Node::get)
+            .enumerate()
+            .filter(|(_, stats)| stats.proven.is_none())
+            .map(|(index, stats)| (index, score(index, stats)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// Applies the move of `node` to `state` and continues the iteration
+    /// from there, updating the nodes on the way back.
+    ///
+    /// If `batched` is true, an unexpanded node is left for a batch
+    /// evaluation, adding a virtual loss to each node on the path, rather
+    /// than being evaluated straight away.
+    fn descend(
+        &mut self,
+        node: &mut NodeMut<'_, '_, SearchNode<G>>,
+        state: &mut G,
+        batched: bool,
+    ) -> Descent<G::Player> {
+        state.apply(&node.get().mv);
+        let player = node.get().player;
+        if self.options.rave.is_some() {
+            self.playout.push((player, node.get().mv.clone()));
+        }
+        let descent = match state.terminal_value(player) {
+            Some(value) => {
+                if self.options.solver {
+                    node.get_mut().proven = Some(value);
+                }
+                Descent::Value(player, value)
+            }
+            None if node.is_expanded() => {
+                let visits = self.visits(node.get());
+                let index = self.select(node.iter(), visits, &[]);
+                self.path.push(index);
+                self.descend(&mut node.at_mut(index), state, batched)
+            }
+            None if !batched => {
+                let (to, value) = self.expand(node, state);
+                Descent::Value(to, value)
+            }
+            None if node.get().in_flight > 0 => Descent::Collision,
+            None => Descent::Leaf,
+        };
+        if self.options.solver
+            && matches!(descent, Descent::Value(..))
+            && node.get().proven.is_none()
+        {
+            let proven = proven_value(node.iter());
+            node.get_mut().proven =
+                proven.map(|result| value_for(player, result));
+        }
+        let stats = node.get_mut();
+        match descent {
+            Descent::Value(to, value) => stats.update((to, value)),
+            Descent::Leaf => stats.in_flight += 1,
+            Descent::Collision => {}
+        }
+        descent
+    }
+
+    /// Updates the AMAF statistics of the siblings of each node on the path
+    /// of the last iteration, given a player and its value for them.
+    fn update_amaf(
+        &self,
+        tree: &mut SlidingTree<'_, SearchNode<G>>,
+        (to, value): (G::Player, f32),
+    ) {
+        let mut depth = 0;
+        tree.for_each_on_path_mut(&self.path, |mut siblings, _| {
+            let later = &self.playout[depth..];
+            depth += 1;
+            for mut sibling in siblings.iter_mut() {
+                let stats = sibling.get_mut();
+                if later.iter().any(|(player, mv)| {
+                    *player == stats.player && *mv == stats.mv
+                }) {
+                    stats.amaf_visits += 1;
+                    stats.amaf_total += value_for(stats.player, (to, value));
+                }
+            }
+        });
+    }
+
+    /// Expands the node at the end of the path of an evaluated leaf, and
+    /// adds its value to the nodes on the path in place of their virtual
+    /// losses.
+    fn complete(
+        &mut self,
+        tree: &mut SlidingTree<'_, SearchNode<G>>,
+        leaf: &mut Leaf<G>,
+    ) {
+        assert_eq!(
+            leaf.priors.len(),
+            leaf.moves.len(),
+            "evaluator should give one prior per move"
+        );
+        let player = leaf.state.player_to_move();
+        let result = (player, leaf.value);
+        let children = leaf
+            .moves
+            .drain(..)
+            .zip(&leaf.priors)
+            .map(|(mv, &prior)| SearchNode::new(mv, player, prior));
+        let Some(last) = leaf.path.len().checked_sub(1) else {
+            tree.set_children(children);
+            return;
+        };
+        let rave = self.options.rave.is_some();
+        self.playout.clear();
+        let mut children = Some(children);
+        let mut depth = 0;
+        tree.for_each_on_path_mut(&leaf.path, |mut siblings, index| {
+            let mut node = siblings.at_mut(index);
+            if depth == last {
+                if let Some(children) = children.take() {
+                    node.set_children(children);
+                }
+            }
+            depth += 1;
+            let stats = node.get_mut();
+            stats.in_flight -= 1;
+            stats.update(result);
+            if rave {
+                self.playout.push((stats.player, stats.mv.clone()));
+            }
+        });
+        if rave {
+            mem::swap(&mut self.path, &mut leaf.path);
+            self.update_amaf(tree, result);
+        }
+    }
+}
+
+/// A Monte Carlo tree search over the moves of a [`Game`].
+///
+/// Each iteration selects a path through the tree from the root state,
+/// expands the first node which has not been expanded yet, evaluates its
+/// state, and adds the value to the statistics of each node on the path. By
+/// default, selection uses UCB1 and the [`Rollout`] evaluator plays random
+/// moves to the end of the game, but an [`Evaluator`] which gives prior
+/// probabilities can be used with PUCT selection instead.
+///
+/// The tree is kept in a [`SlidingTree`], and applying a move keeps the
+/// subtree below it for the following searches and recycles the rest.
+/// Positions which can be solved outright are handled better with the solver
+/// enabled by [`SearchOptions::solver`].
+pub struct Search<'a, G: Game, E = Rollout<G>> {
+    tree: SlidingTree<'a, SearchNode<G>>,
+    state: G,
+    visits: u32,
+    context: Context<G, E>,
+}
+
+impl<'a, G: Game> Search<'a, G> {
+    /// Creates a new search from `state` with the default options.
+    pub fn new(state: G) -> Search<'a, G> {
+        Search::with_options(state, SearchOptions::new())
+    }
+
+    /// Creates a new search from `state` with the given options, using the
+    /// [`Rollout`] evaluator.
+    pub fn with_options(state: G, options: SearchOptions) -> Search<'a, G> {
+        Search::with_evaluator(state, Rollout::new(options.seed), options)
+    }
+}
+
+impl<'a, G: Game, E: Evaluator<G>> Search<'a, G, E> {
+    /// Creates a new search from `state` with the given evaluator and
+    /// options.
+    pub fn with_evaluator(
+        state: G,
+        evaluator: E,
+        options: SearchOptions,
+    ) -> Search<'a, G, E> {
+        Search {
+            tree: SlidingTree::new(),
+            state,
+            visits: 0,
+            context: Context {
+                options,
+                evaluator,
+                rng: Rng(options.seed),
+                moves: Vec::new(),
+                priors: Vec::new(),
+                noise: Vec::new(),
+                path: Vec::new(),
+                playout: Vec::new(),
+            },
+        }
+    }
+
+    /// Returns the root state.
+    pub fn state(&self) -> &G {
+        &self.state
+    }
+
+    /// Returns true if the root state is terminal.
+    pub fn is_terminal(&self) -> bool {
+        Context::<G, E>::is_terminal(&self.state)
+    }
+
+    /// Returns the tree, whose roots are the moves from the root state.
+    pub fn tree(&self) -> &SlidingTree<'a, SearchNode<G>> {
+        &self.tree
+    }
+
+    /// Returns a reference to the evaluator.
+    pub fn evaluator(&self) -> &E {
+        &self.context.evaluator
+    }
+
+    /// Returns the number of iterations which have passed through the root
+    /// state, including those kept from earlier searches.
+    pub fn visits(&self) -> u32 {
+        self.visits
+    }
+
+    /// Returns the value of the root state for the player to move if it has
+    /// been proven by the solver, or `None` otherwise.
+    pub fn proven_value(&self) -> Option<f32> {
+        proven_value(self.tree.iter()).map(|(_, value)| value)
+    }
+
+    /// Returns true if the solver is enabled and has proven the value of the
+    /// root state, so that there is nothing left to search.
+    fn is_solved(&self) -> bool {
+        self.context.options.solver && self.proven_value().is_some()
+    }
+
+    /// Draws new root noise if it is enabled, reusing the allocation of the
+    /// last.
+    fn draw_noise(&mut self) -> Vec<f32> {
+        let mut noise = mem::take(&mut self.context.noise);
+        noise.clear();
+        if self.context.options.noise_fraction > 0.0 {
+            noise.resize(self.tree.len(), 0.0);
+            let alpha = self.context.options.noise_alpha;
+            self.context.rng.dirichlet(alpha, &mut noise);
+        }
+        noise
+    }
+
+    /// Selects a root node and descends from it, with `pending` leaves in
+    /// flight, and returns the outcome and the state reached.
+    fn iterate(
+        &mut self,
+        noise: &[f32],
+        pending: u32,
+        batched: bool,
+    ) -> (Descent<G::Player>, G) {
+        let mut state = self.state.clone();
+        let visits = self.visits + pending * self.context.options.virtual_loss;
+        let index = self.context.select(self.tree.iter(), visits, noise);
+        self.context.path.clear();
+        self.context.path.push(index);
+        self.context.playout.clear();
+        let descent = self.context.descend(
+            &mut self.tree.at_mut(index),
+            &mut state,
+            batched,
+        );
+        if let Descent::Value(to, value) = descent {
+            if self.context.options.rave.is_some() {
+                self.context.update_amaf(&mut self.tree, (to, value));
+            }
+            self.visits += 1;
+        }
+        (descent, state)
+    }
+
+    /// Runs the given number of iterations of the search.
+    ///
+    /// This has no effect if the root state is terminal, and stops early if
+    /// the solver proves the value of the root state.
+    pub fn run(&mut self, iterations: usize) {
+        if self.is_terminal() {
+            return;
+        }
+        if self.tree.is_empty() {
+            self.context.expand(&mut self.tree, &self.state);
+        }
+        let noise = self.draw_noise();
+        for _ in 0..iterations {
+            if self.is_solved() {
+                break;
+            }
+            self.iterate(&noise, 0, false);
+        }
+        self.context.noise = noise;
+    }
+
+    /// Runs the given number of iterations of the search, evaluating the
+    /// leaves in batches of up to `batch_size` with the callback `evaluate`
+    /// instead of the evaluator.
+    ///
+    /// Each batch descends the tree repeatedly, adding a virtual loss to each
+    /// node on the path to an unexpanded leaf so that the following descents
+    /// are steered elsewhere. This stops once `batch_size` leaves have been
+    /// collected, or a descent reaches a leaf which is already in the batch.
+    /// The leaves are then passed to `evaluate`, which must call
+    /// [`Leaf::set_evaluation`] on each of them, before they are expanded and
+    /// their values added to the nodes on their paths. A leaf refers to its
+    /// node by a path of indices, so the tree is not borrowed during the
+    /// evaluation. Iterations which reach a terminal state are completed
+    /// straight away, and the AMAF statistics of RAVE only include the moves
+    /// in the tree.
+    ///
+    /// This has no effect if the root state is terminal, and stops early if
+    /// the solver proves the value of the root state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is zero, or if a leaf is not given one prior
+    /// per move.
+    pub fn run_batched<F>(
+        &mut self,
+        iterations: usize,
+        batch_size: usize,
+        mut evaluate: F,
+    ) where
+        F: FnMut(&mut [Leaf<G>]),
+    {
+        assert!(batch_size > 0, "batch size should be non-zero");
+        if self.is_terminal() {
+            return;
+        }
+        let mut leaves = Vec::new();
+        if self.tree.is_empty() {
+            leaves.push(Leaf::new(Vec::new(), self.state.clone()));
+            evaluate(&mut leaves);
+            for mut leaf in leaves.drain(..) {
+                self.context.complete(&mut self.tree, &mut leaf);
+            }
+        }
+        let noise = self.draw_noise();
+        let mut done = 0;
+        while done < iterations {
+            while leaves.len() < batch_size
+                && done + leaves.len() < iterations
+                && !self.is_solved()
+            {
+                match self.iterate(&noise, leaves.len() as u32, true) {
+                    (Descent::Value(..), _) => done += 1,
+                    (Descent::Leaf, state) => {
+                        let path = self.context.path.clone();
+                        leaves.push(Leaf::new(path, state));
+                    }
+                    (Descent::Collision, _) => break,
+                }
+            }
+            if leaves.is_empty() {
+                break;
+            }
+            evaluate(&mut leaves);
+            for mut leaf in leaves.drain(..) {
+                self.context.complete(&mut self.tree, &mut leaf);
+                self.visits += 1;
+                done += 1;
+            }
+        }
+        self.context.noise = noise;
+    }
+
+    /// Returns the most visited move from the root state, or `None` if no
+    /// moves have been searched.
+    ///
+    /// A move proven to win by the solver is preferred to any other, and a
+    /// move proven to lose is only returned if every move is.
+    pub fn best_move(&self) -> Option<&G::Move> {
+        let rank = |stats: &SearchNode<G>| match stats.proven {
+            Some(value) if value >= 1.0 => 2,
+            Some(value) if value <= 0.0 => 0,
+            _ => 1,
+        };
+        self.tree
+            .iter()
+            .map(Node::get)
+            .max_by(|a, b| {
+                rank(a)
+                    .cmp(&rank(b))
+                    .then(a.visits.cmp(&b.visits))
+                    .then(a.value().total_cmp(&b.value()))
+            })
+            .map(SearchNode::mv)
+    }
+
+    /// Applies a move to the root state, keeping the subtree below it and
+    /// recycling the rest of the tree.
+    pub fn apply_move(&mut self, mv: &G::Move) {
+        match self.tree.iter().position(|node| node.get().mv == *mv) {
+            Some(index) => {
+                let mut node = self.tree.at_mut(index);
+                self.visits = node.get().visits;
+                node.move_children_to_root();
+                self.tree.recycle();
+            }
+            None => {
+                self.visits = 0;
+                self.tree.clear();
+            }
+        }
+        self.state.apply(mv);
+    }
+
+    /// Applies the best move as with [`Self::apply_move`] and returns it, or
+    /// returns `None` if no moves have been searched.
+    pub fn play_best(&mut self) -> Option<G::Move> {
+        let mv = self.best_move()?.clone();
+        self.apply_move(&mv);
+        Some(mv)
+    }
+}
+
+impl<G: Game + Debug, E> Debug for Search<'_, G, E>
+where
+    G::Move: Debug,
+    G::Player: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Search")
+            .field("state", &self.state)
+            .field("visits", &self.visits)
+            .field("tree", &self.tree)
+            .finish()
+    }
+}
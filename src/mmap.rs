@@ -0,0 +1,182 @@
+use core::{mem, ptr::NonNull};
+
+use crate::storage::AllocError;
+
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+/// Options for buffers allocated directly with `mmap` on Linux.
+///
+/// Each buffer is given its own anonymous mapping. This is intended for large
+/// buffers, where transparent huge pages reduce TLB misses and pre-faulting
+/// moves the cost of first-touch page faults out of the search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MmapOptions {
+    huge_pages: bool,
+    prefault: bool,
+    release_on_recycle: bool,
+}
+
+impl MmapOptions {
+    /// Creates a new set of options with every option disabled.
+    pub fn new() -> MmapOptions {
+        MmapOptions::default()
+    }
+
+    /// Requests transparent huge pages for each buffer with `madvise`.
+    ///
+    /// Mappings are aligned to the huge page size so that the whole buffer
+    /// can be backed by huge pages. Whether they are actually used depends on
+    /// the system configuration.
+    pub fn huge_pages(mut self, enable: bool) -> MmapOptions {
+        self.huge_pages = enable;
+        self
+    }
+
+    /// Pre-faults the pages of each new buffer, including those created by
+    /// `preallocate`.
+    pub fn prefault(mut self, enable: bool) -> MmapOptions {
+        self.prefault = enable;
+        self
+    }
+
+    /// Returns the memory of each recycled buffer to the operating system with
+    /// `MADV_DONTNEED`, while keeping the mapping for reuse.
+    ///
+    /// This reduces the resident size of recycled buffers at the cost of page
+    /// faults when they are reused.
+    pub fn release_on_recycle(mut self, enable: bool) -> MmapOptions {
+        self.release_on_recycle = enable;
+        self
+    }
+
+    fn page_size(&self) -> usize {
+        if self.huge_pages {
+            HUGE_PAGE_SIZE
+        } else {
+            system_page_size()
+        }
+    }
+
+    fn mapping_len<T>(&self, capacity: usize) -> Option<usize> {
+        let size = mem::size_of::<T>().checked_mul(capacity)?;
+        let page_size = self.page_size();
+        size.checked_next_multiple_of(page_size)
+    }
+
+    pub(crate) fn allocate<T>(
+        &self,
+        capacity: usize,
+    ) -> Result<NonNull<T>, AllocError> {
+        let len = self.mapping_len::<T>(capacity).ok_or(AllocError)?;
+        if len == 0 {
+            return Ok(NonNull::dangling());
+        }
+        let ptr = if self.huge_pages {
+            map_aligned(len, HUGE_PAGE_SIZE)?
+        } else {
+            map(len)?
+        };
+        if self.huge_pages {
+            // SAFETY: The range is a mapping owned by this buffer. Failure
+            // only means that huge pages are unavailable.
+            unsafe { libc::madvise(ptr.cast(), len, libc::MADV_HUGEPAGE) };
+        }
+        if self.prefault {
+            prefault(ptr, len);
+        }
+        Ok(NonNull::new(ptr as *mut T).unwrap())
+    }
+
+    /// # Safety
+    ///
+    /// The pointer and capacity must match a previous call to `allocate`.
+    pub(crate) unsafe fn deallocate<T>(
+        &self,
+        ptr: NonNull<T>,
+        capacity: usize,
+    ) {
+        let len = self.mapping_len::<T>(capacity).unwrap();
+        if len != 0 {
+            // SAFETY: The caller guarantees that this is a mapping of `len`
+            // bytes created by `allocate`.
+            unsafe { libc::munmap(ptr.as_ptr().cast(), len) };
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The pointer and capacity must match a previous call to `allocate` and
+    /// the buffer must not contain any live values.
+    pub(crate) unsafe fn release<T>(&self, ptr: NonNull<T>, capacity: usize) {
+        if !self.release_on_recycle {
+            return;
+        }
+        let len = self.mapping_len::<T>(capacity).unwrap();
+        if len != 0 {
+            // SAFETY: The caller guarantees that the mapping holds no live
+            // values, so its contents may be discarded.
+            unsafe {
+                libc::madvise(ptr.as_ptr().cast(), len, libc::MADV_DONTNEED)
+            };
+        }
+    }
+}
+
+fn system_page_size() -> usize {
+    // SAFETY: `sysconf` has no preconditions.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    usize::try_from(size).unwrap_or(4096)
+}
+
+fn map(len: usize) -> Result<*mut u8, AllocError> {
+    // SAFETY: Creates a new private anonymous mapping.
+    let ptr = unsafe {
+        libc::mmap(
+            core::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        Err(AllocError)
+    } else {
+        Ok(ptr.cast())
+    }
+}
+
+fn map_aligned(len: usize, align: usize) -> Result<*mut u8, AllocError> {
+    // Over-allocate and then unmap the unaligned head and tail.
+    let padded = len.checked_add(align).ok_or(AllocError)?;
+    let ptr = map(padded)?;
+    let head = ptr.align_offset(align);
+    let tail = padded - head - len;
+    // SAFETY: The head and tail lie within the padded mapping and are not
+    // part of the aligned range which is returned.
+    unsafe {
+        if head != 0 {
+            libc::munmap(ptr.cast(), head);
+        }
+        if tail != 0 {
+            libc::munmap(ptr.add(head + len).cast(), tail);
+        }
+        Ok(ptr.add(head))
+    }
+}
+
+fn prefault(ptr: *mut u8, len: usize) {
+    // SAFETY: The range is a mapping owned by the caller.
+    let populated =
+        unsafe { libc::madvise(ptr.cast(), len, libc::MADV_POPULATE_WRITE) };
+    if populated != 0 {
+        // Older kernels do not support `MADV_POPULATE_WRITE`, so touch each
+        // page instead.
+        for offset in (0..len).step_by(system_page_size()) {
+            // SAFETY: The offset is within the mapping, which is writable and
+            // does not yet hold any values.
+            unsafe { ptr.add(offset).write_volatile(0) };
+        }
+    }
+}
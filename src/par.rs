@@ -0,0 +1,309 @@
+use alloc::vec::Vec;
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Debug, Formatter},
+    sync::atomic::{
+        AtomicBool,
+        Ordering::{Acquire, Relaxed, Release},
+    },
+};
+
+use rayon::{
+    iter::{
+        IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
+        IntoParallelRefMutIterator, Map, ParallelIterator,
+        plumbing::{Consumer, ProducerCallback, UnindexedConsumer},
+    },
+    slice::{self, IterMut},
+};
+
+use crate::{HasChildren, Node, NodeMut, SlidingTreeState};
+
+/// A mutable reference to a node in the tree which can be sent to another
+/// thread.
+///
+/// This is yielded by the `par_iter_mut` methods. Unlike a [`NodeMut`], it can
+/// modify the data of the node and its descendants but cannot give them new
+/// children, as that would require access to the tree's buffers.
+pub struct ParNodeMut<'a, 'b, T> {
+    node: &'b mut Node<'a, T>,
+}
+
+impl<'a, T> ParNodeMut<'a, '_, T> {
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &T {
+        self.node.get()
+    }
+
+    /// Returns a mutable reference to the user data stored in this node.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.node.get_mut()
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none.
+    pub fn is_expanded(&self) -> bool {
+        self.node.is_expanded()
+    }
+
+    /// Returns a mutable reference to the child node at the given index.
+    pub fn at_mut(&mut self, index: usize) -> ParNodeMut<'a, '_, T> {
+        ParNodeMut {
+            node: &mut self.node.children[index],
+        }
+    }
+
+    /// Returns a parallel iterator over mutable references to the child nodes.
+    pub fn par_iter_mut(&mut self) -> ParNodeIterMut<'a, '_, T>
+    where
+        T: Send,
+    {
+        ParNodeIterMut::new(self.node.children)
+    }
+}
+
+impl<'a, T> AsRef<Node<'a, T>> for ParNodeMut<'a, '_, T> {
+    fn as_ref(&self) -> &Node<'a, T> {
+        self.node
+    }
+}
+
+impl<'a, T> Debug for ParNodeMut<'a, '_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParNodeMut")
+            .field("node", &self.node)
+            .finish()
+    }
+}
+
+impl<'a, T> HasChildren<'a, T> for ParNodeMut<'a, '_, T> {
+    fn children(&self) -> &[Node<'a, T>] {
+        self.node.children
+    }
+
+    fn is_empty(&self) -> bool {
+        self.node.children.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.node.children.len()
+    }
+
+    fn iter(&self) -> core::slice::Iter<'_, Node<'a, T>> {
+        self.node.children.iter()
+    }
+
+    fn at(&self, index: usize) -> &Node<'a, T> {
+        &self.node.children[index]
+    }
+
+    fn par_iter(&self) -> slice::Iter<'_, Node<'a, T>>
+    where
+        T: Sync,
+    {
+        self.node.children.par_iter()
+    }
+}
+
+type NodeMapMut<'a, 'b, T> = Map<
+    IterMut<'b, Node<'a, T>>,
+    fn(&'b mut Node<'a, T>) -> ParNodeMut<'a, 'b, T>,
+>;
+
+/// A parallel iterator over a slice of mutable node references.
+pub struct ParNodeIterMut<'a, 'b, T> {
+    iter: IterMut<'b, Node<'a, T>>,
+}
+
+impl<'a, 'b, T: Send> ParNodeIterMut<'a, 'b, T> {
+    pub(crate) fn new(nodes: &'b mut [Node<'a, T>]) -> Self {
+        ParNodeIterMut {
+            iter: nodes.par_iter_mut(),
+        }
+    }
+
+    fn into_map(self) -> NodeMapMut<'a, 'b, T> {
+        let f: fn(&'b mut Node<'a, T>) -> ParNodeMut<'a, 'b, T> =
+            |node| ParNodeMut { node };
+        self.iter.map(f)
+    }
+}
+
+impl<'a, 'b, T: Send> ParallelIterator for ParNodeIterMut<'a, 'b, T> {
+    type Item = ParNodeMut<'a, 'b, T>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.into_map().drive_unindexed(consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+impl<T: Send> IndexedParallelIterator for ParNodeIterMut<'_, '_, T> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+
+    fn drive<C>(self, consumer: C) -> C::Result
+    where
+        C: Consumer<Self::Item>,
+    {
+        self.into_map().drive(consumer)
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: ProducerCallback<Self::Item>,
+    {
+        self.into_map().with_producer(callback)
+    }
+}
+
+/// The buffers used by one rayon worker thread to build subtrees.
+///
+/// A worker claims its state while it builds a node. If it steals another
+/// node from within the builder, the state is already claimed, so that node
+/// is built in a state of its own instead.
+struct Worker<'a, T> {
+    claimed: AtomicBool,
+    state: UnsafeCell<Option<SlidingTreeState<'a, T>>>,
+}
+
+// SAFETY: The state is only accessed by the thread which has claimed it, and
+// is then moved back to the thread which created the worker.
+unsafe impl<'a, T> Sync for Worker<'a, T> where SlidingTreeState<'a, T>: Send {}
+
+impl<'a, T> Worker<'a, T> {
+    fn new() -> Self {
+        Worker {
+            claimed: AtomicBool::new(false),
+            state: UnsafeCell::new(None),
+        }
+    }
+
+    fn claim(&self) -> Option<Claim<'a, '_, T>> {
+        self.claimed
+            .compare_exchange(false, true, Acquire, Relaxed)
+            .ok()?;
+        Some(Claim { worker: self })
+    }
+}
+
+/// Exclusive access to the state of a [`Worker`], which is released when
+/// this is dropped.
+struct Claim<'a, 'b, T> {
+    worker: &'b Worker<'a, T>,
+}
+
+impl<'a, T> Claim<'a, '_, T> {
+    fn state<F>(&mut self, f: F) -> &SlidingTreeState<'a, T>
+    where
+        F: FnOnce() -> SlidingTreeState<'a, T>,
+    {
+        // SAFETY: The worker is claimed until `self` is dropped, so nothing
+        // else accesses the state.
+        unsafe { (*self.worker.state.get()).get_or_insert_with(f) }
+    }
+}
+
+impl<T> Drop for Claim<'_, '_, T> {
+    fn drop(&mut self) {
+        self.worker.claimed.store(false, Release);
+    }
+}
+
+const MOVE_TO_ROOT_IN_PARALLEL: &str = "move_children_to_root cannot be called while building subtrees in parallel";
+
+impl<'a, T: Send> SlidingTreeState<'a, T> {
+    /// Allocates a slice of new nodes whose subtrees are built in parallel.
+    ///
+    /// Each rayon worker thread allocates the subtrees it builds from its own
+    /// buffers. Once they are complete, the new nodes are moved into a slice
+    /// allocated here. The partially filled buffers here are then finished
+    /// and the buffers of the workers are appended after them, in order, so
+    /// that the slice is older than every subtree it references.
+    pub(crate) fn par_alloc_iter_recursive<I, F, U>(
+        &self,
+        iterable: I,
+        builder: F,
+    ) -> &'a mut [Node<'a, T>]
+    where
+        I: IntoParallelIterator<Item = (T, U)>,
+        F: Fn(NodeMut<'a, '_, T>, U) + Sync,
+        U: Send,
+    {
+        let Some(forker) = self.buffers.forker() else {
+            // Static storage cannot be divided between the workers.
+            let items: Vec<_> = iterable.into_par_iter().collect();
+            let pending_roots = self.pending_roots.take();
+            let children =
+                self.alloc_iter_recursive(items, |node, recursion| {
+                    builder(node, recursion);
+                    assert!(
+                        self.pending_roots.take().is_none(),
+                        "{MOVE_TO_ROOT_IN_PARALLEL}"
+                    );
+                });
+            self.pending_roots.set(pending_roots);
+            return children;
+        };
+        let workers: Vec<Worker<'a, T>> = (0..rayon::current_num_threads())
+            .map(|_| Worker::new())
+            .collect();
+        let built: Vec<(Node<'a, T>, Option<SlidingTreeState<'a, T>>)> =
+            iterable
+                .into_par_iter()
+                .map(|(data, recursion)| {
+                    let mut node = Node::leaf(data);
+                    let build = |state: &SlidingTreeState<'a, T>| {
+                        let node = NodeMut {
+                            node: &mut node,
+                            state,
+                        };
+                        builder(node, recursion);
+                        assert!(
+                            state.pending_roots.take().is_none(),
+                            "{MOVE_TO_ROOT_IN_PARALLEL}"
+                        );
+                    };
+                    let new_state = || SlidingTreeState::with_buffers(forker());
+                    let claim = rayon::current_thread_index()
+                        .and_then(|index| workers.get(index))
+                        .and_then(Worker::claim);
+                    let own_state = match claim {
+                        Some(mut claim) => {
+                            build(claim.state(new_state));
+                            None
+                        }
+                        None => {
+                            let state = new_state();
+                            build(&state);
+                            Some(state)
+                        }
+                    };
+                    (node, own_state)
+                })
+                .collect();
+        let (nodes, own_states): (Vec<_>, Vec<_>) = built.into_iter().unzip();
+        let children = self.borrow_buffers().alloc_iter(nodes.into_iter());
+        // The appended buffers must follow every buffer here in the queue,
+        // including those which are still being filled.
+        self.buffers.finish_current();
+        let states = workers
+            .into_iter()
+            .filter_map(|worker| worker.state.into_inner())
+            .chain(own_states.into_iter().flatten());
+        for state in states {
+            self.buffers.append(state.buffers);
+        }
+        children
+    }
+}
@@ -0,0 +1,296 @@
+//! The roots of a [`SlidingTree`](crate::SlidingTree) and of each tree in a
+//! [`SlidingForest`](crate::SlidingForest) are handled the same way: they are
+//! kept in a `roots` cell next to a `SlidingTreeState`, whose `pending_roots`
+//! are moved into the cell before the roots are read.
+
+/// Implements [`HasChildren`](crate::HasChildren) and
+/// [`HasChildrenMut`](crate::HasChildrenMut) on the roots of a tree.
+///
+/// The type must have a `roots` field which dereferences to a
+/// `RefSliceCell<'a, Node<'a, T>>`, a `state` field which dereferences to a
+/// `SlidingTreeState<'a, T>`, and a `process_pending_roots(&self)` method.
+macro_rules! impl_roots {
+    ($ty:ty) => {
+        impl<'a, T> $crate::HasChildren<'a, T> for $ty {
+            fn children(&self) -> &[$crate::Node<'a, T>] {
+                self.process_pending_roots();
+                self.roots.get()
+            }
+
+            fn is_empty(&self) -> bool {
+                self.process_pending_roots();
+                self.roots.get().is_empty()
+            }
+
+            fn len(&self) -> usize {
+                self.process_pending_roots();
+                self.roots.get().len()
+            }
+
+            fn iter(&self) -> ::core::slice::Iter<'_, $crate::Node<'a, T>> {
+                self.process_pending_roots();
+                self.roots.get().iter()
+            }
+
+            fn at(&self, index: usize) -> &$crate::Node<'a, T> {
+                self.process_pending_roots();
+                &self.roots.get()[index]
+            }
+
+            #[cfg(feature = "rayon")]
+            fn par_iter(&self) -> ::rayon::slice::Iter<'_, $crate::Node<'a, T>>
+            where
+                T: Sync,
+            {
+                self.process_pending_roots();
+                ::rayon::iter::IntoParallelRefIterator::par_iter(
+                    self.roots.get(),
+                )
+            }
+        }
+
+        impl<'a, T> $crate::HasChildrenMut<'a, T> for $ty {
+            fn children_mut(&mut self) -> $crate::NodeChildrenMut<'a, '_, T> {
+                self.process_pending_roots();
+                $crate::NodeChildrenMut {
+                    children: self.roots.get_mut(),
+                    state: &self.state,
+                }
+            }
+
+            fn set_children<I>(&mut self, iterable: I)
+            where
+                I: IntoIterator<Item = T>,
+            {
+                self.roots.set(self.state.alloc_iter(iterable));
+                self.state.pending_roots.set(None);
+            }
+
+            fn set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
+            where
+                I: IntoIterator<Item = (T, U)>,
+                F: FnMut($crate::NodeMut<'a, '_, T>, U),
+            {
+                self.roots
+                    .set(self.state.alloc_iter_recursive(iterable, builder));
+                self.state.pending_roots.set(None);
+            }
+
+            #[cfg(feature = "rayon")]
+            fn par_set_children_subtree<I, F, U>(
+                &mut self,
+                iterable: I,
+                builder: F,
+            ) where
+                T: Send,
+                I: ::rayon::iter::IntoParallelIterator<Item = (T, U)>,
+                F: Fn($crate::NodeMut<'a, '_, T>, U) + Sync,
+                U: Send,
+            {
+                self.roots.set(
+                    self.state.par_alloc_iter_recursive(iterable, builder),
+                );
+                self.state.pending_roots.set(None);
+            }
+
+            fn try_set_children<I>(
+                &mut self,
+                iterable: I,
+            ) -> Result<(), $crate::AllocError>
+            where
+                I: IntoIterator<Item = T>,
+            {
+                self.roots.set(self.state.try_alloc_iter(iterable)?);
+                self.state.pending_roots.set(None);
+                Ok(())
+            }
+
+            fn try_set_children_subtree<I, F, U>(
+                &mut self,
+                iterable: I,
+                builder: F,
+            ) -> Result<(), $crate::AllocError>
+            where
+                I: IntoIterator<Item = (T, U)>,
+                F: FnMut(
+                    $crate::NodeMut<'a, '_, T>,
+                    U,
+                ) -> Result<(), $crate::AllocError>,
+            {
+                self.roots.set(
+                    self.state.try_alloc_iter_recursive(iterable, builder)?,
+                );
+                self.state.pending_roots.set(None);
+                Ok(())
+            }
+
+            fn set_children_chain<I>(&mut self, iterable: I)
+            where
+                I: IntoIterator<Item = T>,
+            {
+                self.roots.set(self.state.alloc_chain(iterable));
+                self.state.pending_roots.set(None);
+            }
+
+            fn adopt_grandchildren_at(&mut self, index: usize) {
+                self.process_pending_roots();
+                let node = &mut self.roots.get_mut()[index];
+                let children = ::core::mem::take(&mut node.children);
+                self.roots.set(children);
+                self.state.pending_roots.set(None);
+            }
+
+            fn move_children_to_root(&mut self) {
+                self.process_pending_roots();
+                // This is already the root.
+            }
+
+            fn iter_mut(&mut self) -> $crate::NodeIterMut<'a, '_, T> {
+                self.process_pending_roots();
+                $crate::NodeIterMut {
+                    iter: self.roots.get_mut().iter_mut(),
+                    state: &self.state,
+                }
+            }
+
+            #[cfg(feature = "rayon")]
+            fn par_iter_mut(&mut self) -> $crate::ParNodeIterMut<'a, '_, T>
+            where
+                T: Send,
+            {
+                self.process_pending_roots();
+                $crate::ParNodeIterMut::new(self.roots.get_mut())
+            }
+
+            fn at_mut(&mut self, index: usize) -> $crate::NodeMut<'a, '_, T> {
+                self.process_pending_roots();
+                $crate::NodeMut {
+                    node: &mut self.roots.get_mut()[index],
+                    state: &self.state,
+                }
+            }
+
+            fn get_many_mut<const K: usize>(
+                &mut self,
+                indices: [usize; K],
+            ) -> [$crate::NodeMut<'a, '_, T>; K] {
+                self.process_pending_roots();
+                $crate::NodeSliceMut::new(self.roots.get_mut(), &self.state)
+                    .into_many_mut(indices)
+            }
+
+            fn split_at_mut(
+                &mut self,
+                mid: usize,
+            ) -> (
+                $crate::NodeSliceMut<'a, '_, T>,
+                $crate::NodeSliceMut<'a, '_, T>,
+            ) {
+                self.process_pending_roots();
+                $crate::NodeSliceMut::new(self.roots.get_mut(), &self.state)
+                    .into_split_at(mid)
+            }
+
+            fn split_first_mut(
+                &mut self,
+            ) -> Option<(
+                $crate::NodeMut<'a, '_, T>,
+                $crate::NodeSliceMut<'a, '_, T>,
+            )> {
+                self.process_pending_roots();
+                $crate::NodeSliceMut::new(self.roots.get_mut(), &self.state)
+                    .into_split_first()
+            }
+
+            fn split_last_mut(
+                &mut self,
+            ) -> Option<(
+                $crate::NodeMut<'a, '_, T>,
+                $crate::NodeSliceMut<'a, '_, T>,
+            )> {
+                self.process_pending_roots();
+                $crate::NodeSliceMut::new(self.roots.get_mut(), &self.state)
+                    .into_split_last()
+            }
+
+            fn for_each_on_path_mut<F>(&mut self, path: &[usize], f: F)
+            where
+                F: FnMut($crate::NodeSliceMut<'a, '_, T>, usize),
+            {
+                self.process_pending_roots();
+                $crate::NodeSliceMut::new(self.roots.get_mut(), &self.state)
+                    .into_for_each_on_path(path, f);
+            }
+
+            fn retain_children<F>(&mut self, f: F)
+            where
+                F: FnMut(&$crate::Node<'a, T>) -> bool,
+            {
+                self.children_mut().retain_children(f);
+            }
+
+            fn remove_child(&mut self, index: usize) {
+                self.children_mut().remove_child(index);
+            }
+
+            fn insert_child(&mut self, index: usize, data: T) {
+                self.children_mut().insert_child(index, data);
+            }
+
+            fn push_child(&mut self, data: T) {
+                self.children_mut().push_child(data);
+            }
+
+            fn try_push_child(
+                &mut self,
+                data: T,
+            ) -> Result<(), $crate::AllocError> {
+                self.children_mut().try_push_child(data)
+            }
+
+            fn extend_children<I>(&mut self, iterable: I)
+            where
+                I: IntoIterator<Item = T>,
+            {
+                self.children_mut().extend_children(iterable);
+            }
+
+            fn try_extend_children<I>(
+                &mut self,
+                iterable: I,
+            ) -> Result<(), $crate::AllocError>
+            where
+                I: IntoIterator<Item = T>,
+            {
+                self.children_mut().try_extend_children(iterable)
+            }
+
+            fn swap_children(&mut self, a: usize, b: usize) {
+                self.children_mut().swap_children(a, b);
+            }
+
+            #[cfg(feature = "alloc")]
+            fn sort_children_by<F>(&mut self, compare: F)
+            where
+                F: FnMut(
+                    &$crate::Node<'a, T>,
+                    &$crate::Node<'a, T>,
+                ) -> ::core::cmp::Ordering,
+            {
+                self.children_mut().sort_children_by(compare);
+            }
+
+            fn sort_children_unstable_by<F>(&mut self, compare: F)
+            where
+                F: FnMut(
+                    &$crate::Node<'a, T>,
+                    &$crate::Node<'a, T>,
+                ) -> ::core::cmp::Ordering,
+            {
+                self.children_mut().sort_children_unstable_by(compare);
+            }
+        }
+    };
+}
+
+pub(crate) use impl_roots;
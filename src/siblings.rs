@@ -0,0 +1,279 @@
+#[cfg(feature = "alloc")]
+use alloc::vec;
+use core::{cmp, iter, mem, ptr};
+
+use crate::{AllocError, Node, SlidingTreeState};
+
+fn truncate<'a, T>(children: &mut &'a mut [Node<'a, T>], len: usize) {
+    let all = mem::take(children);
+    let (kept, removed) = all.split_at_mut(len);
+    *children = kept;
+    // The data of the removed nodes is dropped straight away, leaving
+    // tombstones in their slots. Those slots and the descendants of the
+    // removed nodes stay in their buffers until they are recycled.
+    for node in removed {
+        drop(node.take());
+    }
+}
+
+pub(crate) fn retain<'a, T, F>(children: &mut &'a mut [Node<'a, T>], mut f: F)
+where
+    F: FnMut(&Node<'a, T>) -> bool,
+{
+    let mut kept = 0;
+    for i in 0..children.len() {
+        if f(&children[i]) {
+            children.swap(kept, i);
+            kept += 1;
+        }
+    }
+    truncate(children, kept);
+}
+
+pub(crate) fn remove<'a, T>(
+    children: &mut &'a mut [Node<'a, T>],
+    index: usize,
+) {
+    let len = children.len();
+    assert!(
+        index < len,
+        "removal index (is {index}) should be < len (is {len})"
+    );
+    children[index..].rotate_left(1);
+    truncate(children, len - 1);
+}
+
+pub(crate) fn get_many_mut<T, const K: usize>(
+    nodes: &mut [T],
+    indices: [usize; K],
+) -> [&mut T; K] {
+    let len = nodes.len();
+    for (i, &index) in indices.iter().enumerate() {
+        assert!(index < len, "index (is {index}) should be < len (is {len})");
+        assert!(
+            !indices[..i].contains(&index),
+            "index (is {index}) should not be repeated"
+        );
+    }
+    let ptr = nodes.as_mut_ptr();
+    // SAFETY: The indices are within bounds and distinct, so the references
+    // do not overlap.
+    indices.map(|index| unsafe { &mut *ptr.add(index) })
+}
+
+pub(crate) fn insert<'a, T>(
+    state: &SlidingTreeState<'a, T>,
+    children: &mut &'a mut [Node<'a, T>],
+    index: usize,
+    data: T,
+) {
+    let len = children.len();
+    assert!(
+        index <= len,
+        "insertion index (is {index}) should be <= len (is {len})"
+    );
+    push(state, children, data);
+    children[index..].rotate_right(1);
+}
+
+pub(crate) fn push<'a, T>(
+    state: &SlidingTreeState<'a, T>,
+    children: &mut &'a mut [Node<'a, T>],
+    data: T,
+) {
+    if let Err(err) = try_push(state, children, data, 0) {
+        panic!("{}", err);
+    }
+}
+
+pub(crate) fn extend<'a, T, I>(
+    state: &SlidingTreeState<'a, T>,
+    children: &mut &'a mut [Node<'a, T>],
+    iterable: I,
+) where
+    I: IntoIterator<Item = T>,
+{
+    if let Err(err) = try_extend(state, children, iterable) {
+        panic!("{}", err);
+    }
+}
+
+pub(crate) fn try_extend<'a, T, I>(
+    state: &SlidingTreeState<'a, T>,
+    children: &mut &'a mut [Node<'a, T>],
+    iterable: I,
+) -> Result<(), AllocError>
+where
+    I: IntoIterator<Item = T>,
+{
+    let mut iter = iterable.into_iter();
+    while let Some(data) = iter.next() {
+        try_push(state, children, data, iter.size_hint().0)?;
+    }
+    Ok(())
+}
+
+/// Appends a new child node, extending the children in place if they are at
+/// the tail of a current buffer, or otherwise moving them to the tail of a
+/// buffer with room for at least `additional` more.
+///
+/// If an error is returned, the children are unchanged.
+pub(crate) fn try_push<'a, T>(
+    state: &SlidingTreeState<'a, T>,
+    children: &mut &'a mut [Node<'a, T>],
+    data: T,
+    additional: usize,
+) -> Result<(), AllocError> {
+    let buffers = state.borrow_buffers();
+    if let Err(node) = buffers.grow_in_place(children, Node::leaf(data)) {
+        // Leave room in proportion to the current length, so that further
+        // appends with nothing allocated in between are amortised.
+        let room = cmp::max(additional, children.len() + 1);
+        relocate(state, children, node, room)?;
+    }
+    Ok(())
+}
+
+/// Moves `children` to a new slice followed by `extra`, in a buffer with
+/// room for `room` more elements after it.
+///
+/// This takes time in proportion to the number of children, together with
+/// any of their descendants which have to be moved. The vacated slots are
+/// left as tombstones until their buffers are recycled.
+///
+/// The tree remains valid at every step, so if an error is returned then
+/// `children` is unchanged, although some of their subtrees may have moved.
+fn relocate<'a, T>(
+    state: &SlidingTreeState<'a, T>,
+    children: &mut &'a mut [Node<'a, T>],
+    extra: Node<'a, T>,
+    room: usize,
+) -> Result<(), AllocError> {
+    let len = children.len();
+    let new = state.borrow_buffers().try_alloc_iter_with_room(
+        iter::repeat_with(Node::tombstone)
+            .take(len)
+            .chain(iter::once(extra)),
+        (len + 1).saturating_add(room),
+    )?;
+    if continues_run(children) {
+        // Only the child is cut from the run. The rest of the run stays
+        // where it is, and its buffer is kept for as long as the new slice.
+        state.buffers.touch(children);
+    }
+    adopt_subtrees(state, new, children)?;
+    for (dst, src) in new.iter_mut().zip(children.iter_mut()) {
+        *dst = src.take();
+    }
+    *children = new;
+    Ok(())
+}
+
+/// Returns true if `children` is a single node in a run allocated by
+/// `set_children_chain`, whose own child is the next node in the run.
+fn continues_run<T>(children: &[Node<'_, T>]) -> bool {
+    match children {
+        [child] => {
+            child.children.len() == 1
+                && ptr::eq(
+                    child.children.as_ptr(),
+                    children.as_ptr().wrapping_add(1),
+                )
+        }
+        _ => false,
+    }
+}
+
+/// Moves the descendants of `nodes` as necessary so that they can be
+/// referenced from `parent`, which is allocated later than `nodes`.
+///
+/// Subtrees are walked depth-first with an explicit stack. Each group of
+/// children is copied to its new slice only once its own descendants have
+/// been adopted, so the tree remains valid if an error is returned.
+#[cfg(feature = "alloc")]
+fn adopt_subtrees<'a, T>(
+    state: &SlidingTreeState<'a, T>,
+    parent: &[Node<'a, T>],
+    nodes: &mut [Node<'a, T>],
+) -> Result<(), AllocError> {
+    /// A group of children being moved from `old` to `new`, where `owner` is
+    /// their parent, or null for `nodes` themselves.
+    struct Frame<'a, T> {
+        owner: *mut Node<'a, T>,
+        old: *mut [Node<'a, T>],
+        new: *mut [Node<'a, T>],
+        next: usize,
+    }
+
+    let mut stack = vec![Frame {
+        owner: ptr::null_mut(),
+        old: nodes as *mut [Node<'a, T>],
+        new: parent as *const [Node<'a, T>] as *mut [Node<'a, T>],
+        next: 0,
+    }];
+    while let Some(frame) = stack.last_mut() {
+        // SAFETY: The slices of the frames are distinct, and each is only
+        // accessed through the frame at the top of the stack.
+        let (old, new) = unsafe { (&mut *frame.old, &*frame.new) };
+        if let Some(node) = old.get_mut(frame.next) {
+            frame.next += 1;
+            if state.buffers.can_reference(new, node.children) {
+                // The rest of the subtree can be referenced from these
+                // children.
+                continue;
+            }
+            let moved = state.borrow_buffers().try_alloc_iter(
+                iter::repeat_with(Node::tombstone).take(node.children.len()),
+            )?;
+            stack.push(Frame {
+                owner: node,
+                old: &mut *node.children,
+                new: moved,
+                next: 0,
+            });
+        } else if !frame.owner.is_null() {
+            // SAFETY: The new slice was allocated for this frame and is not
+            // referenced by the tree until its owner is updated. The owner is
+            // a node in the old slice of the frame below.
+            unsafe {
+                let new = &mut *frame.new;
+                for (dst, src) in new.iter_mut().zip(old.iter_mut()) {
+                    *dst = src.take();
+                }
+                (*frame.owner).children = new;
+            }
+            stack.pop();
+        } else {
+            stack.pop();
+        }
+    }
+    Ok(())
+}
+
+/// Moves the descendants of `nodes` as necessary so that they can be
+/// referenced from `parent`, which is allocated later than `nodes`.
+///
+/// Without an allocator for an explicit stack, this recurses once for each
+/// level of the subtrees which has to be moved.
+#[cfg(not(feature = "alloc"))]
+fn adopt_subtrees<'a, T>(
+    state: &SlidingTreeState<'a, T>,
+    parent: &[Node<'a, T>],
+    nodes: &mut [Node<'a, T>],
+) -> Result<(), AllocError> {
+    for node in nodes {
+        if state.buffers.can_reference(parent, node.children) {
+            // The rest of the subtree can be referenced from these children.
+            continue;
+        }
+        let new = state.borrow_buffers().try_alloc_iter(
+            iter::repeat_with(Node::tombstone).take(node.children.len()),
+        )?;
+        adopt_subtrees(state, new, node.children)?;
+        for (dst, src) in new.iter_mut().zip(node.children.iter_mut()) {
+            *dst = src.take();
+        }
+        node.children = new;
+    }
+    Ok(())
+}
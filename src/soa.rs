@@ -0,0 +1,619 @@
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Debug, Formatter},
+    iter::{self, FusedIterator},
+    ptr::NonNull,
+    slice,
+};
+
+use crate::SlidingBuffers;
+
+/// A group of siblings in a [`SoaTree`], held as parallel slices of payloads
+/// and of links to the groups of their children.
+struct Group<T> {
+    payloads: NonNull<T>,
+    links: NonNull<Group<T>>,
+    len: usize,
+}
+
+impl<T> Clone for Group<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Group<T> {}
+
+impl<T> Group<T> {
+    // Unexpanded nodes are marked by a length which no group can reach.
+    const UNEXPANDED: Group<T> = Group::dangling(usize::MAX);
+    const EMPTY: Group<T> = Group::dangling(0);
+
+    const fn dangling(len: usize) -> Group<T> {
+        Group {
+            payloads: NonNull::dangling(),
+            links: NonNull::dangling(),
+            len,
+        }
+    }
+
+    fn is_expanded(self) -> bool {
+        self.len != usize::MAX
+    }
+
+    fn len(self) -> usize {
+        if self.is_expanded() { self.len } else { 0 }
+    }
+
+    /// Returns the payloads of the group.
+    ///
+    /// # Safety
+    ///
+    /// The group must be reachable from the roots of a tree which is borrowed
+    /// for `'t`, and the payloads must not be borrowed mutably elsewhere.
+    unsafe fn payloads<'t>(self) -> &'t [T] {
+        // SAFETY: The caller guarantees that the slice is still allocated.
+        unsafe { slice::from_raw_parts(self.payloads.as_ptr(), self.len()) }
+    }
+
+    /// Returns the payloads of the group mutably.
+    ///
+    /// # Safety
+    ///
+    /// As with [`Self::payloads`], and the payloads must not be borrowed at
+    /// all elsewhere.
+    unsafe fn payloads_mut<'t>(self) -> &'t mut [T] {
+        // SAFETY: The caller guarantees that the slice is still allocated and
+        // not aliased.
+        unsafe { slice::from_raw_parts_mut(self.payloads.as_ptr(), self.len()) }
+    }
+
+    /// Returns the links of the group.
+    ///
+    /// # Safety
+    ///
+    /// As with [`Self::payloads`], for the links.
+    unsafe fn links<'t>(self) -> &'t [Group<T>] {
+        // SAFETY: The caller guarantees that the slice is still allocated.
+        unsafe { slice::from_raw_parts(self.links.as_ptr(), self.len()) }
+    }
+
+    fn node_at(self, index: usize) -> (NonNull<T>, NonNull<Group<T>>) {
+        let len = self.len();
+        assert!(index < len, "index (is {index}) should be < len (is {len})");
+        // SAFETY: The index is within both slices.
+        unsafe { (self.payloads.add(index), self.links.add(index)) }
+    }
+}
+
+/// A tree that grows from the leaves and recedes from the root, with a
+/// structure-of-arrays node layout.
+///
+/// This is a variant of [`crate::SlidingTree`] which allocates each group of
+/// siblings as two parallel slices from separate [`SlidingBuffers`], one
+/// holding the payloads and the other the links to the children. Scanning
+/// the payloads of the siblings, such as during UCB selection, then does not
+/// pull the links into the cache, and [`SoaNode::children_payloads`] returns
+/// them as a contiguous slice which is suitable for vectorised searches.
+///
+/// Since a mutable reference to a node borrows the whole tree, the children
+/// of a node are modified one at a time through [`SoaNodeMut::at_mut`], and
+/// groups of siblings cannot be edited in place.
+///
+/// A `SoaTree` is [`Send`] if `T` is, as it owns its buffers and the links
+/// only point into them.
+pub struct SoaTree<T> {
+    payloads: SlidingBuffers<T>,
+    links: SlidingBuffers<Group<T>>,
+    roots: Group<T>,
+}
+
+// SAFETY: The tree owns its buffers, and the links only point into them.
+unsafe impl<T: Send> Send for SoaTree<T> {}
+
+impl<T> SoaTree<T> {
+    /// Creates a new empty `SoaTree` with a default capacity based on the
+    /// size of `T`.
+    pub fn new() -> SoaTree<T> {
+        SoaTree::with_capacity(1000000 / size_of::<T>().max(1))
+    }
+
+    /// Creates a new empty `SoaTree` with the specified capacity.
+    ///
+    /// The `capacity` is the maximum number of nodes that can be allocated
+    /// in a single buffer.
+    pub fn with_capacity(capacity: usize) -> SoaTree<T> {
+        SoaTree {
+            payloads: SlidingBuffers::with_capacity(capacity),
+            links: SlidingBuffers::with_capacity(capacity),
+            roots: Group::EMPTY,
+        }
+    }
+
+    /// Allocates a group of leaves.
+    fn alloc_group<I>(&self, iterable: I) -> Group<T>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let payloads = self.payloads.alloc_iter(iterable.into_iter());
+        let len = payloads.len();
+        let links = self
+            .links
+            .alloc_iter(iter::repeat_n(Group::UNEXPANDED, len));
+        Group {
+            payloads: NonNull::from(payloads).cast(),
+            links: NonNull::from(links).cast(),
+            len,
+        }
+    }
+
+    /// Allocates a group of leaves and then builds their subtrees, so that
+    /// every group of children is allocated after its parent.
+    fn alloc_group_recursive<I, F, U>(
+        &mut self,
+        iterable: I,
+        mut builder: F,
+    ) -> Group<T>
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(SoaNodeMut<'_, T>, U),
+    {
+        let mut recursions = Vec::new();
+        let group = self.alloc_group(iterable.into_iter().map(|(data, u)| {
+            recursions.push(u);
+            data
+        }));
+        for (index, recursion) in recursions.into_iter().enumerate() {
+            let (payload, link) = group.node_at(index);
+            let node = SoaNodeMut {
+                payload,
+                link,
+                tree: self,
+            };
+            builder(node, recursion);
+        }
+        group
+    }
+
+    /// Returns the number of root nodes.
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Returns true if the tree has no root nodes, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.roots.len() == 0
+    }
+
+    /// Returns an iterator over references to the root nodes.
+    pub fn iter(&self) -> SoaIter<'_, T> {
+        // SAFETY: The roots are allocated while the tree is borrowed.
+        unsafe { SoaIter::new(self.roots) }
+    }
+
+    /// Returns a reference to the root node at the given index.
+    pub fn at(&self, index: usize) -> SoaNode<'_, T> {
+        let (payload, link) = self.roots.node_at(index);
+        // SAFETY: The roots are allocated while the tree is borrowed.
+        unsafe { SoaNode::new(payload, link) }
+    }
+
+    /// Returns a mutable reference to the root node at the given index.
+    pub fn at_mut(&mut self, index: usize) -> SoaNodeMut<'_, T> {
+        let (payload, link) = self.roots.node_at(index);
+        SoaNodeMut {
+            payload,
+            link,
+            tree: self,
+        }
+    }
+
+    /// Returns the payloads of the root nodes as a contiguous slice.
+    pub fn children_payloads(&self) -> &[T] {
+        // SAFETY: The roots are allocated while the tree is borrowed.
+        unsafe { self.roots.payloads() }
+    }
+
+    /// Returns the payloads of the root nodes as a contiguous mutable slice.
+    pub fn children_payloads_mut(&mut self) -> &mut [T] {
+        // SAFETY: The roots are allocated while the tree is borrowed
+        // exclusively.
+        unsafe { self.roots.payloads_mut() }
+    }
+
+    /// Sets the root nodes using the provided iterable.
+    ///
+    /// Any previous nodes become inaccessible.
+    pub fn set_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.roots = self.alloc_group(iterable);
+    }
+
+    /// Sets the root nodes using the provided iterable, allowing recursive
+    /// construction of a subtree.
+    ///
+    /// The root nodes are allocated first and then the `builder` function is
+    /// called for each of them in turn. Any previous nodes become
+    /// inaccessible.
+    pub fn set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(SoaNodeMut<'_, T>, U),
+    {
+        self.roots = self.alloc_group_recursive(iterable, builder);
+    }
+
+    /// Adopts the children of the root node at the given index as the roots.
+    ///
+    /// Any previous roots and the other sets of children and their
+    /// descendents become inaccessible.
+    pub fn adopt_grandchildren_at(&mut self, index: usize) {
+        let (_, link) = self.roots.node_at(index);
+        // SAFETY: The link belongs to one of the roots.
+        let children = unsafe { *link.as_ptr() };
+        self.roots = if children.is_expanded() {
+            children
+        } else {
+            Group::EMPTY
+        };
+    }
+
+    /// Clears the tree, removing the roots, all their descendants, and
+    /// recycling all buffers.
+    pub fn clear(&mut self) {
+        self.roots = Group::EMPTY;
+        // SAFETY: Once the roots have been cleared, previously allocated nodes
+        // are inaccessible and can be recycled.
+        unsafe {
+            self.payloads.recycle_all();
+            self.links.recycle_all();
+        }
+    }
+
+    /// Recycles buffers containing nodes that are no longer accessible.
+    pub fn recycle(&mut self) {
+        if self.is_empty() {
+            self.clear();
+        } else {
+            // SAFETY: Every group is allocated after its parent in both sets
+            // of buffers, so nodes allocated before the roots are
+            // inaccessible and can be recycled.
+            unsafe {
+                self.payloads.recycle_older_than(self.roots.payloads());
+                self.links.recycle_older_than(self.roots.links());
+            }
+        }
+    }
+
+    /// Frees unused buffers to reduce memory usage.
+    pub fn trim(&mut self) {
+        self.payloads.trim();
+        self.links.trim();
+    }
+
+    /// Returns the number of payload buffers in the finished, current, and
+    /// recycled states. The buffers of links follow the same pattern.
+    pub fn buffer_stats(&self) -> (usize, usize, usize) {
+        self.payloads.buffer_stats()
+    }
+}
+
+impl<T> Debug for SoaTree<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SoaTree")
+            .field("roots", &self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<T> Default for SoaTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A reference to a node in a [`SoaTree`].
+pub struct SoaNode<'t, T> {
+    payload: &'t T,
+    link: &'t Group<T>,
+}
+
+impl<T> Clone for SoaNode<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SoaNode<'_, T> {}
+
+impl<'t, T> SoaNode<'t, T> {
+    /// # Safety
+    ///
+    /// The node must be reachable from the roots of a tree which is borrowed
+    /// for `'t`.
+    unsafe fn new(payload: NonNull<T>, link: NonNull<Group<T>>) -> Self {
+        // SAFETY: The caller guarantees that the node is still allocated.
+        unsafe {
+            SoaNode {
+                payload: payload.as_ref(),
+                link: link.as_ref(),
+            }
+        }
+    }
+
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &'t T {
+        self.payload
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none.
+    pub fn is_expanded(&self) -> bool {
+        self.link.is_expanded()
+    }
+
+    /// Returns the number of child nodes.
+    pub fn len(&self) -> usize {
+        self.link.len()
+    }
+
+    /// Returns true if this node has no children, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.link.len() == 0
+    }
+
+    /// Returns an iterator over references to the child nodes.
+    pub fn iter(&self) -> SoaIter<'t, T> {
+        // SAFETY: The children of a reachable node are reachable.
+        unsafe { SoaIter::new(*self.link) }
+    }
+
+    /// Returns a reference to the child node at the given index.
+    pub fn at(&self, index: usize) -> SoaNode<'t, T> {
+        let (payload, link) = self.link.node_at(index);
+        // SAFETY: The children of a reachable node are reachable.
+        unsafe { SoaNode::new(payload, link) }
+    }
+
+    /// Returns the payloads of the child nodes as a contiguous slice.
+    pub fn children_payloads(&self) -> &'t [T] {
+        // SAFETY: The children of a reachable node are reachable.
+        unsafe { self.link.payloads() }
+    }
+}
+
+impl<T> Debug for SoaNode<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SoaNode")
+            .field("data", self.get())
+            .field("children", &self.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// A mutable reference to a node in a [`SoaTree`].
+///
+/// This borrows the whole tree, as new children are allocated from it.
+pub struct SoaNodeMut<'t, T> {
+    payload: NonNull<T>,
+    link: NonNull<Group<T>>,
+    tree: &'t mut SoaTree<T>,
+}
+
+impl<T> SoaNodeMut<'_, T> {
+    fn children(&self) -> Group<T> {
+        // SAFETY: The node is reachable while the tree is borrowed.
+        unsafe { *self.link.as_ptr() }
+    }
+
+    fn set_children_group(&mut self, group: Group<T>) {
+        // SAFETY: The node is reachable while the tree is borrowed
+        // exclusively.
+        unsafe { *self.link.as_ptr() = group };
+    }
+
+    /// Returns a reference to the user data stored in this node.
+    pub fn get(&self) -> &T {
+        // SAFETY: The node is reachable while the tree is borrowed.
+        unsafe { self.payload.as_ref() }
+    }
+
+    /// Returns a mutable reference to the user data stored in this node.
+    pub fn get_mut(&mut self) -> &mut T {
+        // SAFETY: The node is reachable while the tree is borrowed
+        // exclusively.
+        unsafe { self.payload.as_mut() }
+    }
+
+    /// Returns a reference to this node.
+    pub fn as_node(&self) -> SoaNode<'_, T> {
+        // SAFETY: The node is reachable while the tree is borrowed.
+        unsafe { SoaNode::new(self.payload, self.link) }
+    }
+
+    /// Returns true if the children of this node have been set, even if
+    /// there are none.
+    pub fn is_expanded(&self) -> bool {
+        self.children().is_expanded()
+    }
+
+    /// Returns the number of child nodes.
+    pub fn len(&self) -> usize {
+        self.children().len()
+    }
+
+    /// Returns true if this node has no children, false otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.children().len() == 0
+    }
+
+    /// Returns an iterator over references to the child nodes.
+    pub fn iter(&self) -> SoaIter<'_, T> {
+        // SAFETY: The children of a reachable node are reachable.
+        unsafe { SoaIter::new(self.children()) }
+    }
+
+    /// Returns a reference to the child node at the given index.
+    pub fn at(&self, index: usize) -> SoaNode<'_, T> {
+        let (payload, link) = self.children().node_at(index);
+        // SAFETY: The children of a reachable node are reachable.
+        unsafe { SoaNode::new(payload, link) }
+    }
+
+    /// Returns a mutable reference to the child node at the given index.
+    pub fn at_mut(&mut self, index: usize) -> SoaNodeMut<'_, T> {
+        let (payload, link) = self.children().node_at(index);
+        SoaNodeMut {
+            payload,
+            link,
+            tree: self.tree,
+        }
+    }
+
+    /// Returns the payloads of the child nodes as a contiguous slice.
+    pub fn children_payloads(&self) -> &[T] {
+        // SAFETY: The children of a reachable node are reachable.
+        unsafe { self.children().payloads() }
+    }
+
+    /// Returns the payloads of the child nodes as a contiguous mutable slice.
+    pub fn children_payloads_mut(&mut self) -> &mut [T] {
+        // SAFETY: The children of a reachable node are reachable, and the
+        // tree is borrowed exclusively.
+        unsafe { self.children().payloads_mut() }
+    }
+
+    /// Sets the child nodes using the provided iterable.
+    ///
+    /// Any previous child nodes become inaccessible.
+    pub fn set_children<I>(&mut self, iterable: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let group = self.tree.alloc_group(iterable);
+        self.set_children_group(group);
+    }
+
+    /// Sets the child nodes using the provided iterable, allowing recursive
+    /// construction of a subtree.
+    ///
+    /// See [`SoaTree::set_children_subtree`].
+    pub fn set_children_subtree<I, F, U>(&mut self, iterable: I, builder: F)
+    where
+        I: IntoIterator<Item = (T, U)>,
+        F: FnMut(SoaNodeMut<'_, T>, U),
+    {
+        let group = self.tree.alloc_group_recursive(iterable, builder);
+        self.set_children_group(group);
+    }
+
+    /// Sets the child nodes using the iterable returned by `f`, unless they
+    /// have already been set.
+    ///
+    /// Returns true if the node was expanded by this call. See
+    /// [`crate::NodeMut::expand_with`].
+    pub fn expand_with<F, I>(&mut self, f: F) -> bool
+    where
+        F: FnOnce(&mut T) -> I,
+        I: IntoIterator<Item = T>,
+    {
+        if self.is_expanded() {
+            return false;
+        }
+        let iterable = f(self.get_mut());
+        self.set_children(iterable);
+        true
+    }
+
+    /// Adopts the children of the child node at the given index as the
+    /// children here.
+    ///
+    /// Any previous child nodes and the other sets of grandchildren and their
+    /// descendents become inaccessible.
+    pub fn adopt_grandchildren_at(&mut self, index: usize) {
+        let (_, link) = self.children().node_at(index);
+        // SAFETY: The children of a reachable node are reachable.
+        let grandchildren = unsafe { *link.as_ptr() };
+        self.set_children_group(grandchildren);
+    }
+
+    /// Moves the child nodes from here to become the roots of the tree.
+    ///
+    /// This replaces the current children of the node with an empty slice.
+    pub fn move_children_to_root(&mut self) {
+        let children = self.children();
+        self.tree.roots = if children.is_expanded() {
+            children
+        } else {
+            Group::EMPTY
+        };
+        self.set_children_group(Group::EMPTY);
+    }
+}
+
+impl<T> Debug for SoaNodeMut<'_, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_node().fmt(f)
+    }
+}
+
+/// An iterator over references to the nodes of a [`SoaTree`].
+pub struct SoaIter<'t, T> {
+    payloads: slice::Iter<'t, T>,
+    links: slice::Iter<'t, Group<T>>,
+}
+
+impl<'t, T> SoaIter<'t, T> {
+    /// # Safety
+    ///
+    /// The group must be reachable from the roots of a tree which is borrowed
+    /// for `'t`.
+    unsafe fn new(group: Group<T>) -> Self {
+        // SAFETY: The caller guarantees that the group is still allocated.
+        unsafe {
+            SoaIter {
+                payloads: group.payloads().iter(),
+                links: group.links().iter(),
+            }
+        }
+    }
+}
+
+impl<'t, T> Iterator for SoaIter<'t, T> {
+    type Item = SoaNode<'t, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(SoaNode {
+            payload: self.payloads.next()?,
+            link: self.links.next()?,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.payloads.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for SoaIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        Some(SoaNode {
+            payload: self.payloads.next_back()?,
+            link: self.links.next_back()?,
+        })
+    }
+}
+
+impl<T> ExactSizeIterator for SoaIter<'_, T> {}
+
+impl<T> FusedIterator for SoaIter<'_, T> {}
@@ -0,0 +1,449 @@
+use core::{
+    fmt::{self, Display, Formatter},
+    mem::{self, MaybeUninit},
+    ptr::NonNull,
+    slice,
+};
+
+#[cfg(feature = "alloc")]
+use alloc::{alloc as heap, vec::Vec};
+#[cfg(feature = "alloc")]
+use core::alloc::Layout;
+
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+use crate::mmap::MmapOptions;
+
+/// The error returned when memory for a buffer cannot be obtained.
+///
+/// With the heap-backed storage this only happens if the global allocator
+/// fails. With static storage it happens once all of the buffers carved out
+/// of the supplied region are in use, or if a single allocation is larger
+/// than a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+impl Display for AllocError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("SlidingBuffers allocation failed")
+    }
+}
+
+impl core::error::Error for AllocError {}
+
+/// The source of the memory backing each buffer.
+pub(crate) enum Storage {
+    #[cfg(feature = "alloc")]
+    Heap,
+    Static(StaticStorage),
+    #[cfg(all(feature = "mmap", target_os = "linux"))]
+    Mmap(MmapOptions),
+}
+
+impl Storage {
+    /// Returns true if buffers of any capacity can be allocated.
+    pub fn is_growable(&self) -> bool {
+        match self {
+            #[cfg(feature = "alloc")]
+            Storage::Heap => true,
+            Storage::Static(_) => false,
+            #[cfg(all(feature = "mmap", target_os = "linux"))]
+            Storage::Mmap(_) => true,
+        }
+    }
+
+    /// Returns a new storage of the same kind, from which buffers can be
+    /// allocated independently and later handed to this one, or `None` for
+    /// static storage, which cannot be divided further.
+    #[cfg(feature = "rayon")]
+    pub fn fork(&self) -> Option<Storage> {
+        match self {
+            Storage::Heap => Some(Storage::Heap),
+            Storage::Static(_) => None,
+            #[cfg(all(feature = "mmap", target_os = "linux"))]
+            Storage::Mmap(options) => Some(Storage::Mmap(*options)),
+        }
+    }
+
+    /// Allocates uninitialised memory for `capacity` elements of `T`.
+    pub fn allocate<T>(
+        &mut self,
+        capacity: usize,
+    ) -> Result<NonNull<T>, AllocError> {
+        match self {
+            #[cfg(feature = "alloc")]
+            Storage::Heap => {
+                let layout =
+                    Layout::array::<T>(capacity).map_err(|_| AllocError)?;
+                if layout.size() == 0 {
+                    return Ok(NonNull::dangling());
+                }
+                // SAFETY: The layout has a non-zero size.
+                let ptr = unsafe { heap::alloc(layout) };
+                NonNull::new(ptr as *mut T).ok_or(AllocError)
+            }
+            Storage::Static(storage) => storage.allocate(capacity),
+            #[cfg(all(feature = "mmap", target_os = "linux"))]
+            Storage::Mmap(options) => options.allocate(capacity),
+        }
+    }
+
+    /// Returns memory previously obtained from `allocate`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer and capacity must match a previous call to `allocate` on
+    /// this storage, and the memory must not be used afterwards.
+    pub unsafe fn deallocate<T>(&mut self, ptr: NonNull<T>, capacity: usize) {
+        match self {
+            #[cfg(feature = "alloc")]
+            Storage::Heap => {
+                let layout = Layout::array::<T>(capacity).unwrap();
+                if layout.size() != 0 {
+                    // SAFETY: The caller guarantees that `ptr` was allocated
+                    // with this layout.
+                    unsafe { heap::dealloc(ptr.as_ptr() as *mut u8, layout) };
+                }
+            }
+            Storage::Static(_) => {
+                // Static buffers are never handed back to the storage. They
+                // stay in the recycled state until the owner is dropped.
+                let _ = (ptr, capacity);
+            }
+            #[cfg(all(feature = "mmap", target_os = "linux"))]
+            Storage::Mmap(options) => {
+                // SAFETY: Forwarded from the caller.
+                unsafe { options.deallocate(ptr, capacity) };
+            }
+        }
+    }
+
+    /// Allows the storage to reclaim the memory of a buffer which has been
+    /// cleared for reuse.
+    ///
+    /// # Safety
+    ///
+    /// The pointer and capacity must match a previous call to `allocate` on
+    /// this storage, and the memory must not contain any live values.
+    pub unsafe fn release<T>(&mut self, ptr: NonNull<T>, capacity: usize) {
+        match self {
+            #[cfg(all(feature = "mmap", target_os = "linux"))]
+            Storage::Mmap(options) => {
+                // SAFETY: Forwarded from the caller.
+                unsafe { options.release(ptr, capacity) };
+            }
+            _ => {
+                let _ = (ptr, capacity);
+            }
+        }
+    }
+}
+
+/// A region of caller-provided memory divided into equally sized buffers.
+pub(crate) struct StaticStorage {
+    base: NonNull<u8>,
+    stride: usize,
+    count: usize,
+    next: usize,
+}
+
+// SAFETY: The storage has exclusive access to its `'static` region, which
+// does not depend on the thread that uses it.
+unsafe impl Send for StaticStorage {}
+
+// SAFETY: The storage has no interior mutability, so a shared reference only
+// allows its fields to be read.
+unsafe impl Sync for StaticStorage {}
+
+impl StaticStorage {
+    fn allocate<T>(
+        &mut self,
+        capacity: usize,
+    ) -> Result<NonNull<T>, AllocError> {
+        debug_assert!(
+            mem::size_of::<T>() == 0
+                || capacity * mem::size_of::<T>() <= self.stride
+        );
+        if self.next == self.count {
+            return Err(AllocError);
+        }
+        // SAFETY: The buffer at index `next` lies within the region.
+        let ptr = unsafe { self.base.add(self.next * self.stride) };
+        self.next += 1;
+        Ok(ptr.cast())
+    }
+}
+
+/// A cursor used to carve typed arrays out of an untyped region of memory.
+pub(crate) struct Region {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Region {
+    pub fn new(region: &'static mut [MaybeUninit<u8>]) -> Region {
+        Region {
+            ptr: region.as_mut_ptr() as *mut u8,
+            len: region.len(),
+        }
+    }
+
+    fn align_to<U>(&mut self) {
+        let offset = self.ptr.align_offset(mem::align_of::<U>());
+        let offset = offset.min(self.len);
+        // SAFETY: The offset is clamped to the remaining length.
+        self.ptr = unsafe { self.ptr.add(offset) };
+        self.len -= offset;
+    }
+
+    /// Takes space for `count` values of `U` from the front of the region.
+    pub fn take<U>(&mut self, count: usize) -> Option<NonNull<U>> {
+        self.align_to::<U>();
+        let size = mem::size_of::<U>().checked_mul(count)?;
+        if size > self.len {
+            return None;
+        }
+        let ptr = self.ptr as *mut U;
+        // SAFETY: `size` bytes are available in the region.
+        self.ptr = unsafe { self.ptr.add(size) };
+        self.len -= size;
+        NonNull::new(ptr)
+    }
+
+    /// Divides the rest of the region into `count` buffers of `T`, returning
+    /// the storage and the capacity of each buffer in elements.
+    pub fn into_storage<T>(mut self, count: usize) -> (StaticStorage, usize) {
+        self.align_to::<T>();
+        let size = mem::size_of::<T>();
+        let (stride, capacity) = match (size, count) {
+            (_, 0) => (0, 0),
+            (0, _) => (0, usize::MAX),
+            _ => {
+                let capacity = self.len / count / size;
+                (capacity * size, capacity)
+            }
+        };
+        let storage = StaticStorage {
+            base: NonNull::new(self.ptr).unwrap_or(NonNull::dangling()),
+            stride,
+            count,
+            next: 0,
+        };
+        (storage, capacity)
+    }
+}
+
+/// A list of buffer records kept either on the heap or in a fixed array.
+///
+/// Records are popped from the front by advancing `head` over the slots
+/// before it, which are left uninitialised, and the rest are only shifted
+/// down once the array is full. Shifting only when at least half of the
+/// slots are free keeps both ends amortised O(1).
+pub(crate) enum Records<B> {
+    #[cfg(feature = "alloc")]
+    Heap { vec: Vec<B>, head: usize },
+    Fixed {
+        ptr: NonNull<B>,
+        head: usize,
+        len: usize,
+        capacity: usize,
+    },
+}
+
+// SAFETY: A fixed list uniquely owns its records, like a `Vec`.
+unsafe impl<B: Send> Send for Records<B> {}
+
+impl<B> Records<B> {
+    #[cfg(feature = "alloc")]
+    pub fn new() -> Records<B> {
+        Records::Heap {
+            vec: Vec::new(),
+            head: 0,
+        }
+    }
+
+    /// Creates a list stored in `capacity` uninitialised slots at `ptr`.
+    ///
+    /// A list which is popped from the front needs twice as many slots as it
+    /// has records, so that shifting them down stays amortised O(1).
+    pub fn fixed(ptr: NonNull<B>, capacity: usize) -> Records<B> {
+        Records::Fixed {
+            ptr,
+            head: 0,
+            len: 0,
+            capacity,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[B] {
+        match self {
+            #[cfg(feature = "alloc")]
+            Records::Heap { vec, head } => {
+                // SAFETY: The slots from `head` to the end are initialised.
+                unsafe {
+                    slice::from_raw_parts(
+                        vec.as_ptr().add(*head),
+                        vec.len() - head,
+                    )
+                }
+            }
+            // SAFETY: The slots from `head` to `len` are initialised.
+            Records::Fixed { ptr, head, len, .. } => unsafe {
+                slice::from_raw_parts(ptr.as_ptr().add(*head), len - head)
+            },
+        }
+    }
+
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [B] {
+        match self {
+            #[cfg(feature = "alloc")]
+            Records::Heap { vec, head } => {
+                let len = vec.len() - *head;
+                // SAFETY: The slots from `head` to the end are initialised.
+                unsafe {
+                    slice::from_raw_parts_mut(vec.as_mut_ptr().add(*head), len)
+                }
+            }
+            // SAFETY: The slots from `head` to `len` are initialised.
+            Records::Fixed { ptr, head, len, .. } => unsafe {
+                slice::from_raw_parts_mut(ptr.as_ptr().add(*head), *len - *head)
+            },
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        match self {
+            #[cfg(feature = "alloc")]
+            Records::Heap { vec, .. } => vec.reserve(additional),
+            Records::Fixed { .. } => {
+                // Fixed lists already have room for every buffer.
+                let _ = additional;
+            }
+        }
+    }
+
+    pub fn push(&mut self, value: B) {
+        match self {
+            #[cfg(feature = "alloc")]
+            Records::Heap { vec, head } => {
+                if vec.len() == vec.capacity() && *head * 2 >= vec.len() {
+                    let len = vec.len() - *head;
+                    // SAFETY: The initialised slots are moved down over the
+                    // uninitialised ones before `head`.
+                    unsafe {
+                        let base = vec.as_mut_ptr();
+                        base.copy_from(base.add(*head), len);
+                        vec.set_len(len);
+                    }
+                    *head = 0;
+                }
+                vec.push(value);
+            }
+            Records::Fixed {
+                ptr,
+                head,
+                len,
+                capacity,
+            } => {
+                if *len == *capacity && *head > 0 {
+                    // SAFETY: As above.
+                    unsafe {
+                        let base = ptr.as_ptr();
+                        base.copy_from(base.add(*head), *len - *head);
+                    }
+                    *len -= *head;
+                    *head = 0;
+                }
+                // Every buffer has a slot in each list, so this can only fail
+                // if the book-keeping has gone wrong.
+                assert!(*len < *capacity, "buffer records exhausted");
+                // SAFETY: The slot at `len` is within the array.
+                unsafe { ptr.as_ptr().add(*len).write(value) };
+                *len += 1;
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<B> {
+        if self.len() == 0 {
+            return None;
+        }
+        match self {
+            #[cfg(feature = "alloc")]
+            Records::Heap { vec, .. } => vec.pop(),
+            Records::Fixed { ptr, len, .. } => {
+                *len -= 1;
+                // SAFETY: The slot was initialised and is now past the end.
+                Some(unsafe { ptr.as_ptr().add(*len).read() })
+            }
+        }
+    }
+
+    /// Removes the first record in O(1).
+    pub fn pop_front(&mut self) -> Option<B> {
+        if self.len() == 0 {
+            return None;
+        }
+        match self {
+            #[cfg(feature = "alloc")]
+            Records::Heap { vec, head } => {
+                // SAFETY: The slot at `head` is initialised, and is left
+                // uninitialised by advancing `head` past it.
+                let value = unsafe { vec.as_ptr().add(*head).read() };
+                *head += 1;
+                if *head == vec.len() {
+                    // SAFETY: No slots are initialised.
+                    unsafe { vec.set_len(0) };
+                    *head = 0;
+                }
+                Some(value)
+            }
+            Records::Fixed { ptr, head, .. } => {
+                // SAFETY: As above.
+                let value = unsafe { ptr.as_ptr().add(*head).read() };
+                *head += 1;
+                Some(value)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> B {
+        assert!(index < self.len(), "record index out of bounds");
+        match self {
+            #[cfg(feature = "alloc")]
+            Records::Heap { vec, head } => vec.remove(*head + index),
+            Records::Fixed { ptr, head, len, .. } => {
+                // SAFETY: The slots from `head` to `len` are initialised and
+                // the tail is shifted down over the removed value.
+                unsafe {
+                    let hole = ptr.as_ptr().add(*head + index);
+                    let value = hole.read();
+                    hole.copy_from(hole.add(1), *len - *head - index - 1);
+                    *len -= 1;
+                    value
+                }
+            }
+        }
+    }
+}
+
+impl<B> Drop for Records<B> {
+    fn drop(&mut self) {
+        // SAFETY: Only the initialised slots are dropped, and the vector is
+        // emptied first so that it does not drop the others.
+        unsafe {
+            let live: *mut [B] = self.as_mut_slice();
+            #[cfg(feature = "alloc")]
+            if let Records::Heap { vec, .. } = self {
+                vec.set_len(0);
+            }
+            live.drop_in_place();
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Formatter};
+
+use crate::{Node, NodeHandle, SlidingTree};
+
+/// A table mapping position keys to nodes in a [`SlidingTree`], whose entries
+/// expire when the buffers holding their nodes are recycled.
+///
+/// Each entry holds a [`NodeHandle`], which records the generation of the
+/// buffer holding its node. Once [`SlidingTree::recycle`] frees that buffer,
+/// a lookup for the entry returns `None`, even if the buffer has since been
+/// reused, so the table never returns a dangling node and there is no need to
+/// remove entries after each move. As with [`SlidingTree::resolve`], a node
+/// which is no longer reachable from the roots is not returned either.
+///
+/// The table has a fixed number of entries, indexed by the low bits of the
+/// key, such as a Zobrist hash, and a new entry replaces any other with the
+/// same index.
+pub struct TranspositionTable {
+    entries: Vec<Option<(u64, NodeHandle)>>,
+}
+
+impl TranspositionTable {
+    /// Creates a new empty `TranspositionTable` with room for at least
+    /// `capacity` entries.
+    ///
+    /// The capacity is rounded up to a power of two.
+    pub fn with_capacity(capacity: usize) -> TranspositionTable {
+        let len = capacity.max(1).next_power_of_two();
+        TranspositionTable {
+            entries: (0..len).map(|_| None).collect(),
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.entries.len() - 1)
+    }
+
+    /// Returns the number of entries the table can hold.
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Maps `key` to `node`, replacing any entry with the same index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` does not belong to `tree`.
+    pub fn insert<'a, T>(
+        &mut self,
+        tree: &SlidingTree<'a, T>,
+        key: u64,
+        node: &Node<'a, T>,
+    ) {
+        let index = self.index(key);
+        self.entries[index] = Some((key, tree.handle(node)));
+    }
+
+    /// Returns the handle mapped to `key`, if there is one.
+    ///
+    /// The handle is returned whether or not its node is still alive.
+    pub fn handle(&self, key: u64) -> Option<NodeHandle> {
+        self.entries[self.index(key)]
+            .filter(|&(entry, _)| entry == key)
+            .map(|(_, handle)| handle)
+    }
+
+    /// Returns a reference to the node mapped to `key` in `tree`, or `None`
+    /// if there is no such entry or the node is no longer alive.
+    pub fn get<'t, 'a, T>(
+        &self,
+        tree: &'t SlidingTree<'a, T>,
+        key: u64,
+    ) -> Option<&'t Node<'a, T>> {
+        tree.resolve(self.handle(key)?)
+    }
+
+    /// Removes the entry for `key`, if there is one.
+    pub fn remove(&mut self, key: u64) {
+        let index = self.index(key);
+        if self.entries[index].is_some_and(|(entry, _)| entry == key) {
+            self.entries[index] = None;
+        }
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
+
+impl Debug for TranspositionTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TranspositionTable")
+            .field("capacity", &self.capacity())
+            .finish_non_exhaustive()
+    }
+}
@@ -1,6 +1,9 @@
+#![cfg(feature = "alloc")]
+
+use std::mem::MaybeUninit;
 use std::panic::{self, AssertUnwindSafe};
 
-use sliding_tree::SlidingBuffers;
+use sliding_tree::{AllocError, SlidingBuffers};
 
 mod common;
 use common::{Counters, DropCounter, PanicAfter, PanicOnSizeHint};
@@ -235,3 +238,187 @@ fn test_panic_does_not_leak_or_double_free() {
         counters.dropped(),
     );
 }
+
+fn static_region(len: usize) -> &'static mut [MaybeUninit<u8>] {
+    Box::leak(vec![MaybeUninit::uninit(); len].into_boxed_slice())
+}
+
+#[test]
+fn test_static_storage() {
+    let buffers =
+        SlidingBuffers::<u64>::with_static_storage(static_region(4096), 2);
+    let capacity = buffers.capacity() as u64;
+    assert!(capacity > 0);
+
+    let slice1 = buffers.try_alloc_iter(0..capacity).unwrap();
+    assert_eq!(slice1.len() as u64, capacity);
+    let slice2 = buffers.try_alloc_iter(0..1).unwrap();
+    assert_eq!(buffers.buffer_stats(), (1, 1, 0));
+
+    // Both buffers are in use, so a full-sized allocation cannot be satisfied.
+    assert_eq!(buffers.try_alloc_iter(0..capacity).err(), Some(AllocError));
+    assert_eq!(buffers.buffer_stats(), (2, 0, 0));
+
+    // An allocation larger than a buffer can never be satisfied.
+    assert_eq!(
+        buffers.try_alloc_iter(0..capacity + 1).err(),
+        Some(AllocError)
+    );
+
+    // Recycling makes the first buffer available again.
+    unsafe {
+        buffers.recycle_older_than(slice2);
+    }
+    assert_eq!(buffers.buffer_stats(), (1, 0, 1));
+    let slice3 = buffers.try_alloc_iter(10..12).unwrap();
+    assert_eq!(slice3, &[10, 11]);
+    assert_eq!(slice2, &[0]);
+
+    // Trimming keeps static buffers.
+    unsafe {
+        buffers.recycle_all();
+    }
+    buffers.trim();
+    assert_eq!(buffers.buffer_stats(), (0, 0, 2));
+}
+
+#[test]
+fn test_static_storage_spanning_failure() {
+    let buffers =
+        SlidingBuffers::<u64>::with_static_storage(static_region(1024), 1);
+    let capacity = buffers.capacity() as u64;
+    let slice1 = buffers.alloc_iter(0..2);
+
+    // Without a size hint, the allocation only fails when the buffer fills.
+    let result = buffers.try_alloc_iter((0..capacity).filter(|_| true));
+    assert_eq!(result.err(), Some(AllocError));
+    assert_eq!(slice1, &[0, 1]);
+    assert_eq!(buffers.buffer_stats(), (1, 0, 0));
+}
+
+#[test]
+#[should_panic(expected = "SlidingBuffers allocation failed")]
+fn test_static_storage_alloc_iter_panics() {
+    let buffers =
+        SlidingBuffers::<u64>::with_static_storage(static_region(1024), 1);
+    let capacity = buffers.capacity() as u64;
+    buffers.alloc_iter(0..capacity + 1);
+}
+
+#[test]
+fn test_static_storage_drops_elements() {
+    let counters = Counters::new();
+    {
+        let buffers = SlidingBuffers::<DropCounter>::with_static_storage(
+            static_region(1024),
+            2,
+        );
+        buffers.alloc_iter((0..3).map(|_| DropCounter::new(&counters)));
+        buffers.alloc_iter((0..2).map(|_| DropCounter::new(&counters)));
+        assert_eq!(counters.dropped(), 0);
+    }
+    assert_eq!(counters.constructed(), 5);
+    assert!(counters.balanced());
+}
+
+#[cfg(all(feature = "mmap", target_os = "linux"))]
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_mmap_storage() {
+    use sliding_tree::MmapOptions;
+
+    let options = MmapOptions::new()
+        .huge_pages(true)
+        .prefault(true)
+        .release_on_recycle(true);
+    let mut buffers = SlidingBuffers::<u64>::with_mmap(1000, options);
+    buffers.preallocate(2);
+    assert_eq!(buffers.buffer_stats(), (0, 0, 2));
+
+    let slice1 = buffers.alloc_iter(0..1000);
+    assert_eq!(slice1.iter().sum::<u64>(), 499500);
+    assert_eq!(buffers.buffer_stats(), (1, 0, 1));
+    let slice2 = buffers.alloc_iter(0..10);
+    assert_eq!(buffers.buffer_stats(), (1, 1, 0));
+
+    unsafe {
+        buffers.recycle_older_than(slice2);
+    }
+    assert_eq!(buffers.buffer_stats(), (0, 1, 1));
+
+    // Released buffers remain usable.
+    unsafe {
+        buffers.recycle_all();
+    }
+    assert_eq!(buffers.buffer_stats(), (0, 0, 2));
+    let slice3 = buffers.alloc_iter(5..10);
+    assert_eq!(slice3, &[5, 6, 7, 8, 9]);
+    buffers.trim();
+    assert_eq!(buffers.buffer_stats(), (0, 1, 0));
+}
+
+#[test]
+fn test_recycle_unmarked() {
+    let buffers = SlidingBuffers::<usize>::with_capacity(10);
+    let slice1 = buffers.alloc_iter(0..10);
+    let _slice2 = buffers.alloc_iter(0..10);
+    let slice3 = buffers.alloc_iter(0..5);
+    assert_eq!(buffers.buffer_stats(), (2, 1, 0));
+
+    // Buffers can be recycled out of order.
+    buffers.mark(slice1);
+    buffers.mark(slice3);
+    buffers.mark(&[]);
+    unsafe {
+        buffers.recycle_unmarked();
+    }
+    assert_eq!(buffers.buffer_stats(), (1, 1, 1));
+    assert_eq!(slice1, (0..10).collect::<Vec<_>>());
+    assert_eq!(slice3, &[0, 1, 2, 3, 4]);
+
+    // Marks are cleared afterwards.
+    buffers.mark(slice3);
+    unsafe {
+        buffers.recycle_unmarked();
+    }
+    assert_eq!(buffers.buffer_stats(), (0, 1, 2));
+}
+
+#[test]
+fn test_recycle_older_than_all() {
+    let buffers = SlidingBuffers::<usize>::with_capacity(10);
+    let _slice1 = buffers.alloc_iter(0..10);
+    let slice2 = buffers.alloc_iter(0..10);
+    let slice3 = buffers.alloc_iter(0..10);
+    assert_eq!(buffers.buffer_stats(), (3, 0, 0));
+
+    // Only buffers older than the oldest slice are recycled.
+    unsafe {
+        buffers.recycle_older_than_all([&slice3[..], &[], &slice2[..]]);
+    }
+    assert_eq!(buffers.buffer_stats(), (2, 0, 1));
+    unsafe {
+        buffers.recycle_older_than_all([]);
+    }
+    assert_eq!(buffers.buffer_stats(), (2, 0, 1));
+    unsafe {
+        buffers.recycle_older_than_all([&slice3[..]]);
+    }
+    assert_eq!(buffers.buffer_stats(), (1, 0, 2));
+    assert_eq!(slice3, (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_send_between_threads() {
+    let buffers = SlidingBuffers::<usize>::with_capacity(10);
+    buffers.alloc_iter(0..15);
+    let buffers = std::thread::spawn(move || {
+        let slice = buffers.alloc_iter(15..20);
+        assert_eq!(slice, [15, 16, 17, 18, 19]);
+        buffers
+    })
+    .join()
+    .unwrap();
+    assert_eq!(buffers.capacity(), 20);
+    assert_eq!(buffers.buffer_stats(), (1, 0, 0));
+}
@@ -0,0 +1,179 @@
+#![cfg(feature = "alloc")]
+
+use sliding_tree::{CompactNode, CompactTree};
+
+mod common;
+use common::{Counters, DropCounter};
+
+fn child_data<'t>(
+    iter: impl Iterator<Item = CompactNode<'t, usize>>,
+) -> Vec<usize> {
+    iter.map(|node| *node.get()).collect()
+}
+
+fn count_nodes<'t, T: 't>(
+    iter: impl Iterator<Item = CompactNode<'t, T>>,
+) -> usize {
+    iter.map(|node| 1 + count_nodes(node.iter())).sum()
+}
+
+#[test]
+fn test_compact_tree() {
+    let mut tree: CompactTree<usize> = CompactTree::new();
+    assert!(tree.is_empty());
+    tree.set_children([1, 2, 3]);
+    assert_eq!(tree.len(), 3);
+    tree.at_mut(1).set_children([40, 50]);
+    assert_eq!(tree.at(0).len(), 0);
+    assert!(!tree.at(0).is_expanded());
+    assert_eq!(child_data(tree.at(1).iter()), [40, 50]);
+    assert_eq!(*tree.at(1).at(1).get(), 50);
+    assert_eq!(child_data(tree.at(1).iter().rev()), [50, 40]);
+
+    *tree.at_mut(2).get_mut() = 30;
+    let mut node = tree.at_mut(1);
+    node.at_mut(0).set_children([]);
+    assert!(node.at(0).is_expanded() && node.at(0).is_empty());
+    assert_eq!(
+        format!("{:?}", node),
+        "CompactNode { data: 2, children: [\
+         CompactNode { data: 40, children: [] }, \
+         CompactNode { data: 50, children: [] }] }"
+    );
+    assert_eq!(child_data(tree.iter()), [1, 2, 30]);
+}
+
+#[test]
+fn test_compact_tree_subtree() {
+    let mut tree: CompactTree<usize> = CompactTree::with_capacity(16);
+    tree.set_children_subtree([(1, 2), (2, 3)], |mut node, width| {
+        node.set_children_subtree((0..width).map(|i| (i, i)), |mut node, i| {
+            node.set_children(0..i);
+        });
+    });
+    assert_eq!(child_data(tree.iter()), [1, 2]);
+    assert_eq!(child_data(tree.at(0).iter()), [0, 1]);
+    assert_eq!(child_data(tree.at(1).at(2).iter()), [0, 1]);
+    assert_eq!(count_nodes(tree.iter()), 2 + 5 + 4);
+    assert_eq!(tree.allocated(), 11);
+}
+
+#[test]
+fn test_compact_tree_expand_with() {
+    let mut tree: CompactTree<usize> = CompactTree::new();
+    tree.set_children([2, 0]);
+    for i in 0..tree.len() {
+        assert!(tree.at_mut(i).expand_with(|&mut n| 0..n));
+    }
+    assert!(!tree.at_mut(0).expand_with(|_| [9]));
+    assert_eq!(child_data(tree.at(0).iter()), [0, 1]);
+    assert!(tree.at(1).is_expanded() && tree.at(1).is_empty());
+}
+
+#[test]
+fn test_compact_tree_recycle() {
+    // Each group of four nodes fills a buffer.
+    let mut tree: CompactTree<usize> = CompactTree::with_capacity(4);
+    tree.set_children(0..4);
+    for i in 0..4 {
+        let mut node = tree.at_mut(i);
+        node.set_children(0..4);
+        for j in 0..4 {
+            node.at_mut(j).set_children(0..4);
+        }
+    }
+    assert_eq!(tree.allocated(), 4 + 16 + 64);
+
+    // Buffers allocated before the new roots are recycled, but those holding
+    // unreachable nodes allocated after them are kept until the root passes
+    // them.
+    tree.at_mut(2).move_children_to_root();
+    assert!(tree.at(0).is_expanded());
+    tree.recycle();
+    assert_eq!(tree.allocated(), 4 + 16 + 64 - 44);
+    assert_eq!(count_nodes(tree.iter()), 20);
+
+    tree.adopt_grandchildren_at(3);
+    tree.recycle();
+    assert_eq!(child_data(tree.iter()), [0, 1, 2, 3]);
+    assert_eq!(tree.allocated(), 4 + 20);
+
+    // The indices keep working as the tree continues to grow and recycle.
+    for round in 0..100 {
+        tree.at_mut(round % 4).set_children([round, round + 1]);
+        tree.at_mut(round % 4).move_children_to_root();
+        tree.at_mut(0).set_children(0..4);
+        tree.adopt_grandchildren_at(0);
+        tree.recycle();
+        assert_eq!(child_data(tree.iter()), [0, 1, 2, 3]);
+        assert_eq!(tree.allocated(), 4);
+    }
+    tree.trim();
+    assert!(tree.capacity() >= 4);
+
+    tree.at_mut(0).move_children_to_root();
+    assert!(tree.is_empty());
+    tree.recycle();
+    assert_eq!(tree.allocated(), 0);
+}
+
+#[test]
+fn test_compact_tree_growth_keeps_nodes_in_place() {
+    let mut tree: CompactTree<usize> = CompactTree::with_capacity(8);
+    tree.set_children(0..8);
+    let first: *const usize = tree.at(0).get();
+    for i in 0..8 {
+        let mut node = tree.at_mut(i);
+        node.set_children(0..8);
+        for j in 0..8 {
+            node.at_mut(j).set_children(0..8);
+        }
+    }
+    assert_eq!(tree.buffer_stats(), (73, 0, 0));
+    assert!(core::ptr::eq(first, tree.at(0).get()));
+    assert_eq!(*tree.at(7).at(7).at(7).get(), 7);
+    assert_eq!(count_nodes(tree.iter()), 8 + 64 + 512);
+
+    tree.at_mut(7).at_mut(7).move_children_to_root();
+    tree.recycle();
+    assert_eq!(tree.allocated(), 8);
+    assert_eq!(child_data(tree.iter()), (0..8).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_compact_tree_adopt_unexpanded() {
+    let mut tree: CompactTree<usize> = CompactTree::new();
+    tree.set_children([0, 1]);
+    tree.at_mut(0).set_children([2]);
+    tree.at_mut(0).adopt_grandchildren_at(0);
+    assert!(!tree.at(0).is_expanded());
+    tree.adopt_grandchildren_at(1);
+    assert!(tree.is_empty());
+    assert_eq!(tree.iter().count(), 0);
+}
+
+#[test]
+fn test_compact_tree_drops_data() {
+    let counters = Counters::new();
+    {
+        let mut tree: CompactTree<DropCounter> = CompactTree::with_capacity(4);
+        tree.set_children((0..3).map(|_| DropCounter::new(&counters)));
+        tree.at_mut(1)
+            .set_children((0..4).map(|_| DropCounter::new(&counters)));
+        tree.at_mut(1).move_children_to_root();
+        tree.recycle();
+        assert_eq!(counters.dropped(), 3);
+        tree.clear();
+        assert_eq!(counters.dropped(), 7);
+        tree.set_children((0..2).map(|_| DropCounter::new(&counters)));
+    }
+    assert!(counters.balanced());
+}
+
+#[test]
+#[should_panic(expected = "index (is 2) should be < len (is 2)")]
+fn test_compact_tree_index_out_of_bounds() {
+    let mut tree: CompactTree<usize> = CompactTree::new();
+    tree.set_children([0, 1]);
+    tree.at(2);
+}
@@ -0,0 +1,179 @@
+#![cfg(feature = "alloc")]
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use sliding_tree::{ConcurrentNode, ConcurrentTree};
+
+#[derive(Debug, Default)]
+struct Stats {
+    depth: usize,
+    visits: AtomicUsize,
+    expansions: AtomicUsize,
+}
+
+impl Stats {
+    fn new(depth: usize) -> Stats {
+        Stats {
+            depth,
+            ..Stats::default()
+        }
+    }
+
+    fn children(&self) -> impl Iterator<Item = Stats> + use<> {
+        self.expansions.fetch_add(1, Ordering::Relaxed);
+        let depth = self.depth + 1;
+        (0..3).map(move |_| Stats::new(depth))
+    }
+}
+
+fn count_nodes<'t, T: 't>(
+    nodes: impl Iterator<Item = ConcurrentNode<'t, T>>,
+) -> usize {
+    nodes.map(|node| 1 + count_nodes(node.iter())).sum()
+}
+
+fn count_expansions(node: ConcurrentNode<'_, Stats>) -> usize {
+    node.get().expansions.load(Ordering::Relaxed)
+        + node.iter().map(count_expansions).sum::<usize>()
+}
+
+fn check_expansions(node: ConcurrentNode<'_, Stats>) {
+    let expected = if node.is_expanded() { 1 } else { 0 };
+    assert_eq!(node.get().expansions.load(Ordering::Relaxed), expected);
+    node.iter().for_each(check_expansions);
+}
+
+#[test]
+fn test_concurrent_descent() {
+    const THREADS: usize = 4;
+    let mut tree: ConcurrentTree<Stats> = ConcurrentTree::with_capacity(32, 4);
+    tree.set_children([Stats::new(0), Stats::new(0)]);
+
+    thread::scope(|scope| {
+        for _ in 0..THREADS {
+            let mut worker = tree.worker().unwrap();
+            scope.spawn(move || {
+                for _ in 0..100 {
+                    // Follow the least visited children down to a leaf.
+                    let tree = worker.tree();
+                    let mut node = tree
+                        .iter()
+                        .min_by_key(|n| n.get().visits.load(Ordering::Relaxed))
+                        .unwrap();
+                    while node.is_expanded() && !node.is_empty() {
+                        node.get().visits.fetch_add(1, Ordering::Relaxed);
+                        node = node
+                            .iter()
+                            .min_by_key(|n| {
+                                n.get().visits.load(Ordering::Relaxed)
+                            })
+                            .unwrap();
+                    }
+                    node.get().visits.fetch_add(1, Ordering::Relaxed);
+                    worker.expand_with(node, Stats::children);
+                }
+            });
+        }
+    });
+    assert!(tree.worker().is_some());
+
+    // Every node was expanded at most once, by whichever thread claimed it.
+    tree.iter().for_each(check_expansions);
+    let expansions: usize = tree.iter().map(count_expansions).sum();
+    assert_eq!(count_nodes(tree.iter()), 2 + 3 * expansions);
+    assert!(expansions > 100);
+
+    // Slide the root and recycle while no workers exist.
+    let (finished, current, _) = tree.buffer_stats();
+    tree.adopt_grandchildren_at(1);
+    assert_eq!(tree.len(), 3);
+    assert!(tree.iter().all(|node| node.get().depth == 1));
+    let remaining = count_nodes(tree.iter());
+    tree.recycle();
+    let (kept_finished, kept_current, _) = tree.buffer_stats();
+    assert!(kept_finished + kept_current <= finished + current);
+    assert_eq!(count_nodes(tree.iter()), remaining);
+    tree.iter().for_each(check_expansions);
+}
+
+#[test]
+fn test_workers_claim_shards() {
+    let mut tree: ConcurrentTree<usize> = ConcurrentTree::new(2);
+    tree.set_children([0]);
+    let a = tree.worker().unwrap();
+    let mut b = tree.worker().unwrap();
+    assert!(tree.worker().is_none());
+    drop(a);
+    let mut c = tree.worker().unwrap();
+
+    assert!(b.expand_with(tree.at(0), |&n| n + 1..n + 3));
+    assert!(!c.expand_with(tree.at(0), |_| [9]));
+    assert!(c.expand_with(tree.at(0).at(1), |_| []));
+    assert!(tree.at(0).at(1).is_expanded());
+    assert!(tree.at(0).at(1).is_empty());
+    assert!(!tree.at(0).at(0).is_expanded());
+    assert_eq!(
+        format!("{:?}", tree),
+        "ConcurrentTree { roots: [ConcurrentNode { data: 0, children: [\
+         ConcurrentNode { data: 1, children: [] }, \
+         ConcurrentNode { data: 2, children: [] }] }] }"
+    );
+}
+
+#[test]
+fn test_expand_with_panic_leaves_node_unexpanded() {
+    let mut tree: ConcurrentTree<usize> = ConcurrentTree::with_capacity(8, 1);
+    tree.set_children([0]);
+    let mut worker = tree.worker().unwrap();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        worker.expand_with(tree.at(0), |_| -> [usize; 0] { panic!("boom") })
+    }));
+    assert!(result.is_err());
+    assert!(!tree.at(0).is_expanded());
+    assert!(worker.expand_with(tree.at(0), |_| [1, 2]));
+    assert_eq!(tree.at(0).len(), 2);
+}
+
+#[test]
+#[should_panic(expected = "node belongs to a different ConcurrentTree")]
+fn test_expand_with_node_from_other_tree() {
+    let mut a: ConcurrentTree<usize> = ConcurrentTree::with_capacity(8, 1);
+    let b: ConcurrentTree<usize> = ConcurrentTree::with_capacity(8, 1);
+    a.set_children([0]);
+    b.worker().unwrap().expand_with(a.at(0), |_| [1]);
+}
+
+#[test]
+fn test_recycle_drops_unreachable_data() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    struct Counted;
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let mut tree: ConcurrentTree<Counted> = ConcurrentTree::with_capacity(4, 2);
+    tree.set_children((0..4).map(|_| Counted));
+    thread::scope(|scope| {
+        for i in 0..2 {
+            let tree = &tree;
+            scope.spawn(move || {
+                let mut worker = tree.worker().unwrap();
+                for j in [i, i + 2] {
+                    worker.expand_with(tree.at(j), |_| (0..4).map(|_| Counted));
+                }
+            });
+        }
+    });
+    assert_eq!(count_nodes(tree.iter()), 20);
+    tree.adopt_grandchildren_at(3);
+    tree.recycle();
+    assert_eq!(tree.len(), 4);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 16);
+    tree.clear();
+    assert!(tree.is_empty());
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 20);
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "alloc")]
+
+use std::cell::Cell;
+
+use sliding_tree::{DagNode, DagTree};
+
+mod common;
+use common::{Counters, DropCounter};
+
+fn child_data<'t, 'a: 't>(
+    iter: impl Iterator<Item = DagNode<'t, 'a, usize>>,
+) -> Vec<usize> {
+    iter.map(|node| *node.get()).collect()
+}
+
+#[test]
+fn test_dag_tree() {
+    let tree: DagTree<usize> = DagTree::new();
+    assert!(tree.is_empty());
+    tree.set_children([1, 2, 3]);
+    assert_eq!(tree.len(), 3);
+    tree.at(1).set_children([40, 50]);
+    assert!(!tree.at(0).is_expanded());
+    assert_eq!(child_data(tree.at(1).iter()), [40, 50]);
+    assert_eq!(child_data(tree.at(1).iter().rev()), [50, 40]);
+
+    // Both parents refer to the same children.
+    tree.at(2).share_children_of(tree.at(1));
+    assert!(tree.at(2).shares_children_with(tree.at(1)));
+    assert!(!tree.at(0).shares_children_with(tree.at(1)));
+    tree.at(2).at(0).set_children([]);
+    assert!(tree.at(1).at(0).is_expanded() && tree.at(1).at(0).is_empty());
+    assert_eq!(
+        format!("{:?}", tree.at(2)),
+        "DagNode { data: 3, children: [\
+         DagNode { data: 40, children: [] }, \
+         DagNode { data: 50, children: [] }] }"
+    );
+
+    assert!(tree.at(0).expand_with(|&n| [n + 10]));
+    assert!(!tree.at(0).expand_with(|_| [0]));
+    assert_eq!(child_data(tree.at(0).iter()), [11]);
+}
+
+#[test]
+fn test_dag_tree_statistics() {
+    // Statistics are updated through cells along every path to a position.
+    let tree: DagTree<Cell<u32>> = DagTree::new();
+    tree.set_children([Cell::new(0), Cell::new(0)]);
+    tree.at(0).set_children([Cell::new(0)]);
+    tree.at(1).share_children_of(tree.at(0));
+    for root in tree.iter() {
+        root.get().set(root.get().get() + 1);
+        let child = root.at(0);
+        child.get().set(child.get().get() + 1);
+    }
+    assert_eq!(tree.at(0).get().get(), 1);
+    assert_eq!(tree.at(1).at(0).get().get(), 2);
+}
+
+#[test]
+fn test_dag_tree_recycle() {
+    let mut tree: DagTree<usize> = DagTree::with_capacity(4);
+    tree.set_children(0..4);
+    tree.at(0).set_children(10..14);
+    tree.at(1).set_children(20..24);
+    tree.at(1).at(0).share_children_of(tree.at(0));
+    assert_eq!(tree.buffer_stats(), (3, 0, 0));
+
+    // The shared children are older than the new roots but stay reachable,
+    // so only the buffer of the old roots is recycled.
+    tree.adopt_grandchildren_at(1);
+    tree.recycle();
+    assert_eq!(tree.buffer_stats(), (2, 0, 1));
+    assert_eq!(child_data(tree.iter()), [20, 21, 22, 23]);
+    assert_eq!(child_data(tree.at(0).iter()), [10, 11, 12, 13]);
+
+    tree.adopt_grandchildren_at(0);
+    tree.recycle();
+    assert_eq!(tree.buffer_stats(), (1, 0, 2));
+    assert_eq!(child_data(tree.iter()), [10, 11, 12, 13]);
+
+    tree.set_children([]);
+    tree.recycle();
+    assert_eq!(tree.buffer_stats(), (0, 0, 3));
+}
+
+#[test]
+fn test_dag_tree_drops_data() {
+    let counters = Counters::new();
+    {
+        let mut tree: DagTree<DropCounter> = DagTree::with_capacity(8);
+        tree.set_children((0..3).map(|_| DropCounter::new(&counters)));
+        tree.at(0)
+            .set_children((0..5).map(|_| DropCounter::new(&counters)));
+        tree.at(1).share_children_of(tree.at(0));
+        tree.at(2).share_children_of(tree.at(0));
+        tree.recycle();
+        assert_eq!(counters.dropped(), 0);
+        tree.clear();
+        assert_eq!(counters.dropped(), 8);
+        tree.set_children((0..2).map(|_| DropCounter::new(&counters)));
+    }
+    assert!(counters.balanced());
+}
+
+#[test]
+#[should_panic(expected = "node belongs to a different DagTree")]
+fn test_dag_tree_share_from_other_tree() {
+    let a: DagTree<usize> = DagTree::new();
+    let b: DagTree<usize> = DagTree::new();
+    a.set_children([0]);
+    b.set_children([1]);
+    b.at(0).set_children([2]);
+    a.at(0).share_children_of(b.at(0));
+}
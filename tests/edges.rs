@@ -0,0 +1,109 @@
+#![cfg(feature = "alloc")]
+
+use sliding_tree::{EdgeTree, HasEdges, HasEdgesMut};
+
+fn edges<'a, H: HasEdges<'a, N, E>, N: 'a, E: Copy + 'a>(node: &H) -> Vec<E> {
+    node.edges().copied().collect()
+}
+
+fn data<'a, H: HasEdges<'a, N, E>, N: Copy + 'a, E: 'a>(node: &H) -> Vec<N> {
+    node.iter().map(|n| *n.get()).collect()
+}
+
+#[test]
+fn test_edges_and_data() {
+    let mut tree: EdgeTree<u32, char> = EdgeTree::with_capacity(100);
+    tree.set_children([('a', 1), ('b', 2), ('c', 3)]);
+    assert_eq!(edges(&tree), ['a', 'b', 'c']);
+    assert_eq!(data(&tree), [1, 2, 3]);
+    assert_eq!(tree.edges().len(), 3);
+    assert_eq!(tree.edges().next_back(), Some(&'c'));
+
+    let mut node = tree.at_mut(1);
+    *node.edge_mut() = 'B';
+    *node.get_mut() += 10;
+    node.set_children([('x', 20), ('y', 21)]);
+    assert_eq!(*tree.at(1).edge(), 'B');
+    assert_eq!(*tree.at(1).get(), 12);
+    assert_eq!(edges(tree.at(1)), ['x', 'y']);
+    assert_eq!(
+        format!("{:?}", tree.at(1).at(0)),
+        "EdgeNode { edge: 'x', data: 20, children: [] }"
+    );
+}
+
+#[test]
+fn test_set_children_subtree() {
+    let mut tree: EdgeTree<usize, u8> = EdgeTree::with_capacity(10);
+    tree.set_children_subtree(
+        (0..3).map(|i| (i as u8, i * 10, i)),
+        |mut node, i| {
+            node.set_children((0..i).map(|j| (j as u8 + 100, j)));
+        },
+    );
+    assert_eq!(edges(&tree), [0, 1, 2]);
+    assert_eq!(data(&tree), [0, 10, 20]);
+    assert_eq!(edges(tree.at(2)), [100, 101]);
+    assert_eq!(data(tree.at(2)), [0, 1]);
+}
+
+#[test]
+fn test_move_children_to_root() {
+    let mut tree: EdgeTree<i32, &str> = EdgeTree::with_capacity(100);
+    tree.set_children([("left", 1), ("right", 2)]);
+    tree.at_mut(1).set_children([("up", 3), ("down", 4)]);
+
+    // The new roots keep the labels of the edges that led to them.
+    tree.at_mut(1).move_children_to_root();
+    tree.recycle();
+    assert_eq!(edges(&tree), ["up", "down"]);
+    assert_eq!(data(&tree), [3, 4]);
+
+    tree.move_children_to_root();
+    assert_eq!(edges(&tree), ["up", "down"]);
+
+    tree.at_mut(0).set_children([("in", 5)]);
+    tree.adopt_grandchildren_at(0);
+    assert_eq!(edges(&tree), ["in"]);
+    assert_eq!(data(&tree), [5]);
+}
+
+#[test]
+fn test_sibling_editing() {
+    let mut tree: EdgeTree<u32, char> = EdgeTree::with_capacity(100);
+    tree.set_children([('a', 1)]);
+    tree.at_mut(0).set_children([('x', 9)]);
+    tree.push_child('b', 2);
+    tree.extend_children([('c', 3), ('d', 4)]);
+    tree.retain_children(|node| *node.edge() != 'c');
+    tree.remove_child(2);
+    assert_eq!(edges(&tree), ['a', 'b']);
+    assert_eq!(data(&tree), [1, 2]);
+    assert_eq!(edges(tree.at(0)), ['x']);
+
+    let mut count = 0;
+    for mut node in tree.iter_mut() {
+        count += 1;
+        *node.get_mut() *= 2;
+    }
+    assert_eq!(count, 2);
+    assert_eq!(data(&tree), [2, 4]);
+    assert!(tree.try_set_children([('z', 0)]).is_ok());
+    assert_eq!(
+        format!("{:?}", tree),
+        "EdgeTree { roots: [EdgeNode { edge: 'z', data: 0, children: [] }] }"
+    );
+}
+
+#[test]
+fn test_expand_with() {
+    let mut tree: EdgeTree<u32, char> = EdgeTree::with_capacity(100);
+    tree.set_children([('a', 2)]);
+    assert!(!tree.at(0).is_expanded());
+    let mut node = tree.at_mut(0);
+    assert!(node.expand_with(|&mut n| (0..n).map(|i| ((b'x' + i as u8) as char, i))));
+    assert!(!node.expand_with(|_| [('z', 0)]));
+    assert!(node.is_expanded());
+    assert_eq!(edges(tree.at(0)), ['x', 'y']);
+    assert_eq!(data(tree.at(0)), [0, 1]);
+}
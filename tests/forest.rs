@@ -0,0 +1,123 @@
+#![cfg(feature = "alloc")]
+
+use sliding_tree::{HasChildren, HasChildrenMut, SlidingForest};
+
+mod common;
+use common::{Counters, DropCounter};
+
+fn child_data<'a, H: HasChildren<'a, usize>>(node: &H) -> Vec<usize> {
+    node.iter().map(|n| *n.get()).collect()
+}
+
+#[test]
+fn test_forest_shares_buffers() {
+    let mut forest: SlidingForest<usize> = SlidingForest::with_capacity(10);
+    let ids: Vec<_> = (0..3).map(|_| forest.open_tree()).collect();
+    for (i, &id) in ids.iter().enumerate() {
+        let mut tree = forest.tree_mut(id);
+        tree.set_children([i, i + 10]);
+        tree.at_mut(1).set_children([i + 20]);
+    }
+    assert_eq!(forest.len(), 3);
+    assert_eq!(forest.buffer_stats(), (0, 1, 0));
+    assert_eq!(child_data(&forest.tree(ids[1])), [1, 11]);
+    assert_eq!(child_data(forest.tree(ids[2]).at(1)), [22]);
+    assert_eq!(forest.tree_ids().collect::<Vec<_>>(), ids);
+    assert_eq!(
+        format!("{:?}", forest.tree_mut(ids[0])),
+        "ForestTree { roots: [Node { data: 0, children: [] }, \
+         Node { data: 10, children: [Node { data: 20, children: [] }] }] }"
+    );
+}
+
+#[test]
+fn test_forest_recycle_by_oldest_root() {
+    let mut forest: SlidingForest<usize> = SlidingForest::with_capacity(4);
+    let a = forest.open_tree();
+    let b = forest.open_tree();
+    forest.tree_mut(a).set_children(0..4);
+    forest.tree_mut(b).set_children(10..14);
+    forest.tree_mut(a).at_mut(0).set_children(20..24);
+    forest.tree_mut(a).at_mut(0).move_children_to_root();
+    assert_eq!(forest.buffer_stats(), (3, 0, 0));
+
+    // The roots of `b` hold back the buffers allocated after them.
+    forest.recycle();
+    assert_eq!(forest.buffer_stats(), (2, 0, 1));
+    assert_eq!(child_data(&forest.tree(a)), [20, 21, 22, 23]);
+    assert_eq!(child_data(&forest.tree(b)), [10, 11, 12, 13]);
+
+    // Closing `b` lets the buffers slide forward.
+    forest.close_tree(b);
+    assert!(!forest.contains_tree(b));
+    forest.recycle();
+    assert_eq!(forest.buffer_stats(), (1, 0, 2));
+    assert_eq!(child_data(&forest.tree(a)), [20, 21, 22, 23]);
+
+    // A tree opened later reuses the freed identifier.
+    let c = forest.open_tree();
+    assert_eq!(c, b);
+    assert!(forest.tree(c).is_empty());
+    forest.close_tree(a);
+    forest.recycle();
+    assert_eq!(forest.buffer_stats(), (0, 0, 3));
+}
+
+#[test]
+fn test_forest_pending_roots_stay_with_their_tree() {
+    let mut forest: SlidingForest<usize> = SlidingForest::with_capacity(10);
+    let a = forest.open_tree();
+    let b = forest.open_tree();
+    forest.tree_mut(a).set_children([0, 1]);
+    forest.tree_mut(a).at_mut(1).set_children([2, 3]);
+    forest.tree_mut(b).set_children([4]);
+
+    // The temporary reference to `a` is dropped before its roots are moved.
+    forest.tree_mut(a).at_mut(1).move_children_to_root();
+    forest.tree_mut(b).push_child(5);
+    assert_eq!(child_data(&forest.tree(a)), [2, 3]);
+    assert_eq!(child_data(&forest.tree(b)), [4, 5]);
+
+    forest.tree_mut(b).at_mut(0).set_children([6]);
+    forest.tree_mut(b).at_mut(0).move_children_to_root();
+    forest.recycle();
+    assert_eq!(child_data(&forest.tree(a)), [2, 3]);
+    assert_eq!(child_data(&forest.tree(b)), [6]);
+}
+
+#[test]
+#[should_panic(expected = "tree has been closed")]
+fn test_forest_closed_tree_panics() {
+    let mut forest: SlidingForest<usize> = SlidingForest::with_capacity(10);
+    let id = forest.open_tree();
+    forest.close_tree(id);
+    forest.tree_mut(id);
+}
+
+#[test]
+fn test_forest_drops_data() {
+    let counters = Counters::new();
+    {
+        let mut forest: SlidingForest<DropCounter> =
+            SlidingForest::with_capacity(4);
+        let a = forest.open_tree();
+        let b = forest.open_tree();
+        forest
+            .tree_mut(a)
+            .set_children((0..4).map(|_| DropCounter::new(&counters)));
+        forest
+            .tree_mut(b)
+            .set_children((0..4).map(|_| DropCounter::new(&counters)));
+        forest.close_tree(a);
+        forest.recycle();
+        assert_eq!(counters.dropped(), 4);
+        forest.clear();
+        assert!(forest.is_empty());
+        assert_eq!(counters.dropped(), 8);
+        let c = forest.open_tree();
+        forest
+            .tree_mut(c)
+            .set_children((0..2).map(|_| DropCounter::new(&counters)));
+    }
+    assert!(counters.balanced());
+}
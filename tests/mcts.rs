@@ -1,3 +1,5 @@
+#![cfg(feature = "alloc")]
+
 use enumoid::{EnumMap, Enumoid};
 use rand::prelude::*;
 use sliding_tree::{HasChildren, HasChildrenMut, Node, NodeMut, SlidingTree};
@@ -152,15 +154,15 @@ fn select_and_backpropagate(
     rng: &mut impl Rng,
 ) -> Player {
     let state = node.get();
-    let winner = if !state.is_terminal() && !node.is_empty() {
+    let winner = if state.is_terminal() {
+        state.winning_player(goal).unwrap()
+    } else if node.is_expanded() {
         let index =
             select_with_ucb(node.iter(), state.visits, state.next_player);
         let mut child_node = node.at_mut(index);
         select_and_backpropagate(&mut child_node, goal, rng)
-    } else if !state.is_terminal() {
-        expand_and_simulate(node, goal, rng)
     } else {
-        state.winning_player(goal).unwrap()
+        expand_and_simulate(node, goal, rng)
     };
     node.get_mut().update_stats(winner);
     winner
@@ -171,17 +173,19 @@ fn expand_and_simulate(
     goal: Goal,
     rng: &mut impl Rng,
 ) -> Player {
-    let state = node.get().clone();
     // Expansion
-    let moves = state.possible_moves();
-    node.set_children(moves.iter().map(|&mv| {
-        let mut new_state = state.clone();
-        new_state.apply_move(mv);
-        new_state
-    }));
+    node.expand_with(|state| {
+        let state = state.clone();
+        let moves = state.possible_moves();
+        moves.into_iter().map(move |mv| {
+            let mut new_state = state.clone();
+            new_state.apply_move(mv);
+            new_state
+        })
+    });
 
     // Simulation
-    let mut sim_state = state.clone();
+    let mut sim_state = node.get().clone();
     while !sim_state.is_terminal() {
         let moves = sim_state.possible_moves();
         let mv = moves[rng.random_range(0..moves.len())];
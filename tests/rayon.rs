@@ -0,0 +1,215 @@
+#![cfg(feature = "rayon")]
+
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+use sliding_tree::{HasChildren, HasChildrenMut, Node, NodeMut, SlidingTree};
+
+fn count_nodes(nodes: &[Node<'_, usize>]) -> usize {
+    nodes
+        .iter()
+        .map(|node| 1 + count_nodes(node.children()))
+        .sum()
+}
+
+fn child_data<'a, H: HasChildren<'a, T>, T: Copy + 'a>(node: &H) -> Vec<T> {
+    node.iter().map(|n| *n.get()).collect()
+}
+
+/// Builds a complete subtree of the given depth and width below `node`,
+/// where each node holds its depth.
+fn build(mut node: NodeMut<'_, '_, usize>, depth: usize, width: usize) {
+    if depth > 0 {
+        node.set_children_subtree(
+            (0..width).map(|_| (depth, ())),
+            |node, ()| build(node, depth - 1, width),
+        );
+    }
+}
+
+#[test]
+fn test_par_set_children_subtree() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(50);
+    tree.par_set_children_subtree(
+        (0..64).into_par_iter().map(|i| (i, i)),
+        |node, i| build(node, 2 + i % 2, 3),
+    );
+    assert_eq!(child_data(&tree), (0..64).collect::<Vec<_>>());
+    assert_eq!(count_nodes(tree.at(0).children()), 3 + 9);
+    assert_eq!(count_nodes(tree.at(1).children()), 3 + 9 + 27);
+    assert_eq!(count_nodes(tree.children()), 64 + 32 * 12 + 32 * 39);
+
+    // Recycling checks that every node can reference its children.
+    tree.recycle();
+    let (finished, current, _) = tree.buffer_stats();
+    tree.at_mut(63).at_mut(2).move_children_to_root();
+    tree.recycle();
+    assert_eq!(child_data(&tree), [2, 2, 2]);
+    assert_eq!(count_nodes(tree.children()), 12);
+    let (kept_finished, kept_current, _) = tree.buffer_stats();
+    assert!(kept_finished + kept_current < (finished + current) / 2);
+
+    // The tree can keep growing sequentially afterwards.
+    tree.at_mut(0).at_mut(0).set_children([7, 8]);
+    tree.recycle();
+    assert_eq!(child_data(tree.at(0).at(0)), [7, 8]);
+}
+
+#[test]
+fn test_par_set_children_subtree_one_state_per_worker() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(10000);
+    tree.par_set_children_subtree(
+        (0..1000).into_par_iter().map(|i| (i, ())),
+        |node, ()| build(node, 1, 2),
+    );
+    assert_eq!(count_nodes(tree.children()), 3000);
+
+    // Each worker fills a single buffer, however the items are split.
+    let (finished, current, _) = tree.buffer_stats();
+    assert!(finished + current <= rayon::current_num_threads() + 1);
+    tree.recycle();
+    assert_eq!(child_data(tree.at(999)), [1, 1]);
+}
+
+#[test]
+#[should_panic(expected = "move_children_to_root cannot be called")]
+fn test_par_set_children_subtree_move_to_root_panics() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.par_set_children_subtree(
+        (0..4).into_par_iter().map(|i| (i, ())),
+        |mut node, ()| {
+            node.set_children([1, 2]);
+            node.move_children_to_root();
+        },
+    );
+}
+
+#[test]
+#[should_panic(expected = "move_children_to_root cannot be called")]
+fn test_par_set_children_subtree_static_storage_move_to_root_panics() {
+    let region = Box::leak(Box::new([MaybeUninit::uninit(); 8192]));
+    let mut tree: SlidingTree<usize> =
+        SlidingTree::with_static_storage(region, 4);
+    tree.par_set_children_subtree(
+        (0..4).into_par_iter().map(|i| (i, ())),
+        |mut node, ()| {
+            node.set_children([1, 2]);
+            node.move_children_to_root();
+        },
+    );
+}
+
+#[test]
+fn test_par_set_children_subtree_nested() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(20);
+    tree.set_children([0, 1]);
+    tree.at_mut(1).par_set_children_subtree(
+        (0..8).into_par_iter().map(|i| (i, i)),
+        |mut node, i| {
+            node.par_set_children_subtree(
+                (0..i).into_par_iter().map(|j| (j, ())),
+                |node, ()| build(node, 1, 2),
+            );
+        },
+    );
+
+    // Within a sequential builder, the buffers share its generation.
+    tree.at_mut(0).set_children_subtree(
+        [(10, ()), (11, ())],
+        |mut node, ()| {
+            node.par_set_children_subtree(
+                (0..10).into_par_iter().map(|j| (j, ())),
+                |node, ()| build(node, 2, 2),
+            );
+        },
+    );
+    tree.recycle();
+    assert_eq!(child_data(tree.at(1)), (0..8).collect::<Vec<_>>());
+    assert_eq!(child_data(tree.at(1).at(5)), [0, 1, 2, 3, 4]);
+    assert_eq!(count_nodes(tree.at(1).children()), 8 + 28 * 3);
+    assert_eq!(count_nodes(tree.at(0).children()), 2 + 20 * 7);
+
+    tree.at_mut(1).at_mut(7).move_children_to_root();
+    tree.recycle();
+    assert_eq!(count_nodes(tree.children()), 21);
+    tree.at_mut(0).move_children_to_root();
+    tree.recycle();
+    assert_eq!(child_data(&tree), [1, 1]);
+}
+
+#[test]
+fn test_par_set_children_subtree_drops_data() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    struct Counted;
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let mut tree: SlidingTree<Counted> = SlidingTree::with_capacity(10);
+    tree.par_set_children_subtree(
+        (0..20).into_par_iter().map(|_| (Counted, ())),
+        |mut node, ()| node.set_children((0..4).map(|_| Counted)),
+    );
+    assert_eq!(tree.len(), 20);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 0);
+    tree.clear();
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 100);
+}
+
+#[test]
+fn test_par_set_children_subtree_static_storage() {
+    let region = Box::leak(Box::new([MaybeUninit::uninit(); 8192]));
+    let mut tree: SlidingTree<usize> =
+        SlidingTree::with_static_storage(region, 4);
+    tree.par_set_children_subtree(
+        (0..5).into_par_iter().map(|i| (i, i)),
+        |node, i| build(node, 1, i),
+    );
+    assert_eq!(child_data(&tree), [0, 1, 2, 3, 4]);
+    assert_eq!(count_nodes(tree.children()), 15);
+}
+
+#[test]
+fn test_par_iter() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..10);
+    for mut node in tree.iter_mut() {
+        let i = *node.get();
+        node.set_children(0..i);
+    }
+    let sum: usize = tree
+        .par_iter()
+        .map(|node| node.par_iter().map(|child| child.get()).sum::<usize>())
+        .sum();
+    assert_eq!(sum, (0..10usize).map(|i| i * i.saturating_sub(1) / 2).sum());
+    assert_eq!(tree.freeze().par_iter().count(), 10);
+    assert_eq!(tree.at_mut(4).par_iter().count(), 4);
+}
+
+#[test]
+fn test_par_iter_mut() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..10);
+    for mut node in tree.iter_mut() {
+        node.set_children(0..3);
+    }
+    tree.par_iter_mut().for_each(|mut node| {
+        *node.get_mut() *= 10;
+        let parent = *node.get();
+        node.par_iter_mut()
+            .for_each(|mut child| *child.get_mut() += parent);
+        assert!(node.is_expanded() && !node.at_mut(0).is_expanded());
+    });
+    assert_eq!(child_data(&tree), (0..100).step_by(10).collect::<Vec<_>>());
+    assert_eq!(child_data(tree.at(7)), [70, 71, 72]);
+
+    let mut node = tree.at_mut(2);
+    node.par_iter_mut().enumerate().for_each(|(i, mut child)| {
+        assert_eq!(child.len(), 0);
+        *child.get_mut() = i;
+    });
+    assert_eq!(child_data(tree.at(2)), [0, 1, 2]);
+}
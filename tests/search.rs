@@ -0,0 +1,327 @@
+#![cfg(feature = "mcts")]
+
+use sliding_tree::{Game, Search};
+
+/// Nim where the player who takes the last object wins.
+#[derive(Clone, Debug)]
+pub struct Nim {
+    pub piles: [u8; 3],
+    pub player: u8,
+}
+
+impl Nim {
+    pub fn new(piles: [u8; 3]) -> Nim {
+        Nim { piles, player: 0 }
+    }
+}
+
+impl Game for Nim {
+    type Move = (usize, u8);
+    type Player = u8;
+
+    fn player_to_move(&self) -> u8 {
+        self.player
+    }
+
+    fn legal_moves(&self, moves: &mut Vec<(usize, u8)>) {
+        for (i, &pile) in self.piles.iter().enumerate() {
+            moves.extend((1..=pile).map(|take| (i, take)));
+        }
+    }
+
+    fn apply(&mut self, &(i, take): &(usize, u8)) {
+        self.piles[i] -= take;
+        self.player = 1 - self.player;
+    }
+
+    fn terminal_value(&self, player: u8) -> Option<f32> {
+        let over = self.piles.iter().all(|&pile| pile == 0);
+        // The player to move at the end did not take the last object.
+        over.then_some(if player == self.player { 0.0 } else { 1.0 })
+    }
+}
+
+pub fn play(search: &mut Search<Nim>, iterations: usize) -> u8 {
+    while !search.is_terminal() {
+        search.run(iterations);
+        search.play_best();
+    }
+    1 - search.state().player
+}
+
+#[cfg(test)]
+#[allow(unused_imports)]
+mod tests {
+    use super::*;
+    use sliding_tree::{
+        Evaluator, HasChildren, Leaf, Node, RaveSchedule, SearchNode,
+        SearchOptions, Selection,
+    };
+
+    /// Favours the moves which leave piles with a zero nim-sum.
+    #[allow(dead_code)]
+    struct NimSum;
+
+    impl Evaluator<Nim> for NimSum {
+        fn evaluate(
+            &mut self,
+            state: &Nim,
+            moves: &[(usize, u8)],
+            priors: &mut Vec<f32>,
+        ) -> f32 {
+            let sum = state.piles.iter().fold(0, |acc, &pile| acc ^ pile);
+            priors.extend(moves.iter().map(|&(i, take)| {
+                if sum ^ state.piles[i] ^ (state.piles[i] - take) == 0 {
+                    1.0
+                } else {
+                    0.1
+                }
+            }));
+            0.5
+        }
+    }
+
+    #[allow(dead_code)]
+    fn evaluate_batch(leaves: &mut [Leaf<Nim>]) {
+        let mut priors = Vec::new();
+        for leaf in leaves {
+            priors.clear();
+            let value =
+                NimSum.evaluate(leaf.state(), leaf.moves(), &mut priors);
+            leaf.set_evaluation(value, &priors);
+        }
+    }
+
+    #[allow(dead_code)]
+    fn in_flight<'t, 'a: 't>(
+        iter: impl Iterator<Item = &'t Node<'a, SearchNode<Nim>>>,
+    ) -> u32 {
+        iter.map(|n| n.get().in_flight() + in_flight(n.iter()))
+            .sum()
+    }
+
+    #[allow(dead_code)]
+    fn visit_counts(search: &Search<Nim>) -> Vec<u32> {
+        search.tree().iter().map(|n| n.get().visits()).collect()
+    }
+
+    #[test]
+    fn test_search_best_move() {
+        let mut search = Search::new(Nim::new([1, 2, 4]));
+        assert_eq!(search.best_move(), None);
+        search.run(2000);
+        assert_eq!(search.visits(), 2000);
+        assert_eq!(search.tree().len(), 7);
+        let visits: u32 = search.tree().iter().map(|n| n.get().visits()).sum();
+        assert_eq!(visits, 2000);
+
+        // Taking one from the largest pile is the only winning move.
+        assert_eq!(search.best_move(), Some(&(2, 1)));
+        let best = search.tree().iter().find(|n| *n.get().mv() == (2, 1));
+        assert!(best.unwrap().get().value() > 0.75);
+    }
+
+    #[test]
+    fn test_search_reuses_tree() {
+        let mut search = Search::with_options(
+            Nim::new([1, 2, 4]),
+            SearchOptions::new().seed(7),
+        );
+        search.run(2000);
+        let child = search.tree().iter().find(|n| *n.get().mv() == (0, 1));
+        let (visits, len) = {
+            let child = child.unwrap();
+            (child.get().visits(), child.len())
+        };
+        search.apply_move(&(0, 1));
+        assert_eq!(search.state().piles, [0, 2, 4]);
+        assert_eq!(search.visits(), visits);
+        assert_eq!(search.tree().len(), len);
+
+        // A move which has not been expanded starts a new tree.
+        let mut search = Search::new(Nim::new([1, 2, 4]));
+        search.run(1);
+        assert_eq!(search.tree().iter().filter(|n| n.is_expanded()).count(), 1);
+        let mv = *search
+            .tree()
+            .iter()
+            .find(|n| !n.is_expanded())
+            .unwrap()
+            .get()
+            .mv();
+        search.apply_move(&mv);
+        assert_eq!(search.visits(), 0);
+        assert!(search.tree().is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_search_first_player_wins() {
+        let mut search = Search::new(Nim::new([2, 3, 4]));
+        assert_eq!(play(&mut search, 10000), 0);
+        assert_eq!(search.best_move(), None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_search_second_player_wins() {
+        let mut search = Search::new(Nim::new([1, 2, 3]));
+        assert_eq!(play(&mut search, 10000), 1);
+    }
+
+    #[test]
+    fn test_search_puct() {
+        let options = SearchOptions::new()
+            .selection(Selection::Puct)
+            .exploration(1.5)
+            .first_play_urgency(0.4);
+        let mut search =
+            Search::with_evaluator(Nim::new([1, 2, 4]), NimSum, options);
+        search.run(200);
+        let priors: Vec<f32> =
+            search.tree().iter().map(|n| n.get().prior()).collect();
+        assert_eq!(priors, [0.1, 0.1, 0.1, 1.0, 0.1, 0.1, 0.1]);
+        assert_eq!(search.best_move(), Some(&(2, 1)));
+
+        // Children below the root are expanded with priors too, and no move
+        // from a zero nim-sum leaves another.
+        search.apply_move(&(2, 1));
+        assert!(search.tree().iter().all(|n| n.get().prior() == 0.1));
+    }
+
+    #[test]
+    fn test_search_root_noise() {
+        let noisy = |seed| {
+            let options = SearchOptions::new()
+                .selection(Selection::Puct)
+                .root_noise(0.3, 0.5)
+                .seed(seed);
+            let mut search = Search::with_options(Nim::new([3, 4, 5]), options);
+            search.run(100);
+            visit_counts(&search)
+        };
+        assert_eq!(noisy(1), noisy(1));
+        assert_ne!(noisy(1), noisy(2));
+        assert_eq!(noisy(1).iter().sum::<u32>(), 100);
+    }
+
+    #[test]
+    fn test_search_rave() {
+        for schedule in [
+            RaveSchedule::Equivalence(100.0),
+            RaveSchedule::MinimumError(0.1),
+        ] {
+            let options = SearchOptions::new().rave(schedule);
+            let mut search = Search::with_options(Nim::new([1, 2, 4]), options);
+            search.run(2000);
+            assert_eq!(search.best_move(), Some(&(2, 1)));
+
+            // Each node is updated whenever it is visited, and its siblings
+            // are updated when its move is played later on.
+            for node in search.tree().iter() {
+                assert!(node.get().amaf_visits() >= node.get().visits());
+            }
+            let amaf_visits: u32 =
+                search.tree().iter().map(|n| n.get().amaf_visits()).sum();
+            assert!(amaf_visits > search.visits());
+        }
+
+        // Without RAVE, no AMAF statistics are kept.
+        let mut search = Search::new(Nim::new([1, 2, 4]));
+        search.run(100);
+        assert!(search.tree().iter().all(|n| n.get().amaf_visits() == 0));
+    }
+
+    #[test]
+    fn test_search_run_batched() {
+        let options = SearchOptions::new()
+            .selection(Selection::Puct)
+            .virtual_loss(3);
+        let mut search =
+            Search::with_evaluator(Nim::new([1, 2, 4]), NimSum, options);
+        let mut batches = Vec::new();
+        search.run_batched(300, 8, |leaves| {
+            let mut paths: Vec<_> = leaves.iter().map(Leaf::path).collect();
+            paths.sort();
+            paths.dedup();
+            assert_eq!(paths.len(), leaves.len());
+            batches.push(leaves.len());
+            evaluate_batch(leaves);
+        });
+
+        // The root state is evaluated first, and virtual losses steer later
+        // descents in a batch to different leaves.
+        assert_eq!(batches[0], 1);
+        assert!(batches.iter().all(|&len| len <= 8));
+        assert!(batches.iter().any(|&len| len > 1));
+        assert_eq!(search.visits(), 300);
+        let visits: u32 = search.tree().iter().map(|n| n.get().visits()).sum();
+        assert_eq!(visits, 300);
+        assert_eq!(in_flight(search.tree().iter()), 0);
+        assert_eq!(search.best_move(), Some(&(2, 1)));
+
+        // Batched and unbatched iterations can be mixed.
+        search.run(100);
+        search.run_batched(100, 4, evaluate_batch);
+        assert_eq!(search.visits(), 500);
+    }
+
+    #[test]
+    fn test_search_solver() {
+        let options = SearchOptions::new().solver(true);
+        let mut search = Search::with_options(Nim::new([1, 2, 4]), options);
+        search.run(100000);
+        assert!(search.visits() < 100000);
+        assert_eq!(search.proven_value(), Some(1.0));
+        assert_eq!(search.best_move(), Some(&(2, 1)));
+
+        // Only the winning move needs to be proven.
+        let best = search.tree().iter().find(|n| *n.get().mv() == (2, 1));
+        assert_eq!(best.unwrap().get().proven(), Some(1.0));
+        let visits = search.visits();
+        search.run(100);
+        assert_eq!(search.visits(), visits);
+
+        // Every move from a losing position is proven to lose.
+        let mut search = Search::with_options(Nim::new([1, 2, 3]), options);
+        search.run(100000);
+        assert_eq!(search.proven_value(), Some(0.0));
+        assert!(search.tree().iter().all(|n| n.get().proven() == Some(0.0)));
+        assert!(search.best_move().is_some());
+
+        // Without the solver, nothing is proven.
+        let mut search = Search::new(Nim::new([1, 2, 4]));
+        search.run(2000);
+        assert_eq!(search.proven_value(), None);
+        assert!(search.tree().iter().all(|n| n.get().proven().is_none()));
+    }
+
+    #[test]
+    fn test_search_solver_batched() {
+        let options =
+            SearchOptions::new().selection(Selection::Puct).solver(true);
+        let mut search =
+            Search::with_evaluator(Nim::new([2, 3, 4]), NimSum, options);
+        search.run_batched(100000, 8, evaluate_batch);
+        assert!(search.visits() < 100000);
+        assert_eq!(search.proven_value(), Some(1.0));
+        assert_eq!(in_flight(search.tree().iter()), 0);
+
+        // The proven subtree is kept, so the rest of the game needs no more
+        // iterations.
+        while !search.is_terminal() {
+            let visits = search.visits();
+            search.play_best();
+            assert!(search.visits() <= visits);
+            assert!(search.proven_value().is_some() || search.is_terminal());
+        }
+        assert_eq!(search.state().player, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "evaluator should give one prior per move")]
+    fn test_search_run_batched_without_evaluation() {
+        let mut search = Search::new(Nim::new([1, 2, 4]));
+        search.run_batched(10, 4, |_| {});
+    }
+}
@@ -0,0 +1,139 @@
+#![cfg(feature = "alloc")]
+
+use sliding_tree::{SoaNode, SoaTree};
+
+mod common;
+use common::{Counters, DropCounter};
+
+fn child_data<'t>(
+    iter: impl Iterator<Item = SoaNode<'t, usize>>,
+) -> Vec<usize> {
+    iter.map(|node| *node.get()).collect()
+}
+
+fn count_nodes<'t, T: 't>(iter: impl Iterator<Item = SoaNode<'t, T>>) -> usize {
+    iter.map(|node| 1 + count_nodes(node.iter())).sum()
+}
+
+#[test]
+fn test_soa_tree() {
+    let mut tree: SoaTree<usize> = SoaTree::new();
+    assert!(tree.is_empty());
+    tree.set_children([1, 2, 3]);
+    assert_eq!(tree.len(), 3);
+    tree.at_mut(1).set_children([40, 50]);
+    assert_eq!(tree.at(0).len(), 0);
+    assert!(!tree.at(0).is_expanded());
+    assert_eq!(child_data(tree.at(1).iter()), [40, 50]);
+    assert_eq!(*tree.at(1).at(1).get(), 50);
+    assert_eq!(child_data(tree.at(1).iter().rev()), [50, 40]);
+
+    *tree.at_mut(2).get_mut() = 30;
+    let mut node = tree.at_mut(1);
+    node.at_mut(0).set_children([]);
+    assert!(node.at(0).is_expanded() && node.at(0).is_empty());
+    assert_eq!(
+        format!("{:?}", node),
+        "SoaNode { data: 2, children: [\
+         SoaNode { data: 40, children: [] }, \
+         SoaNode { data: 50, children: [] }] }"
+    );
+    assert_eq!(child_data(tree.iter()), [1, 2, 30]);
+}
+
+#[test]
+fn test_soa_tree_children_payloads() {
+    let mut tree: SoaTree<(u32, f32)> = SoaTree::new();
+    tree.set_children([(1, 0.5), (4, 0.25), (2, 1.0)]);
+    let best = |payloads: &[(u32, f32)]| {
+        (0..payloads.len())
+            .max_by(|&a, &b| payloads[a].1.total_cmp(&payloads[b].1))
+    };
+    assert_eq!(best(tree.children_payloads()), Some(2));
+
+    tree.children_payloads_mut()[0].1 = 2.0;
+    assert_eq!(best(tree.children_payloads()), Some(0));
+    assert_eq!(*tree.at(0).get(), (1, 2.0));
+
+    let mut node = tree.at_mut(0);
+    assert!(node.children_payloads().is_empty());
+    node.set_children([(0, 0.0), (0, 3.0)]);
+    for payload in node.children_payloads_mut() {
+        payload.0 += 1;
+    }
+    assert_eq!(node.children_payloads(), [(1, 0.0), (1, 3.0)]);
+    assert_eq!(best(tree.at(0).children_payloads()), Some(1));
+}
+
+#[test]
+fn test_soa_tree_subtree() {
+    let mut tree: SoaTree<usize> = SoaTree::with_capacity(4);
+    tree.set_children_subtree([(1, 2), (2, 3)], |mut node, width| {
+        node.set_children_subtree((0..width).map(|i| (i, i)), |mut node, i| {
+            node.set_children(0..i);
+        });
+    });
+    assert_eq!(child_data(tree.iter()), [1, 2]);
+    assert_eq!(child_data(tree.at(0).iter()), [0, 1]);
+    assert_eq!(child_data(tree.at(1).at(2).iter()), [0, 1]);
+    assert_eq!(count_nodes(tree.iter()), 2 + 5 + 4);
+}
+
+#[test]
+fn test_soa_tree_expand_with() {
+    let mut tree: SoaTree<usize> = SoaTree::new();
+    tree.set_children([2, 0]);
+    for i in 0..tree.len() {
+        assert!(tree.at_mut(i).expand_with(|&mut n| 0..n));
+    }
+    assert!(!tree.at_mut(0).expand_with(|_| [9]));
+    assert_eq!(child_data(tree.at(0).iter()), [0, 1]);
+    assert!(tree.at(1).is_expanded() && tree.at(1).is_empty());
+}
+
+#[test]
+fn test_soa_tree_recycle() {
+    let counters = Counters::new();
+    {
+        let mut tree: SoaTree<DropCounter> = SoaTree::with_capacity(8);
+        tree.set_children((0..4).map(|_| DropCounter::new(&counters)));
+        for i in 0..4 {
+            tree.at_mut(i)
+                .set_children((0..4).map(|_| DropCounter::new(&counters)));
+        }
+        assert_eq!(tree.buffer_stats(), (2, 1, 0));
+
+        // Only whole buffers older than the roots are recycled.
+        tree.at_mut(2).move_children_to_root();
+        tree.recycle();
+        assert_eq!(counters.dropped(), 8);
+        assert_eq!(tree.buffer_stats(), (1, 1, 1));
+        assert_eq!(count_nodes(tree.iter()), 4);
+
+        tree.at_mut(0)
+            .set_children((0..4).map(|_| DropCounter::new(&counters)));
+        tree.adopt_grandchildren_at(0);
+        tree.recycle();
+        assert_eq!(counters.dropped(), 16);
+        assert_eq!(tree.len(), 4);
+
+        tree.at_mut(0).move_children_to_root();
+        assert!(tree.is_empty());
+        tree.recycle();
+        assert_eq!(counters.dropped(), 24);
+        assert_eq!(tree.buffer_stats(), (0, 0, 3));
+        tree.trim();
+        assert_eq!(tree.buffer_stats(), (0, 0, 0));
+
+        tree.set_children((0..2).map(|_| DropCounter::new(&counters)));
+    }
+    assert!(counters.balanced());
+}
+
+#[test]
+#[should_panic(expected = "index (is 2) should be < len (is 2)")]
+fn test_soa_tree_index_out_of_bounds() {
+    let mut tree: SoaTree<usize> = SoaTree::new();
+    tree.set_children([0, 1]);
+    tree.at(2);
+}
@@ -1,9 +1,14 @@
+#![cfg(feature = "alloc")]
+
+use std::mem::MaybeUninit;
 use std::ops::RangeBounds;
 use std::panic::{self, AssertUnwindSafe};
 use std::slice;
+use std::thread;
 
 use sliding_tree::{
-    HasChildren, HasChildrenMut, Node, NodeIterMut, SlidingTree,
+    AllocError, FrozenTree, HasChildren, HasChildrenMut, Node, NodeIterMut,
+    NodeMut, SlidingTree, TranspositionTable,
 };
 
 mod common;
@@ -669,3 +674,961 @@ fn test_root_noop_and_default() {
     tree.recycle();
     assert_eq!(child_data(&tree), [0, 1, 2]);
 }
+
+#[test]
+fn test_static_storage() {
+    let region = Box::leak(Box::new([MaybeUninit::uninit(); 4096]));
+    let mut tree: SlidingTree<usize> =
+        SlidingTree::with_static_storage(region, 4);
+    let capacity = tree.capacity();
+    assert!(capacity >= 8);
+
+    // Fill the tree by following the first child until allocation fails.
+    fn grow_first(
+        node: &mut NodeMut<usize>,
+        capacity: usize,
+    ) -> Result<(), AllocError> {
+        if node.is_empty() {
+            node.try_set_children(0..capacity)
+        } else {
+            grow_first(&mut node.at_mut(0), capacity)
+        }
+    }
+    tree.try_set_children(0..capacity).unwrap();
+    let mut depth = 0;
+    while grow_first(&mut tree.at_mut(0), capacity).is_ok() {
+        depth += 1;
+    }
+    assert_eq!(depth, 3);
+    assert_eq!(tree.buffer_stats(), (4, 0, 0));
+
+    // Sliding the root allows the oldest buffer to be reused.
+    tree.at_mut(0).move_children_to_root();
+    tree.recycle();
+    assert_eq!(tree.buffer_stats(), (3, 0, 1));
+    tree.at_mut(0)
+        .at_mut(0)
+        .at_mut(0)
+        .try_set_children(0..2)
+        .unwrap();
+    assert_eq!(child_data(tree.at(0).at(0).at(0)), [0, 1]);
+}
+
+#[test]
+fn test_static_storage_slides_repeatedly() {
+    let region = Box::leak(Box::new([MaybeUninit::uninit(); 4096]));
+    let mut tree: SlidingTree<usize> =
+        SlidingTree::with_static_storage(region, 3);
+    let capacity = tree.capacity();
+    tree.set_children(0..capacity);
+
+    // The finished queue is popped from the front many times over without
+    // running out of records.
+    for i in 0..100 {
+        tree.at_mut(i % capacity)
+            .set_children((0..capacity).map(|j| i + j));
+        tree.at_mut(i % capacity).move_children_to_root();
+        tree.recycle();
+        assert_eq!(tree.buffer_stats(), (1, 0, 1));
+        assert_eq!(*tree.at(0).get(), i);
+    }
+}
+
+#[test]
+fn test_static_storage_subtree_failure_preserves_tree() {
+    let region = Box::leak(Box::new([MaybeUninit::uninit(); 4096]));
+    let mut tree: SlidingTree<usize> =
+        SlidingTree::with_static_storage(region, 2);
+    let capacity = tree.capacity();
+    tree.set_children(0..3);
+
+    // Each subtree needs a full buffer, so the second one fails.
+    let result = tree
+        .at_mut(0)
+        .try_set_children_subtree((0..2).map(|i| (i, ())), |mut node, ()| {
+            node.try_set_children(0..capacity)
+        });
+    assert_eq!(result, Err(AllocError));
+    assert_eq!(child_data(&tree), [0, 1, 2]);
+    assert!(tree.at(0).is_empty());
+}
+
+fn preorder(
+    nodes: &[Node<'_, usize>],
+    depth: usize,
+    out: &mut Vec<(usize, usize)>,
+) {
+    for node in nodes {
+        out.push((depth, *node.get()));
+        preorder(node.children(), depth + 1, out);
+    }
+}
+
+fn snapshot(tree: &SlidingTree<usize>) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    preorder(tree.children(), 0, &mut out);
+    out
+}
+
+#[test]
+fn test_compact() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..10);
+    deepen_tree(tree.iter_mut(), 0..10);
+    deepen_tree(tree.iter_mut(), 0..5);
+    assert_eq!(tree.fragmentation(), 0.0);
+
+    // Sliding onto a middle child leaves its siblings' subtrees as garbage in
+    // buffers allocated after the new roots.
+    tree.at_mut(5).move_children_to_root();
+    tree.recycle();
+    assert_eq!(stats(&tree), (60, 6, 1, 0));
+    assert!(tree.fragmentation() > 0.8);
+
+    let before = snapshot(&tree);
+    tree.compact().unwrap();
+    assert_eq!(snapshot(&tree), before);
+    assert_eq!(tree.fragmentation(), 0.0);
+    assert_eq!(stats(&tree), (60, 0, 1, 7));
+
+    // The tree remains usable afterwards.
+    tree.at_mut(9).at_mut(4).set_children(0..3);
+    tree.at_mut(9).move_children_to_root();
+    tree.recycle();
+    assert_eq!(child_data(&tree), [0, 1, 2, 3, 4]);
+    assert_eq!(child_data(tree.at(4)), [0, 1, 2]);
+}
+
+#[test]
+fn test_compact_moves_payloads() {
+    let counters = Counters::new();
+    {
+        let mut tree: SlidingTree<DropCounter> = SlidingTree::with_capacity(8);
+        tree.set_children((0..4).map(|_| DropCounter::new(&counters)));
+        tree.at_mut(1)
+            .set_children((0..6).map(|_| DropCounter::new(&counters)));
+        tree.at_mut(1)
+            .at_mut(0)
+            .set_children((0..2).map(|_| DropCounter::new(&counters)));
+        tree.at_mut(1).move_children_to_root();
+        tree.recycle();
+
+        // Payloads which cannot be cloned are moved rather than dropped.
+        assert_eq!(counters.dropped(), 4);
+        tree.compact().unwrap();
+        assert_eq!(counters.dropped(), 4);
+        assert_eq!(tree.fragmentation(), 0.0);
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.at(0).len(), 2);
+        tree.set_children([]);
+        tree.recycle();
+        assert_eq!(counters.dropped(), 12);
+    }
+    assert!(counters.balanced());
+
+    // Deep trees are moved without recursion.
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100000);
+    tree.set_children_chain(0..100000);
+    tree.compact().unwrap();
+    assert_eq!(tree.buffer_stats(), (1, 0, 1));
+}
+
+#[test]
+fn test_compact_empty() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.compact().unwrap();
+    tree.set_children(0..10);
+    tree.at_mut(0).move_children_to_root();
+    tree.compact().unwrap();
+    assert_eq!(stats(&tree), (0, 0, 0, 1));
+}
+
+#[test]
+fn test_auto_compact() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_compaction_threshold(Some(0.5));
+    tree.set_children(0..10);
+    deepen_tree(tree.iter_mut(), 0..10);
+    deepen_tree(tree.iter_mut(), 0..10);
+
+    // Most of the retained nodes are garbage, so recycling compacts the tree.
+    tree.at_mut(3).move_children_to_root();
+    tree.recycle();
+    assert_eq!(tree.fragmentation(), 0.0);
+    assert_eq!(snapshot(&tree).len(), 110);
+
+    // The threshold is not checked again until as many nodes have been
+    // allocated as were reachable.
+    tree.at_mut(3).move_children_to_root();
+    tree.recycle();
+    assert!(tree.fragmentation() > 0.5);
+    tree.at_mut(0).set_children(0..100);
+    tree.at_mut(0).set_children(0..10);
+    tree.recycle();
+    assert_eq!(tree.fragmentation(), 0.0);
+    assert_eq!(snapshot(&tree).len(), 20);
+
+    tree.set_compaction_threshold(None);
+    tree.at_mut(1).set_children(0..100);
+    tree.at_mut(1).set_children(0..1);
+    tree.recycle();
+    assert!(tree.fragmentation() > 0.5);
+}
+
+#[test]
+fn test_compact_failure_preserves_tree() {
+    let region = Box::leak(Box::new([MaybeUninit::uninit(); 4096]));
+    let mut tree: SlidingTree<usize> =
+        SlidingTree::with_static_storage(region, 4);
+    let capacity = tree.capacity();
+    tree.set_children(0..capacity);
+    tree.at_mut(0).set_children(0..capacity);
+    tree.at_mut(0).at_mut(0).set_children(0..1);
+    let before = snapshot(&tree);
+
+    // Only one buffer is free, but the copy needs three. The roots are
+    // moved before the failure, and are moved back along with the current
+    // buffer.
+    assert_eq!(tree.compact(), Err(AllocError));
+    assert_eq!(snapshot(&tree), before);
+    assert_eq!(tree.buffer_stats(), (2, 1, 1));
+
+    // Recycling after a slide makes room for compaction to succeed.
+    tree.at_mut(0).move_children_to_root();
+    tree.recycle();
+    tree.compact().unwrap();
+    assert_eq!(snapshot(&tree).len(), capacity + 1);
+}
+
+#[test]
+fn test_recycle_unreachable() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..10);
+    tree.at_mut(0).set_children(0..100);
+    tree.at_mut(1).set_children(0..50);
+    assert_eq!(stats(&tree), (160, 2, 1, 0));
+
+    // Pruning leaves a whole buffer newer than the roots unreachable.
+    tree.at_mut(0).set_children(0..2);
+    tree.recycle();
+    assert_eq!(stats(&tree), (62, 2, 1, 0));
+    tree.recycle_unreachable();
+    assert_eq!(stats(&tree), (62, 1, 1, 1));
+    assert_eq!(child_data(&tree), (0..10).collect::<Vec<_>>());
+    assert_eq!(child_data(tree.at(0)), [0, 1]);
+    assert_eq!(child_data(tree.at(1)), (0..50).collect::<Vec<_>>());
+
+    // Moving to a subtree makes the buffer holding the roots unreachable too.
+    tree.at_mut(1).move_children_to_root();
+    tree.recycle_unreachable();
+    assert_eq!(stats(&tree), (50, 0, 1, 2));
+
+    tree.at_mut(0).move_children_to_root();
+    tree.recycle_unreachable();
+    assert_eq!(stats(&tree), (0, 0, 0, 3));
+
+    // Deep trees are walked without recursion.
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100000);
+    tree.set_children_chain(0..100000);
+    tree.set_children_chain(0..100000);
+    tree.recycle_unreachable();
+    assert_eq!(tree.buffer_stats(), (1, 0, 1));
+}
+
+#[test]
+fn test_sibling_editing() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..6);
+    for (i, mut node) in tree.iter_mut().enumerate() {
+        node.set_children([i * 10, i * 10 + 1]);
+    }
+
+    // Each node keeps its subtree when siblings are edited in place.
+    tree.retain_children(|node| node.get() % 2 == 0);
+    assert_eq!(child_data(&tree), [0, 2, 4]);
+    assert_eq!(child_data(tree.at(1)), [20, 21]);
+
+    tree.swap_children(0, 2);
+    assert_eq!(child_data(&tree), [4, 2, 0]);
+    assert_eq!(child_data(tree.at(0)), [40, 41]);
+
+    tree.sort_children_by(|a, b| a.get().cmp(b.get()));
+    assert_eq!(child_data(&tree), [0, 2, 4]);
+    assert_eq!(child_data(tree.at(2)), [40, 41]);
+
+    tree.sort_children_unstable_by(|a, b| b.get().cmp(a.get()));
+    assert_eq!(child_data(&tree), [4, 2, 0]);
+    assert_eq!(child_data(tree.at(0)), [40, 41]);
+
+    tree.remove_child(1);
+    assert_eq!(child_data(&tree), [4, 0]);
+    assert_eq!(child_data(tree.at(1)), [0, 1]);
+
+    // Inserting moves the existing nodes together with their subtrees.
+    tree.insert_child(1, 7);
+    tree.at_mut(0).insert_child(2, 42);
+    assert_eq!(child_data(&tree), [4, 7, 0]);
+    assert_eq!(child_data(tree.at(0)), [40, 41, 42]);
+    assert!(tree.at(1).is_empty());
+    assert_eq!(child_data(tree.at(2)), [0, 1]);
+
+    // The buffer invariants still hold when sliding the root.
+    tree.recycle();
+    tree.at_mut(0).move_children_to_root();
+    tree.recycle();
+    assert_eq!(child_data(&tree), [40, 41, 42]);
+}
+
+#[test]
+fn test_sibling_editing_children_mut() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..1);
+    let mut node = tree.at_mut(0);
+    let mut children = node.children_mut();
+    children.set_children(0..5);
+    children.remove_child(0);
+    children.insert_child(4, 9);
+    children.retain_children(|node| *node.get() != 2);
+    assert_eq!(child_data(&children), [1, 3, 4, 9]);
+    children.retain_children(|_| false);
+    assert!(children.is_empty());
+    children.insert_child(0, 5);
+    assert_eq!(child_data(tree.at(0)), [5]);
+}
+
+#[test]
+#[should_panic(expected = "removal index (is 3) should be < len (is 3)")]
+fn test_remove_child_out_of_bounds() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..3);
+    tree.remove_child(3);
+}
+
+#[test]
+#[should_panic(expected = "insertion index (is 4) should be <= len (is 3)")]
+fn test_insert_child_out_of_bounds() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..3);
+    tree.insert_child(4, 0);
+}
+
+#[test]
+fn test_push_child_in_place() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..3);
+    tree.at_mut(0).set_children(0..2);
+
+    // The roots are no longer at the end of the buffer, so the first append
+    // moves them, leaving spare room for the following ones.
+    tree.push_child(3);
+    let after_move = stats(&tree);
+    tree.extend_children(4..6);
+    tree.at_mut(0).push_child(2);
+    assert_eq!(stats(&tree), (9, 0, 1, 0));
+    assert_eq!(after_move, (6, 0, 1, 0));
+    assert_eq!(child_data(&tree), [0, 1, 2, 3, 4, 5]);
+    assert_eq!(child_data(tree.at(0)), [0, 1, 2]);
+
+    // Appending at the end of the buffer leaves no garbage at all.
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..2);
+    tree.extend_children(2..4);
+    tree.push_child(4);
+    assert_eq!(tree.fragmentation(), 0.0);
+    assert_eq!(child_data(&tree), [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn test_progressive_widening() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(8);
+    tree.set_children(0..2);
+    let mut expected = [Vec::new(), Vec::new()];
+    for round in 0..50 {
+        // Interleaved appends to both roots, each of whose children also
+        // grows a subtree, so that most appends cannot happen at the tail.
+        for (index, expected) in expected.iter_mut().enumerate() {
+            let mut node = tree.at_mut(index);
+            node.push_child(round);
+            expected.push(round);
+            let last = node.len() - 1;
+            node.at_mut(last).set_children(0..round % 3);
+        }
+    }
+    for (index, expected) in expected.iter().enumerate() {
+        assert_eq!(child_data(tree.at(index)), *expected);
+        for (round, child) in tree.at(index).iter().enumerate() {
+            assert_eq!(child_data(child), (0..round % 3).collect::<Vec<_>>());
+        }
+    }
+
+    // Interleaved appends move the children each time, leaving garbage
+    // which compaction reclaims.
+    assert!(tree.fragmentation() > 0.0);
+    tree.compact().unwrap();
+    assert_eq!(tree.fragmentation(), 0.0);
+
+    // The buffer invariants still hold when sliding the root.
+    tree.recycle();
+    tree.at_mut(1).at_mut(49).move_children_to_root();
+    tree.recycle();
+    assert_eq!(child_data(&tree), [0]);
+}
+
+#[test]
+fn test_children_chain_split_in_place() {
+    // Returns the data and address of each node on the first line down from
+    // `node`.
+    fn line(mut node: &Node<usize>) -> Vec<(usize, usize)> {
+        let mut line = Vec::new();
+        while let Some(child) = node.children().first() {
+            line.push((*child.get(), child as *const Node<usize> as usize));
+            node = child;
+        }
+        line
+    }
+
+    let len = 100000;
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(len);
+    tree.set_children([0]);
+    tree.at_mut(0).set_children_chain(1..len);
+    assert_eq!(tree.buffer_stats(), (1, 0, 0));
+    let before = line(tree.at(0));
+
+    // The run fills its buffer, so the child of the node moves to a newer
+    // one, but the rest of the run stays where it was.
+    let split = len / 2;
+    let mut depth = 0;
+    tree.for_each_on_path_mut(&vec![0; split], |mut siblings, index| {
+        depth += 1;
+        if depth == split {
+            siblings.at_mut(index).push_child(len);
+        }
+    });
+    let after = line(tree.at(0));
+    assert_eq!(after.len(), before.len());
+    assert_eq!(after[..split - 1], before[..split - 1]);
+    assert_ne!(after[split - 1].1, before[split - 1].1);
+    assert_eq!(after[split..], before[split..]);
+    assert_eq!(after[split - 1].0, split);
+    assert!(tree.fragmentation() < 0.001);
+
+    // The cut-off part of the run can become the root, and its buffer is
+    // kept while the moved child needs it.
+    let mut depth = 0;
+    tree.for_each_on_path_mut(&vec![0; split], |mut siblings, index| {
+        depth += 1;
+        if depth == split {
+            siblings.at_mut(index).move_children_to_root();
+        }
+    });
+    tree.recycle();
+    assert_eq!(child_data(&tree), [split, len]);
+    assert_eq!(line(tree.at(0)), before[split..]);
+}
+
+#[test]
+fn test_push_child_moves_deep_subtree() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100000);
+    tree.set_children(0..2);
+    tree.at_mut(0).set_children_chain(1..99999);
+    assert_eq!(tree.buffer_stats(), (1, 0, 0));
+
+    // The buffer is full, so the roots move to a newer buffer and the chain
+    // has to follow them.
+    tree.push_child(2);
+    assert_eq!(child_data(&tree), [0, 1, 2]);
+    let mut node = tree.at(0);
+    let mut expected = 1;
+    while let [child] = node.children() {
+        assert_eq!(*child.get(), expected);
+        expected += 1;
+        node = child;
+    }
+    assert_eq!(expected, 99999);
+    tree.recycle();
+    assert_eq!(tree.buffer_stats().0, 1);
+}
+
+#[test]
+fn test_try_push_child_static_storage() {
+    let region = Box::leak(Box::new([MaybeUninit::uninit(); 4096]));
+    let mut tree: SlidingTree<usize> =
+        SlidingTree::with_static_storage(region, 2);
+    let capacity = tree.capacity();
+    tree.set_children(0..2);
+
+    // The roots grow in place until their buffer is full, and cannot then be
+    // moved to a buffer with room for them.
+    assert_eq!(tree.try_extend_children(2..=capacity), Err(AllocError));
+    assert_eq!(child_data(&tree), (0..capacity).collect::<Vec<_>>());
+    assert_eq!(tree.try_push_child(capacity), Err(AllocError));
+    assert_eq!(tree.len(), capacity);
+
+    // A smaller group of children can still be moved.
+    tree.at_mut(0).set_children(0..2);
+    tree.at_mut(1).try_push_child(5).unwrap();
+    tree.at_mut(0).try_push_child(2).unwrap();
+    assert_eq!(child_data(tree.at(0)), [0, 1, 2]);
+    assert_eq!(child_data(tree.at(1)), [5]);
+}
+
+#[test]
+fn test_push_child_drops_node_data() {
+    let counters = Counters::new();
+    let live = || counters.constructed() - counters.dropped();
+    {
+        let mut tree: SlidingTree<DropCounter> =
+            SlidingTree::with_capacity(100);
+        tree.set_children((0..3).map(|_| DropCounter::new(&counters)));
+        tree.at_mut(0).set_children([DropCounter::new(&counters)]);
+        for _ in 0..10 {
+            tree.push_child(DropCounter::new(&counters));
+        }
+        tree.remove_child(0);
+        tree.retain_children(|_| false);
+        // Only the child of a removed node is still waiting to be recycled.
+        assert_eq!(live(), 1);
+        tree.push_child(DropCounter::new(&counters));
+        tree.insert_child(0, DropCounter::new(&counters));
+        assert_eq!(live(), 3);
+    }
+    assert!(counters.balanced());
+}
+
+#[test]
+fn test_children_chain() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children([0]);
+    tree.at_mut(0).set_children_chain(1..6);
+    assert_eq!(stats(&tree), (6, 0, 1, 0));
+
+    // The chain is walked through the usual API, and is laid out as one run.
+    let mut line = Vec::new();
+    let mut node = tree.at(0);
+    while let [child] = node.children() {
+        line.push((*child.get(), child as *const Node<usize>));
+        node = child;
+    }
+    assert!(!node.is_expanded());
+    assert_eq!(
+        line.iter().map(|&(data, _)| data).collect::<Vec<_>>(),
+        [1, 2, 3, 4, 5]
+    );
+    assert!(line.windows(2).all(|w| w[0].1.wrapping_add(1) == w[1].1));
+
+    // Adding a second child splits the run after the node.
+    let mut node = tree.at_mut(0);
+    let mut node = node.at_mut(0);
+    node.at_mut(0).push_child(10);
+    assert_eq!(child_data(tree.at(0).at(0).at(0)), [3, 10]);
+    assert_eq!(child_data(tree.at(0).at(0).at(0).at(0)), [4]);
+    assert_eq!(count_nodes(tree.iter()), 7);
+
+    // The tail of the run can become the root and the rest is recycled.
+    let mut node = tree.at_mut(0);
+    node.at_mut(0).at_mut(0).at_mut(0).move_children_to_root();
+    tree.recycle();
+    assert_eq!(child_data(&tree), [4]);
+    assert_eq!(child_data(tree.at(0)), [5]);
+
+    tree.set_children_chain([]);
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn test_expand_with() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children([3, 0]);
+    assert!(!tree.at(0).is_expanded());
+    assert!(!tree.at(1).is_expanded());
+
+    // The children are generated from the node's data exactly once.
+    let mut calls = 0;
+    for _ in 0..2 {
+        for mut node in tree.iter_mut() {
+            node.expand_with(|&mut n| {
+                calls += 1;
+                0..n
+            });
+        }
+    }
+    assert_eq!(calls, 2);
+    assert_eq!(child_data(tree.at(0)), [0, 1, 2]);
+
+    // A node without any children is still expanded, unlike a new leaf.
+    assert!(tree.at(1).is_expanded());
+    assert!(tree.at(1).is_empty());
+    assert!(!tree.at(0).at(0).is_expanded());
+
+    // Setting children directly also counts as an expansion, even after
+    // they have all been removed.
+    let mut parent = tree.at_mut(0);
+    let mut node = parent.at_mut(1);
+    node.set_children(0..2);
+    node.retain_children(|_| false);
+    assert!(node.is_expanded());
+    assert!(!node.expand_with(|_| 0..5));
+    assert!(node.is_empty());
+
+    // Compaction preserves the distinction.
+    tree.compact().unwrap();
+    assert!(!tree.at(0).at(0).is_expanded());
+    assert!(tree.at(0).at(1).is_expanded());
+    assert!(tree.at(1).is_expanded());
+}
+
+#[test]
+fn test_expand_with_panic_leaves_node_unexpanded() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children([1]);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        tree.at_mut(0).expand_with(|_| PanicAfter::new(2));
+    }));
+    assert!(result.is_err());
+    assert!(!tree.at(0).is_expanded());
+    assert!(tree.at_mut(0).expand_with(|_| 0..2));
+    assert_eq!(child_data(tree.at(0)), [0, 1]);
+}
+
+#[test]
+fn test_get_many_mut() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..4);
+    let [mut a, mut b] = tree.get_many_mut([3, 1]);
+    std::mem::swap(a.get_mut(), b.get_mut());
+    a.set_children(10..12);
+    b.set_children(20..22);
+    assert_eq!(child_data(&tree), [0, 3, 2, 1]);
+    assert_eq!(child_data(tree.at(3)), [10, 11]);
+    assert_eq!(child_data(tree.at(1)), [20, 21]);
+
+    let mut node = tree.at_mut(3);
+    let [x, y] = node.get_many_mut([0, 1]);
+    assert_eq!((*x.get(), *y.get()), (10, 11));
+}
+
+#[test]
+#[should_panic(expected = "index (is 1) should not be repeated")]
+fn test_get_many_mut_repeated_index() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..3);
+    tree.get_many_mut([1, 2, 1]);
+}
+
+#[test]
+#[should_panic(expected = "index (is 3) should be < len (is 3)")]
+fn test_get_many_mut_out_of_bounds() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100);
+    tree.set_children(0..3);
+    tree.get_many_mut([0, 3]);
+}
+
+#[test]
+fn test_split_children_mut() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(10);
+    tree.set_children(0..4);
+
+    // Both halves can grow their nodes at the same time.
+    let (mut left, mut right) = tree.split_at_mut(2);
+    assert_eq!((left.len(), right.len()), (2, 2));
+    for (mut l, mut r) in left.iter_mut().zip(right.iter_mut()) {
+        l.set_children(0..*r.get());
+        r.set_children(0..*l.get());
+        *l.get_mut() += *r.get();
+    }
+    left.at_mut(0).at_mut(0).set_children([7]);
+    assert_eq!(child_data(&tree), [2, 4, 2, 3]);
+    assert_eq!(child_data(tree.at(0)), [0, 1]);
+    assert_eq!(child_data(tree.at(0).at(0)), [7]);
+    assert_eq!(child_data(tree.at(3)), [0]);
+
+    let mut node = tree.at_mut(0);
+    let (mut first, mut rest) = node.split_first_mut().unwrap();
+    first.get_mut().clone_from(rest.at(0).get());
+    rest.at_mut(0).set_children([5]);
+    let (last, mut rest) = tree.split_last_mut().unwrap();
+    assert_eq!(*last.get(), 3);
+    let (_, mut middle) = rest.split_first_mut().unwrap();
+    let [a, b] = middle.get_many_mut([1, 0]);
+    assert_eq!((*a.get(), *b.get()), (2, 4));
+    assert_eq!(child_data(tree.at(0)), [1, 1]);
+    assert_eq!(child_data(tree.at(0).at(1)), [5]);
+
+    // The buffer invariants still hold when sliding the root.
+    tree.at_mut(0).move_children_to_root();
+    tree.recycle();
+    assert_eq!(child_data(&tree), [1, 1]);
+
+    tree.set_children([]);
+    assert!(tree.split_first_mut().is_none());
+    assert!(tree.split_last_mut().is_none());
+}
+
+#[test]
+fn test_for_each_on_path_mut() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(10);
+    tree.set_children(0..3);
+    tree.at_mut(1).set_children(10..14);
+    tree.at_mut(1).at_mut(2).set_children(20..22);
+
+    // Every sibling of each node on the path is visited, from the top down.
+    let mut visited = Vec::new();
+    tree.for_each_on_path_mut(&[1, 2, 0], |mut siblings, index| {
+        visited.push((*siblings.at(index).get(), siblings.len()));
+        for mut sibling in siblings.iter_mut() {
+            *sibling.get_mut() += 100;
+        }
+    });
+    assert_eq!(visited, [(1, 3), (12, 4), (20, 2)]);
+    assert_eq!(child_data(&tree), [100, 101, 102]);
+    assert_eq!(child_data(tree.at(1)), [110, 111, 112, 113]);
+    assert_eq!(child_data(tree.at(1).at(2)), [120, 121]);
+
+    // Children given to a node on the path are descended into.
+    let mut node = tree.at_mut(0);
+    node.set_children([0]);
+    node.for_each_on_path_mut(&[0, 0], |mut siblings, index| {
+        if !siblings.at(index).is_expanded() {
+            siblings.at_mut(index).set_children([1]);
+        }
+        *siblings.at_mut(index).get_mut() += 10;
+    });
+    assert_eq!(child_data(tree.at(0)), [10]);
+    assert_eq!(child_data(tree.at(0).at(0)), [11]);
+
+    let mut calls = 0;
+    tree.for_each_on_path_mut(&[], |_, _| calls += 1);
+    assert_eq!(calls, 0);
+}
+
+#[test]
+#[should_panic(expected = "index (is 4) should be < len (is 4)")]
+fn test_for_each_on_path_mut_out_of_bounds() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(10);
+    tree.set_children(0..3);
+    tree.at_mut(0).set_children(0..4);
+    tree.for_each_on_path_mut(&[0, 4], |_, _| {});
+}
+
+fn assert_send<T: Send>(value: T) -> T {
+    value
+}
+
+#[test]
+fn test_send_between_threads() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(10);
+    tree.set_children(0..10);
+    deepen_tree(tree.iter_mut(), 0..10);
+
+    // Make a move and keep growing the tree on a worker thread.
+    let mut tree = thread::spawn(move || {
+        let mut tree = assert_send(tree);
+        tree.at_mut(3).move_children_to_root();
+        tree.recycle();
+        deepen_tree(tree.iter_mut(), 0..5);
+        tree
+    })
+    .join()
+    .unwrap();
+    assert_eq!(count_nodes(tree.iter()), 60);
+    assert_eq!(child_data(tree.at(9)), [0, 1, 2, 3, 4]);
+
+    // And once more with a scoped thread, before continuing on this one.
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            tree.at_mut(9).move_children_to_root();
+            tree.recycle();
+        });
+    });
+    tree.at_mut(4).set_children([1]);
+    assert_eq!(child_data(&tree), [0, 1, 2, 3, 4]);
+    assert_eq!(child_data(tree.at(4)), [1]);
+}
+
+#[test]
+fn test_send_static_storage() {
+    let region = Box::leak(Box::new([MaybeUninit::uninit(); 4096]));
+    let mut tree: SlidingTree<usize> =
+        SlidingTree::with_static_storage(region, 4);
+    tree.set_children(0..3);
+    let tree = thread::spawn(move || {
+        let mut tree = assert_send(tree);
+        tree.at_mut(1).set_children(0..2);
+        tree
+    })
+    .join()
+    .unwrap();
+    assert_eq!(child_data(tree.at(1)), [0, 1]);
+}
+
+#[test]
+fn test_freeze_shared_between_threads() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(10);
+    tree.set_children(0..4);
+    deepen_tree(tree.iter_mut(), 0..4);
+    deepen_tree(tree.iter_mut(), 0..4);
+    tree.at_mut(0).push_child(4);
+    deepen_tree(tree.at_mut(0).iter_mut(), 0..4);
+
+    // The frozen view sees the roots set through a node.
+    tree.at_mut(0).move_children_to_root();
+    let frozen: FrozenTree<usize> = tree.freeze();
+    assert_eq!(child_data(&frozen), [0, 1, 2, 3, 4]);
+
+    let counts = thread::scope(|scope| {
+        let handles: Vec<_> = frozen
+            .iter()
+            .map(|root| scope.spawn(|| 1 + count_nodes(root.iter())))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(counts, [21, 21, 21, 21, 5]);
+    assert_eq!(counts.iter().sum::<usize>(), count_nodes(frozen.iter()));
+    assert_eq!(
+        format!("{:?}", frozen.at(4)),
+        "Node { data: 4, children: [Node { data: 0, children: [] }, \
+         Node { data: 1, children: [] }, Node { data: 2, children: [] }, \
+         Node { data: 3, children: [] }] }"
+    );
+
+    tree.at_mut(4).set_children([7]);
+    assert_eq!(child_data(tree.freeze().at(4)), [7]);
+}
+
+#[test]
+fn test_node_handle() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(10);
+    tree.set_children([0, 1, 2]);
+    tree.at_mut(1).set_children([10, 11]);
+    let deep = tree.handle(tree.at(1).at(1));
+    let root = tree.handle(tree.at(0));
+    assert_eq!(tree.resolve(deep).map(Node::get), Some(&11));
+    assert_eq!(tree.resolve(root).map(Node::get), Some(&0));
+
+    // A node which is no longer reachable is not resolved, even before its
+    // buffer is recycled.
+    tree.at_mut(1).move_children_to_root();
+    assert!(tree.resolve(root).is_none());
+    assert_eq!(tree.resolve(deep).map(Node::get), Some(&11));
+    tree.recycle();
+    assert!(tree.resolve(root).is_none());
+    assert_eq!(tree.resolve(deep).map(Node::get), Some(&11));
+
+    tree.clear();
+    assert!(tree.resolve(deep).is_none());
+
+    // Deep trees are walked without recursion.
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(100000);
+    tree.set_children_chain(0..100000);
+    let mut last = tree.at(0);
+    while !last.is_empty() {
+        last = last.at(0);
+    }
+    let last = tree.handle(last);
+    assert_eq!(tree.resolve(last).map(Node::get), Some(&99999));
+}
+
+#[test]
+fn test_node_handle_recycled_buffer() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(3);
+    tree.set_children([0, 1, 2]);
+    let old = tree.handle(tree.at(2));
+    tree.at_mut(0).set_children([5, 6, 7]);
+    tree.at_mut(0).move_children_to_root();
+    tree.recycle();
+    assert_eq!(tree.buffer_stats(), (1, 0, 1));
+
+    // The recycled buffer is reused for a reachable node at the same address.
+    tree.at_mut(0).set_children([8, 9, 10]);
+    let new = tree.handle(tree.at(0).at(2));
+    assert_ne!(old, new);
+    assert!(tree.resolve(old).is_none());
+    assert_eq!(tree.resolve(new).map(Node::get), Some(&10));
+}
+
+#[test]
+fn test_node_handle_in_older_current_buffer() {
+    // The nested allocation leaves the buffer of the first subtree below a
+    // newer one, and later small allocations go back into the older buffer
+    // even when their parents are in the newer one.
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(4);
+    tree.set_children([1, 2, 3]);
+    tree.at_mut(0).set_children_subtree(
+        [(10, ()), (20, ())],
+        |mut node, ()| {
+            node.set_children([0, 1, 2]);
+        },
+    );
+    tree.at_mut(1).set_children([0]);
+    tree.at_mut(1).at_mut(0).set_children([70]);
+    let handle = tree.handle(tree.at(1).at(0).at(0));
+    assert_eq!(tree.resolve(handle).map(Node::get), Some(&70));
+}
+
+#[test]
+#[should_panic(expected = "node not present in this tree")]
+fn test_node_handle_from_other_tree() {
+    let mut a: SlidingTree<usize> = SlidingTree::with_capacity(10);
+    let mut b: SlidingTree<usize> = SlidingTree::with_capacity(10);
+    a.set_children([0]);
+    b.set_children([0]);
+    b.handle(a.at(0));
+}
+
+#[test]
+fn test_transposition_table() {
+    let mut tree: SlidingTree<usize> = SlidingTree::with_capacity(4);
+    let mut table = TranspositionTable::with_capacity(10);
+    assert_eq!(table.capacity(), 16);
+    tree.set_children(0..4);
+    tree.at_mut(1).set_children(10..14);
+    table.insert(&tree, 100, tree.at(1));
+    table.insert(&tree, 101, tree.at(1).at(2));
+    assert_eq!(table.get(&tree, 100).map(Node::get), Some(&1));
+    assert_eq!(table.get(&tree, 100).unwrap().len(), 4);
+    assert_eq!(table.handle(101), Some(tree.handle(tree.at(1).at(2))));
+    assert!(table.get(&tree, 102).is_none());
+
+    // Entries for nodes in recycled buffers expire, and the others remain.
+    tree.at_mut(1).move_children_to_root();
+    tree.recycle();
+    assert_eq!(tree.buffer_stats(), (1, 0, 1));
+    assert!(table.get(&tree, 100).is_none());
+    assert_eq!(table.get(&tree, 101).map(Node::get), Some(&12));
+
+    // A recycled buffer which is reused does not revive old entries.
+    tree.at_mut(2).set_children(0..4);
+    assert!(table.get(&tree, 100).is_none());
+    assert!(table.handle(100).is_some());
+
+    table.insert(&tree, 100, tree.at(3));
+    table.insert(&tree, 116, tree.at(2).at(1));
+    assert!(table.get(&tree, 100).is_none());
+    assert_eq!(table.get(&tree, 116).map(Node::get), Some(&1));
+    table.remove(100);
+    assert!(table.get(&tree, 116).is_some());
+    table.remove(116);
+    assert!(table.get(&tree, 116).is_none());
+
+    table.insert(&tree, 1, tree.at(0));
+    tree.clear();
+    assert!(table.get(&tree, 1).is_none());
+    table.clear();
+    assert!(table.handle(1).is_none());
+
+    // A node allocated in an older current buffer than its parent is found.
+    tree.set_children([1, 2, 3]);
+    tree.at_mut(0).set_children_subtree(
+        [(10, ()), (20, ())],
+        |mut node, ()| {
+            node.set_children([0, 1, 2]);
+        },
+    );
+    tree.at_mut(1).set_children([0]);
+    tree.at_mut(1).at_mut(0).set_children([70]);
+    table.insert(&tree, 7, tree.at(1).at(0).at(0));
+    assert_eq!(table.get(&tree, 7).map(Node::get), Some(&70));
+}